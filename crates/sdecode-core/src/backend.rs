@@ -0,0 +1,30 @@
+use std::{collections::HashMap, convert::Infallible, hash::BuildHasher};
+
+use alloy_primitives::B256;
+
+use crate::StorageEntries;
+
+/// Source of storage slots read on demand, such as a node or a database.
+#[auto_impl::auto_impl(&mut, Box)]
+pub trait SlotBackend {
+    type Error;
+
+    /// Reads the word stored at `slot`. Unset slots hold the zero word.
+    fn sload(&mut self, slot: B256) -> Result<B256, Self::Error>;
+}
+
+impl SlotBackend for StorageEntries {
+    type Error = Infallible;
+
+    fn sload(&mut self, slot: B256) -> Result<B256, Self::Error> {
+        Ok(self.get(&slot).copied().unwrap_or_default())
+    }
+}
+
+impl<S: BuildHasher> SlotBackend for HashMap<B256, B256, S> {
+    type Error = Infallible;
+
+    fn sload(&mut self, slot: B256) -> Result<B256, Self::Error> {
+        Ok(self.get(&slot).copied().unwrap_or_default())
+    }
+}
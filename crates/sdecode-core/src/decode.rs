@@ -4,18 +4,89 @@ use alloy_primitives::{B256, U256};
 use quick_impl::quick_impl_all;
 use sdecode_preimages::{PreimagesProvider, PreimagesProviderMut, caches::StoragePreimagesCache};
 
-use crate::slot::MAX_STORAGE_OFFSET;
+use crate::{DecodeMode, StorageDiagnostic, StorageReport, slot::MAX_STORAGE_OFFSET};
+
+/// Result of a lenient decoding: the value along with the diagnostics collected on the way.
+pub type DecodeWithDiagnostics<T, PE, LE> =
+    Result<(T, Vec<StorageDiagnostic>), StorageError<PE, LE>>;
 
 pub trait StorageDecode: Sized {
     type LayoutError: Error;
 
+    /// Decodes the storage in the given mode, with the layout starting at `base_slot`. The
+    /// violations skipped in [`DecodeMode::Lenient`] and the entries not claimed by the layout
+    /// are returned along with the value.
+    fn sdecode_at_with_mode_mut<P, E>(
+        base_slot: B256,
+        preimages_provider: &mut P,
+        storage_entries: E,
+        mode: DecodeMode,
+    ) -> Result<StorageReport<Self>, StorageError<P::Error, Self::LayoutError>>
+    where
+        P: PreimagesProviderMut,
+        E: IntoIterator<Item = (B256, B256)>;
+
+    /// Decodes the storage in the given mode, with the layout starting at its default base
+    /// slot, which is slot zero unless overridden.
+    fn sdecode_with_mode_mut<P, E>(
+        preimages_provider: &mut P,
+        storage_entries: E,
+        mode: DecodeMode,
+    ) -> Result<StorageReport<Self>, StorageError<P::Error, Self::LayoutError>>
+    where
+        P: PreimagesProviderMut,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_at_with_mode_mut(B256::ZERO, preimages_provider, storage_entries, mode)
+    }
+
+    fn sdecode_at_mut<P, E>(
+        base_slot: B256,
+        preimages_provider: &mut P,
+        storage_entries: E,
+    ) -> Result<Self, StorageError<P::Error, Self::LayoutError>>
+    where
+        P: PreimagesProviderMut,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_at_with_mode_mut(
+            base_slot,
+            preimages_provider,
+            storage_entries,
+            DecodeMode::Strict,
+        )
+        .map(|report| report.value)
+    }
+
+    /// Decodes a layout starting at `base_slot`, such as namespaced storage (ERC-7201) or a
+    /// diamond storage struct.
+    fn sdecode_at<P, E>(
+        base_slot: B256,
+        preimages_provider: P,
+        storage_entries: E,
+    ) -> Result<Self, StorageError<P::Error, Self::LayoutError>>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_at_mut(
+            base_slot,
+            &mut StoragePreimagesCache::new(preimages_provider, U256::from(MAX_STORAGE_OFFSET)),
+            storage_entries,
+        )
+    }
+
     fn sdecode_mut<P, E>(
         preimages_provider: &mut P,
         storage_entries: E,
     ) -> Result<Self, StorageError<P::Error, Self::LayoutError>>
     where
         P: PreimagesProviderMut,
-        E: IntoIterator<Item = (B256, B256)>;
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_with_mode_mut(preimages_provider, storage_entries, DecodeMode::Strict)
+            .map(|report| report.value)
+    }
 
     fn sdecode<P, E>(
         preimages_provider: P,
@@ -30,6 +101,37 @@ pub trait StorageDecode: Sized {
             storage_entries,
         )
     }
+
+    /// Best-effort decoding: dirty padding bytes and unexpected hashed values are returned as
+    /// diagnostics instead of aborting the decoding.
+    fn sdecode_lenient<P, E>(
+        preimages_provider: P,
+        storage_entries: E,
+    ) -> DecodeWithDiagnostics<Self, P::Error, Self::LayoutError>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_report(preimages_provider, storage_entries, DecodeMode::Lenient)
+            .map(|report| (report.value, report.diagnostics))
+    }
+
+    /// Decodes the storage in the given mode, reporting what the layout did not account for.
+    fn sdecode_report<P, E>(
+        preimages_provider: P,
+        storage_entries: E,
+        mode: DecodeMode,
+    ) -> Result<StorageReport<Self>, StorageError<P::Error, Self::LayoutError>>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        Self::sdecode_with_mode_mut(
+            &mut StoragePreimagesCache::new(preimages_provider, U256::from(MAX_STORAGE_OFFSET)),
+            storage_entries,
+            mode,
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, thiserror::Error)]
@@ -0,0 +1,365 @@
+use std::{fmt, sync::Arc};
+
+use alloy_primitives::{B256, Bytes};
+
+use crate::{MappingEntryLocation, MappingKeySide};
+
+/// Scheme deriving the slot of a mapping entry from its key and the slot of the mapping: the slot
+/// of the entry is the keccak256 hash of a preimage built by the scheme.
+pub trait SlotDerivation {
+    /// Location of the entry whose slot is the hash of `preimage`, if the preimage has the shape of
+    /// the scheme.
+    fn split(&self, preimage: &[u8]) -> Option<MappingEntryLocation>;
+
+    /// Preimage of the slot of the entry at `location`.
+    fn join(&self, location: &MappingEntryLocation) -> Bytes;
+
+    /// Whether 32-byte keys may be the hashes of the original keys, such as the `Bytes` and
+    /// `String` keys of Vyper. Their preimages are then looked up while decoding.
+    fn hashes_keys(&self) -> bool {
+        false
+    }
+}
+
+impl SlotDerivation for MappingKeySide {
+    fn split(&self, preimage: &[u8]) -> Option<MappingEntryLocation> {
+        Self::split(self, preimage)
+    }
+
+    fn join(&self, location: &MappingEntryLocation) -> Bytes {
+        location.clone().into_preimage(*self)
+    }
+
+    fn hashes_keys(&self) -> bool {
+        *self == Self::VYPER
+    }
+}
+
+impl<D: SlotDerivation + ?Sized> SlotDerivation for &D {
+    fn split(&self, preimage: &[u8]) -> Option<MappingEntryLocation> {
+        (**self).split(preimage)
+    }
+
+    fn join(&self, location: &MappingEntryLocation) -> Bytes {
+        (**self).join(location)
+    }
+
+    fn hashes_keys(&self) -> bool {
+        (**self).hashes_keys()
+    }
+}
+
+/// Scheme of the entries of a single mapping, whose preimages do not necessarily contain the slot
+/// of the mapping. It is attached to a slot with [`SlotDerivations::with`].
+pub trait MappingSlotDerivation: fmt::Debug {
+    /// Key of the entry whose slot is the hash of `preimage`, if the preimage has the shape of the
+    /// scheme.
+    fn split_key(&self, preimage: &[u8]) -> Option<Bytes>;
+
+    /// Preimage of the slot of the entry at `key`, if the key can be derived by the scheme.
+    fn join_key(&self, key: &Bytes) -> Option<Bytes>;
+
+    /// Length of the preimages of the scheme, if they all have the same. Preimages of other
+    /// lengths are not split by the scheme.
+    fn preimage_len(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl<D: MappingSlotDerivation + ?Sized> MappingSlotDerivation for &D {
+    fn split_key(&self, preimage: &[u8]) -> Option<Bytes> {
+        (**self).split_key(preimage)
+    }
+
+    fn join_key(&self, key: &Bytes) -> Option<Bytes> {
+        (**self).join_key(key)
+    }
+
+    fn preimage_len(&self) -> Option<usize> {
+        (**self).preimage_len()
+    }
+}
+
+/// Slot derivations of a layout: the schemes attached to some mappings whose preimages may have
+/// the length of a preimage are tried in order, before the rule of the language.
+#[derive(Debug, Clone)]
+pub struct SlotDerivations {
+    side: MappingKeySide,
+    mappings: Vec<AttachedDerivation>,
+}
+
+#[derive(Debug, Clone)]
+struct AttachedDerivation {
+    mapping_slot: B256,
+    preimage_len: Option<usize>,
+    derivation: Arc<dyn MappingSlotDerivation + Send + Sync>,
+}
+
+impl SlotDerivations {
+    pub fn new(side: MappingKeySide) -> Self {
+        Self {
+            side,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Derives the entries of the mapping at `mapping_slot` with `derivation`.
+    pub fn with(
+        mut self,
+        mapping_slot: B256,
+        derivation: impl MappingSlotDerivation + Send + Sync + 'static,
+    ) -> Self {
+        self.extend([(mapping_slot, derivation)]);
+        self
+    }
+
+    pub const fn side(&self) -> MappingKeySide {
+        self.side
+    }
+}
+
+impl<D> Extend<(B256, D)> for SlotDerivations
+where
+    D: MappingSlotDerivation + Send + Sync + 'static,
+{
+    fn extend<I: IntoIterator<Item = (B256, D)>>(&mut self, iter: I) {
+        self.mappings
+            .extend(
+                iter.into_iter()
+                    .map(|(mapping_slot, derivation)| AttachedDerivation {
+                        mapping_slot,
+                        preimage_len: derivation.preimage_len(),
+                        derivation: Arc::new(derivation),
+                    }),
+            );
+    }
+}
+
+impl SlotDerivation for SlotDerivations {
+    fn split(&self, preimage: &[u8]) -> Option<MappingEntryLocation> {
+        self.mappings
+            .iter()
+            .filter(|mapping| {
+                mapping
+                    .preimage_len
+                    .is_none_or(|preimage_len| preimage_len == preimage.len())
+            })
+            .find_map(|mapping| {
+                let entry_key = mapping.derivation.split_key(preimage)?;
+                Some(MappingEntryLocation {
+                    entry_key,
+                    mapping_slot: mapping.mapping_slot,
+                })
+            })
+            .or_else(|| self.side.split(preimage))
+    }
+
+    fn join(&self, location: &MappingEntryLocation) -> Bytes {
+        self.mappings
+            .iter()
+            .filter(|mapping| mapping.mapping_slot == location.mapping_slot)
+            .find_map(|mapping| mapping.derivation.join_key(&location.entry_key))
+            .unwrap_or_else(|| SlotDerivation::join(&self.side, location))
+    }
+
+    fn hashes_keys(&self) -> bool {
+        self.side.hashes_keys()
+    }
+}
+
+/// Preimages made of packed keys and constant seeds, as derived by hand-optimized contracts, such
+/// as `keccak256(owner . _BALANCE_SLOT_SEED)` in Solady's ERC20.
+///
+/// Each key is left-padded to 32 bytes, like the keys of Solidity value types, and the keys of a
+/// preimage holding several of them are concatenated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct PackedSlotDerivation(pub Vec<PreimageSegment>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PreimageSegment {
+    /// Key packed on the given number of bytes, at most 32.
+    Key(usize),
+
+    /// Constant bytes.
+    Seed(Bytes),
+}
+
+impl PackedSlotDerivation {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Appends a key packed on `size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero or greater than 32.
+    pub fn key(mut self, size: usize) -> Self {
+        assert!(
+            (1..=32).contains(&size),
+            "a packed key takes 1 to 32 bytes, not {size}"
+        );
+        self.0.push(PreimageSegment::Key(size));
+        self
+    }
+
+    /// Appends constant bytes.
+    pub fn seed(mut self, seed: impl Into<Bytes>) -> Self {
+        self.0.push(PreimageSegment::Seed(seed.into()));
+        self
+    }
+}
+
+impl MappingSlotDerivation for PackedSlotDerivation {
+    fn split_key(&self, mut preimage: &[u8]) -> Option<Bytes> {
+        let mut key = Vec::new();
+        for segment in &self.0 {
+            match segment {
+                PreimageSegment::Key(size) => {
+                    if *size > 32 {
+                        return None;
+                    }
+                    let (packed, rest) = preimage.split_at_checked(*size)?;
+                    key.extend_from_slice(B256::left_padding_from(packed).as_slice());
+                    preimage = rest;
+                }
+                PreimageSegment::Seed(seed) => {
+                    preimage = preimage.strip_prefix(seed.as_ref())?;
+                }
+            }
+        }
+
+        preimage.is_empty().then(|| key.into())
+    }
+
+    fn join_key(&self, key: &Bytes) -> Option<Bytes> {
+        let mut words = key.chunks_exact(32);
+        if !words.remainder().is_empty() {
+            return None;
+        }
+
+        let mut preimage = Vec::new();
+        for segment in &self.0 {
+            match segment {
+                PreimageSegment::Key(size) => {
+                    let (padding, packed) = words.next()?.split_at(32_usize.checked_sub(*size)?);
+                    if padding.iter().any(|byte| *byte != 0) {
+                        return None;
+                    }
+                    preimage.extend_from_slice(packed);
+                }
+                PreimageSegment::Seed(seed) => preimage.extend_from_slice(seed),
+            }
+        }
+
+        words.next().is_none().then(|| preimage.into())
+    }
+
+    fn preimage_len(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                PreimageSegment::Key(size) => *size,
+                PreimageSegment::Seed(seed) => seed.len(),
+            })
+            .try_fold(0_usize, usize::checked_add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, bytes, keccak256};
+
+    use super::*;
+
+    #[test]
+    fn test_packed_slot_derivation() {
+        let owner = Address::repeat_byte(0x11);
+        let spender = Address::repeat_byte(0x22);
+        let balances = B256::with_last_byte(3);
+        let allowances = B256::with_last_byte(4);
+
+        // Solady's ERC20: `keccak256(owner . _BALANCE_SLOT_SEED)` and
+        // `keccak256(owner . _ALLOWANCE_SLOT_SEED . spender)`, the seeds taking 12 bytes.
+        let derivations = SlotDerivations::new(MappingKeySide::SOLIDITY)
+            .with(
+                balances,
+                PackedSlotDerivation::new()
+                    .key(20)
+                    .seed(bytes!("0x000000000000000087a211a2")),
+            )
+            .with(
+                allowances,
+                PackedSlotDerivation::new()
+                    .key(20)
+                    .seed(bytes!("0x00000000000000007f5e9f20"))
+                    .key(20),
+            );
+
+        let balance_preimage = Bytes::from(
+            [
+                owner.as_slice(),
+                bytes!("0x000000000000000087a211a2").as_ref(),
+            ]
+            .concat(),
+        );
+        let location = MappingEntryLocation {
+            entry_key: owner.into_word().into(),
+            mapping_slot: balances,
+        };
+        assert_eq!(derivations.split(&balance_preimage), Some(location.clone()));
+        assert_eq!(derivations.join(&location), balance_preimage);
+
+        let allowance_preimage = Bytes::from(
+            [
+                owner.as_slice(),
+                bytes!("0x00000000000000007f5e9f20").as_ref(),
+                spender.as_slice(),
+            ]
+            .concat(),
+        );
+        let location = MappingEntryLocation {
+            entry_key: [owner.into_word().as_slice(), spender.into_word().as_slice()]
+                .concat()
+                .into(),
+            mapping_slot: allowances,
+        };
+        assert_eq!(
+            derivations.split(&allowance_preimage),
+            Some(location.clone())
+        );
+        assert_eq!(derivations.join(&location), allowance_preimage);
+
+        // Other mappings follow the rule of the language.
+        let location = MappingEntryLocation {
+            entry_key: owner.into_word().into(),
+            mapping_slot: B256::with_last_byte(5),
+        };
+        let preimage = derivations.join(&location);
+        assert_eq!(
+            preimage,
+            location.clone().into_preimage(MappingKeySide::SOLIDITY)
+        );
+        assert_eq!(derivations.split(&preimage), Some(location));
+
+        // A scheme only splits preimages of its length.
+        assert_eq!(
+            derivations
+                .mappings
+                .iter()
+                .map(|mapping| mapping.preimage_len)
+                .collect::<Vec<_>>(),
+            [Some(32), Some(52)]
+        );
+
+        // A key that cannot be packed falls back to the rule of the language.
+        let location = MappingEntryLocation {
+            entry_key: keccak256("wide key").into(),
+            mapping_slot: balances,
+        };
+        assert_eq!(
+            derivations.join(&location),
+            location.clone().into_preimage(MappingKeySide::SOLIDITY)
+        );
+    }
+}
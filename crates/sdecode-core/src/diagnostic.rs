@@ -0,0 +1,282 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, btree_map},
+    fmt,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use alloy_primitives::{B256, Bytes, keccak256};
+use quick_impl::quick_impl_all;
+
+use crate::{
+    MappingEntryLocation, MappingKeySide, SlotDerivation, SlotDerivations, StorageNodeChildren,
+};
+
+/// How violations of the layout are handled while decoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[quick_impl_all(pub const is)]
+pub enum DecodeMode {
+    /// Any violation of the layout aborts the decoding.
+    #[default]
+    Strict,
+
+    /// Dirty padding bytes and unexpected hashed values are recorded as [`StorageDiagnostic`]s
+    /// and ignored, as if they were zero. Other violations still abort the decoding.
+    Lenient,
+}
+
+/// Violation of the layout skipped by a [`DecodeMode::Lenient`] decoding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StorageDiagnostic {
+    /// Slot of the word holding the violation.
+    pub slot: B256,
+    /// Value being decoded when the violation was found.
+    pub path: LayoutPath,
+    pub violation: StorageViolation,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum StorageViolation {
+    /// Non-zero bytes on a part of a word that is not used by any value.
+    #[error("non zero bytes remaining on unused part of a word: {remaining}")]
+    RemainingBytes { remaining: Bytes },
+
+    /// Values stored at a hashed location under a value that cannot have any.
+    #[error("unexpected hashed values under the keys {keys:?}")]
+    UnexpectedChildren { keys: Vec<Bytes> },
+}
+
+impl StorageViolation {
+    pub fn unexpected_children<'a>(keys: impl IntoIterator<Item = &'a Bytes>) -> Self {
+        Self::UnexpectedChildren {
+            keys: keys.into_iter().cloned().collect(),
+        }
+    }
+}
+
+impl fmt::Display for StorageDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "at slot {}: {}", self.slot, self.violation)
+        } else {
+            write!(
+                f,
+                "at slot {} (`{}`): {}",
+                self.slot, self.path, self.violation
+            )
+        }
+    }
+}
+
+/// Location of a value, from a state variable down to the value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct LayoutPath(pub Vec<LayoutPathSegment>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LayoutPathSegment {
+    Field(Cow<'static, str>),
+    Index(u64),
+    /// Raw key, as found in the preimage of the slot.
+    Key(Bytes),
+    /// Anchor of a raw storage tree, when the layout is unknown.
+    Slot(B256),
+}
+
+impl LayoutPath {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for LayoutPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                LayoutPathSegment::Field(name) if i == 0 => f.write_str(name)?,
+                LayoutPathSegment::Field(name) => write!(f, ".{name}")?,
+                LayoutPathSegment::Index(index) => write!(f, "[{index}]")?,
+                LayoutPathSegment::Key(key) => write!(f, "[{key}]")?,
+                LayoutPathSegment::Slot(slot) => write!(f, "{slot}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sink shared by the readers of a [`DecodeMode::Lenient`] decoding.
+///
+/// Cloning the sink is cheap, and all the clones record into the same list. The hashed values
+/// skipped by the decoding are kept along with the diagnostics.
+#[derive(Debug, Clone)]
+pub struct StorageDiagnostics(Arc<Mutex<DiagnosticsState>>);
+
+#[derive(Debug)]
+struct DiagnosticsState {
+    derivations: SlotDerivations,
+    path: LayoutPath,
+    diagnostics: Vec<StorageDiagnostic>,
+    children: BTreeMap<B256, StorageNodeChildren>,
+}
+
+impl StorageDiagnostics {
+    /// Creates an empty sink. `side` is used to compute the slots of the hashed values.
+    pub fn new(side: MappingKeySide) -> Self {
+        Self::with_derivations(SlotDerivations::new(side))
+    }
+
+    /// Same as [`new`](Self::new), but computes the slots of the hashed values with `derivations`.
+    pub fn with_derivations(derivations: SlotDerivations) -> Self {
+        Self(Arc::new(Mutex::new(DiagnosticsState {
+            derivations,
+            path: LayoutPath::default(),
+            diagnostics: Vec::new(),
+            children: BTreeMap::new(),
+        })))
+    }
+
+    fn state(&self) -> MutexGuard<'_, DiagnosticsState> {
+        // The state stays consistent even if a holder of the lock panicked.
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn side(&self) -> MappingKeySide {
+        self.state().derivations.side()
+    }
+
+    pub(crate) fn entry_slot(&self, location: &MappingEntryLocation) -> B256 {
+        keccak256(self.state().derivations.join(location))
+    }
+
+    /// Removes the recorded diagnostics, in the order they were found.
+    pub fn take(&self) -> Vec<StorageDiagnostic> {
+        std::mem::take(&mut self.state().diagnostics)
+    }
+
+    /// Removes the skipped hashed values, by slot of the value they were found under.
+    pub fn take_children(&self) -> BTreeMap<B256, StorageNodeChildren> {
+        std::mem::take(&mut self.state().children)
+    }
+
+    pub(crate) fn record(&self, slot: B256, violation: StorageViolation) {
+        let mut state = self.state();
+        let path = state.path.clone();
+        state.diagnostics.push(StorageDiagnostic {
+            slot,
+            path,
+            violation,
+        });
+    }
+
+    pub(crate) fn keep_children(&self, slot: B256, children: StorageNodeChildren) {
+        match self.state().children.entry(slot) {
+            btree_map::Entry::Vacant(vacant_entry) => {
+                vacant_entry.insert(children);
+            }
+            btree_map::Entry::Occupied(mut occupied_entry) => {
+                occupied_entry.get_mut().extend(children);
+            }
+        }
+    }
+
+    pub(crate) fn enter(&self, segment: LayoutPathSegment) {
+        self.state().path.0.push(segment);
+    }
+
+    pub(crate) fn leave(&self) {
+        self.state().path.0.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{FixedBytes, U256};
+    use sdecode_preimages::MemoryPreimagesProvider;
+
+    use super::*;
+    use crate::{Storage, StorageReader};
+
+    #[test]
+    fn test_lenient_decoding() {
+        let side = MappingKeySide::SOLIDITY;
+        let key = Bytes::from(B256::with_last_byte(0xaa));
+
+        // A mapping at slot 0 with one entry, holding a dirty byte after a `uint8`, and a short
+        // `bytes` at slot 1 with a hashed value under it.
+        let mut preimages = MemoryPreimagesProvider::new();
+        let entry_slot = preimages.insert(
+            MappingEntryLocation {
+                entry_key: key.clone(),
+                mapping_slot: B256::ZERO,
+            }
+            .into_preimage(side),
+        );
+        let child_slot =
+            preimages.insert(Bytes::copy_from_slice(B256::with_last_byte(1).as_slice()));
+        let mut entry = B256::with_last_byte(10);
+        entry[11] = 0xff;
+        let entries = [
+            (entry_slot, entry),
+            (B256::with_last_byte(1), B256::with_last_byte(2)),
+            (child_slot, B256::with_last_byte(1)),
+        ];
+
+        let decode = |diagnostics: Option<StorageDiagnostics>| {
+            let mut storage = Storage::decode(&preimages, entries, side).unwrap();
+            let mut reader = storage.reader_at_with(B256::ZERO, diagnostics);
+
+            let mapping = reader.next::<B256>().unwrap();
+            let (key, structure) = mapping.children.into_iter().next().unwrap();
+            let (amount, remaining) = {
+                let mut entry_reader = reader.child_reader(&key, structure);
+                entry_reader.enter(|| LayoutPathSegment::Key(key.clone()));
+                let amount = entry_reader.next::<FixedBytes<1>>().unwrap().word;
+                let remaining = entry_reader.consume_remaining();
+                entry_reader.leave();
+                (amount, remaining)
+            };
+
+            reader.enter(|| LayoutPathSegment::Field("data".into()));
+            let data = reader.next::<B256>().unwrap();
+            let rejected = reader.reject_children(data.children);
+            reader.leave();
+
+            (amount, remaining, rejected)
+        };
+
+        let (amount, remaining, rejected) = decode(None);
+        assert_eq!(amount, FixedBytes([10]));
+        assert!(remaining.is_not_zero());
+        assert_eq!(
+            rejected,
+            Err(StorageViolation::UnexpectedChildren {
+                keys: vec![Bytes::new()]
+            })
+        );
+
+        let diagnostics = StorageDiagnostics::new(side);
+        let (amount, remaining, rejected) = decode(Some(diagnostics.clone()));
+        assert_eq!(amount, FixedBytes([10]));
+        assert!(remaining.is_zero());
+        assert_eq!(rejected, Ok(()));
+
+        let diagnostics = diagnostics.take();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].slot, entry_slot);
+        assert_eq!(
+            diagnostics[0].path.to_string(),
+            format!("[{}]", B256::with_last_byte(0xaa))
+        );
+        assert!(matches!(
+            diagnostics[0].violation,
+            StorageViolation::RemainingBytes { .. }
+        ));
+        assert_eq!(diagnostics[1].slot, B256::from(U256::from(1)));
+        assert_eq!(diagnostics[1].path.to_string(), "data");
+        assert_eq!(
+            diagnostics[1].violation,
+            StorageViolation::UnexpectedChildren {
+                keys: vec![Bytes::new()]
+            }
+        );
+    }
+}
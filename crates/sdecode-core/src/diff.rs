@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use alloy_primitives::B256;
+use quick_impl::quick_impl_all;
+
+use crate::{LayoutPath, LayoutPathSegment, Storage, StorageNode, StorageStructure};
+
+/// Difference of a single value between two storage snapshots.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StorageChange<T> {
+    pub path: LayoutPath,
+    pub kind: StorageChangeKind<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[quick_impl_all(pub const is)]
+pub enum StorageChangeKind<T> {
+    Added(T),
+    Removed(T),
+    Changed { before: T, after: T },
+}
+
+impl<T: PartialEq> StorageChangeKind<T> {
+    /// Change between two optional values, `None` meaning that the value is absent or zero.
+    pub fn between(before: Option<T>, after: Option<T>) -> Option<Self> {
+        match (before, after) {
+            (None, None) => None,
+            (None, Some(after)) => Some(Self::Added(after)),
+            (Some(before), None) => Some(Self::Removed(before)),
+            (Some(before), Some(after)) if before == after => None,
+            (Some(before), Some(after)) => Some(Self::Changed { before, after }),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for StorageChange<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: ", self.path)?;
+        match &self.kind {
+            StorageChangeKind::Added(value) => write!(f, "added {value}"),
+            StorageChangeKind::Removed(value) => write!(f, "removed {value}"),
+            StorageChangeKind::Changed { before, after } => write!(f, "{before} -> {after}"),
+        }
+    }
+}
+
+impl Storage {
+    /// Word by word changes from `self` to `other`. The words are located by the slot of their
+    /// anchor, followed by the mapping keys and offsets leading to them.
+    pub fn diff(&self, other: &Self) -> Vec<StorageChange<B256>> {
+        let mut changes = Vec::new();
+        let mut path = LayoutPath::default();
+
+        for (slot, before, after) in zip_maps(&self.anchors, &other.anchors) {
+            path.0.push(LayoutPathSegment::Slot(*slot));
+            diff_node(
+                before.unwrap_or(const { &StorageNode::empty() }),
+                after.unwrap_or(const { &StorageNode::empty() }),
+                &mut path,
+                &mut changes,
+            );
+            path.0.pop();
+        }
+
+        for (slot, before, after) in zip_maps(&self.undecoded, &other.undecoded) {
+            path.0.push(LayoutPathSegment::Slot(*slot));
+            diff_structure(
+                before.map_or(EMPTY_STRUCTURE, |(_, structure)| structure),
+                after.map_or(EMPTY_STRUCTURE, |(_, structure)| structure),
+                &mut path,
+                &mut changes,
+            );
+            path.0.pop();
+        }
+
+        changes
+    }
+}
+
+const EMPTY_STRUCTURE: &StorageStructure = &StorageStructure(Vec::new());
+
+fn diff_node(
+    before: &StorageNode,
+    after: &StorageNode,
+    path: &mut LayoutPath,
+    changes: &mut Vec<StorageChange<B256>>,
+) {
+    let word = |node: &StorageNode| Some(node.value()).filter(|value| !value.is_zero());
+    if let Some(kind) = StorageChangeKind::between(word(before), word(after)) {
+        changes.push(StorageChange {
+            path: path.clone(),
+            kind,
+        });
+    }
+
+    for (key, before, after) in zip_maps(&before.children, &after.children) {
+        path.0.push(LayoutPathSegment::Key(key.clone()));
+        diff_structure(
+            before.unwrap_or(EMPTY_STRUCTURE),
+            after.unwrap_or(EMPTY_STRUCTURE),
+            path,
+            changes,
+        );
+        path.0.pop();
+    }
+}
+
+fn diff_structure(
+    before: &StorageStructure,
+    after: &StorageStructure,
+    path: &mut LayoutPath,
+    changes: &mut Vec<StorageChange<B256>>,
+) {
+    for index in 0..before.len().max(after.len()) {
+        path.0.push(LayoutPathSegment::Index(index as u64));
+        diff_node(
+            before.get(index).unwrap_or(const { &StorageNode::empty() }),
+            after.get(index).unwrap_or(const { &StorageNode::empty() }),
+            path,
+            changes,
+        );
+        path.0.pop();
+    }
+}
+
+/// Entries of two maps, by key.
+fn zip_maps<'a, K: Ord, V>(
+    left: &'a BTreeMap<K, V>,
+    right: &'a BTreeMap<K, V>,
+) -> impl Iterator<Item = (&'a K, Option<&'a V>, Option<&'a V>)> {
+    let keys: BTreeSet<&K> = left.keys().chain(right.keys()).collect();
+    keys.into_iter()
+        .map(|key| (key, left.get(key), right.get(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_storage_diff() {
+        let key = Bytes::from_static(&[0xaa; 32]);
+        let slot = B256::with_last_byte(3);
+
+        let mut before = Storage::default();
+        before.anchors.insert(
+            B256::ZERO,
+            StorageNode::word(B256::with_last_byte(1)).with_child(
+                key.clone(),
+                StorageStructure(vec![StorageNode::word(B256::with_last_byte(2))]),
+            ),
+        );
+        before
+            .anchors
+            .insert(slot, StorageNode::word(B256::with_last_byte(4)));
+
+        let mut after = before.clone();
+        after
+            .anchors
+            .get_mut(&B256::ZERO)
+            .unwrap()
+            .children
+            .get_mut(&key)
+            .unwrap()
+            .0 = vec![
+            StorageNode::word(B256::with_last_byte(5)),
+            StorageNode::word(B256::with_last_byte(6)),
+        ];
+        after.anchors.remove(&slot);
+
+        assert_eq!(before.diff(&before), []);
+
+        let changes = before.diff(&after);
+        assert_eq!(
+            changes,
+            [
+                StorageChange {
+                    path: LayoutPath(vec![
+                        LayoutPathSegment::Slot(B256::ZERO),
+                        LayoutPathSegment::Key(key.clone()),
+                        LayoutPathSegment::Index(0),
+                    ]),
+                    kind: StorageChangeKind::Changed {
+                        before: B256::with_last_byte(2),
+                        after: B256::with_last_byte(5),
+                    },
+                },
+                StorageChange {
+                    path: LayoutPath(vec![
+                        LayoutPathSegment::Slot(B256::ZERO),
+                        LayoutPathSegment::Key(key.clone()),
+                        LayoutPathSegment::Index(1),
+                    ]),
+                    kind: StorageChangeKind::Added(B256::with_last_byte(6)),
+                },
+                StorageChange {
+                    path: LayoutPath(vec![LayoutPathSegment::Slot(slot)]),
+                    kind: StorageChangeKind::Removed(B256::with_last_byte(4)),
+                },
+            ]
+        );
+        assert_eq!(
+            changes[2].to_string(),
+            format!("`{slot}`: removed {}", B256::with_last_byte(4))
+        );
+    }
+}
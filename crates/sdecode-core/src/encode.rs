@@ -0,0 +1,50 @@
+use std::error::Error;
+
+use alloy_primitives::B256;
+use sdecode_preimages::MemoryPreimagesProvider;
+
+use crate::StorageEntries;
+
+/// Inverse of [`StorageDecode`](crate::StorageDecode).
+pub trait StorageEncode {
+    /// Raised when the value does not fit its layout, e.g. an array of the wrong length.
+    type EncodeError: Error;
+
+    /// Encodes with the layout starting at `base_slot`.
+    fn sencode_at_mut(
+        &self,
+        base_slot: B256,
+        preimages: &mut MemoryPreimagesProvider,
+        storage_entries: &mut StorageEntries,
+    ) -> Result<(), Self::EncodeError>;
+
+    /// Encodes with the layout starting at its default base slot, which is slot zero unless
+    /// overridden.
+    fn sencode_mut(
+        &self,
+        preimages: &mut MemoryPreimagesProvider,
+        storage_entries: &mut StorageEntries,
+    ) -> Result<(), Self::EncodeError> {
+        self.sencode_at_mut(B256::ZERO, preimages, storage_entries)
+    }
+
+    /// Encodes into storage entries, along with the preimages necessary to decode them.
+    fn sencode(&self) -> Result<(StorageEntries, MemoryPreimagesProvider), Self::EncodeError> {
+        let mut preimages = MemoryPreimagesProvider::new();
+        let mut storage_entries = StorageEntries::new();
+        self.sencode_mut(&mut preimages, &mut storage_entries)?;
+        Ok((storage_entries, preimages))
+    }
+
+    /// Encodes with the layout starting at `base_slot`, along with the preimages necessary to
+    /// decode the entries.
+    fn sencode_at(
+        &self,
+        base_slot: B256,
+    ) -> Result<(StorageEntries, MemoryPreimagesProvider), Self::EncodeError> {
+        let mut preimages = MemoryPreimagesProvider::new();
+        let mut storage_entries = StorageEntries::new();
+        self.sencode_at_mut(base_slot, &mut preimages, &mut storage_entries)?;
+        Ok((storage_entries, preimages))
+    }
+}
@@ -2,7 +2,7 @@ use alloy_primitives::{B256, Bytes};
 use quick_impl::quick_impl_all;
 use sdecode_preimages::{PreimagesProvider, PreimagesProviderMut, WrapPreimagesProvider};
 
-use crate::{DecodedStorageSlot, MappingKeySide};
+use crate::{DecodedStorageSlot, SlotDerivation};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct StorageItem {
@@ -38,13 +38,13 @@ pub enum HashLink {
 impl StorageItem {
     pub fn decode<P: PreimagesProvider>(
         provider: P,
-        side: MappingKeySide,
+        derivation: impl SlotDerivation,
         slot: B256,
         value: B256,
     ) -> Result<Self, P::Error> {
         Self::decode_inner(
             &mut WrapPreimagesProvider(provider),
-            side,
+            &derivation,
             slot,
             HashLink::Leaf { value },
         )
@@ -52,16 +52,16 @@ impl StorageItem {
 
     pub fn decode_mut<P: PreimagesProviderMut>(
         provider: &mut P,
-        side: MappingKeySide,
+        derivation: impl SlotDerivation,
         slot: B256,
         value: B256,
     ) -> Result<Self, P::Error> {
-        Self::decode_inner(provider, side, slot, HashLink::Leaf { value })
+        Self::decode_inner(provider, &derivation, slot, HashLink::Leaf { value })
     }
 
-    fn decode_inner<P: PreimagesProviderMut>(
+    fn decode_inner<P: PreimagesProviderMut, D: SlotDerivation + ?Sized>(
         provider: &mut P,
-        side: MappingKeySide,
+        derivation: &D,
         slot: B256,
         child_link: HashLink,
     ) -> Result<Self, P::Error> {
@@ -73,7 +73,7 @@ impl StorageItem {
             return Ok(item);
         };
 
-        if let Some(mapping_entry_location) = side.split(decoded.preimage()) {
+        if let Some(mapping_entry_location) = derivation.split(decoded.preimage()) {
             let child = HashLink::Inner {
                 key: mapping_entry_location.entry_key,
                 remaining_chain: Box::new(HashChain {
@@ -81,7 +81,12 @@ impl StorageItem {
                     link: child_link,
                 }),
             };
-            Self::decode_inner(provider, side, mapping_entry_location.mapping_slot, child)
+            Self::decode_inner(
+                provider,
+                derivation,
+                mapping_entry_location.mapping_slot,
+                child,
+            )
         } else {
             let item = Self {
                 anchor: decoded.slot(),
@@ -103,6 +108,8 @@ mod tests {
     use alloy_primitives::{b256, bytes};
     use sdecode_preimages::MemoryPreimagesProvider;
 
+    use crate::MappingKeySide;
+
     use super::*;
 
     #[test]
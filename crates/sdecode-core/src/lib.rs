@@ -5,9 +5,29 @@ use std::collections::BTreeMap;
 
 pub type StorageEntries = BTreeMap<B256, B256>;
 
+mod backend;
+pub use backend::SlotBackend;
+
 mod decode;
 
-pub use decode::{StorageDecode, StorageError};
+mod derivation;
+pub use derivation::{
+    MappingSlotDerivation, PackedSlotDerivation, PreimageSegment, SlotDerivation, SlotDerivations,
+};
+
+mod diff;
+pub use diff::{StorageChange, StorageChangeKind};
+
+mod diagnostic;
+pub use diagnostic::{
+    DecodeMode, LayoutPath, LayoutPathSegment, StorageDiagnostic, StorageDiagnostics,
+    StorageViolation,
+};
+
+pub use decode::{DecodeWithDiagnostics, StorageDecode, StorageError};
+
+mod encode;
+pub use encode::StorageEncode;
 
 mod item;
 pub use item::{AnchorKind, HashChain, HashLink, StorageItem};
@@ -22,6 +42,9 @@ mod reader;
 use reader::StorageReaderImpl;
 pub use reader::{IntoStorageReader, StorageReader, StorageReaderNext, SubB256};
 
+mod report;
+pub use report::{StorageLeftovers, StorageReport};
+
 mod slot;
 pub use slot::{DecodedStorageSlot, MAX_STORAGE_OFFSET};
 
@@ -29,3 +52,6 @@ mod storage;
 pub use storage::Storage;
 
 mod utils;
+
+mod writer;
+pub use writer::{StorageStructureWriter, StorageWriter};
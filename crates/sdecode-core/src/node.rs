@@ -1,9 +1,13 @@
 use std::collections::{BTreeMap, btree_map};
 
-use alloy_primitives::{B256, Bytes};
+use alloy_primitives::{B256, Bytes, U256};
 use quick_impl::quick_impl;
+use sdecode_preimages::MemoryPreimagesProvider;
 
-use crate::{HashChain, HashLink, IntoStorageReader, StorageReader, StorageReaderImpl};
+use crate::{
+    HashChain, HashLink, IntoStorageReader, MappingEntryLocation, SlotDerivation, StorageEntries,
+    StorageReader, StorageReaderImpl, utils::b256_to_u256,
+};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct StorageNode {
@@ -67,6 +71,44 @@ impl StorageNode {
         self.value.unwrap_or_default()
     }
 
+    /// Whether this node and all its children only hold zero words.
+    pub fn is_zero(&self) -> bool {
+        self.value().is_zero() && self.children.values().all(StorageStructure::is_zero)
+    }
+
+    /// Whether this node holds no word and has no children.
+    pub fn is_empty(&self) -> bool {
+        self.value().is_zero() && self.children.is_empty()
+    }
+
+    /// Flattens this node, located at `slot`, into storage entries. The preimages needed to
+    /// locate its children are inserted in `preimages`.
+    pub fn encode_into<D: SlotDerivation + ?Sized>(
+        &self,
+        slot: B256,
+        derivation: &D,
+        preimages: &mut MemoryPreimagesProvider,
+        storage_entries: &mut StorageEntries,
+    ) {
+        let value = self.value();
+        if !value.is_zero() {
+            storage_entries.insert(slot, value);
+        }
+
+        for (key, child) in &self.children {
+            if child.is_zero() {
+                continue;
+            }
+
+            let location = MappingEntryLocation {
+                entry_key: key.clone(),
+                mapping_slot: slot,
+            };
+            let child_slot = preimages.insert(derivation.join(&location));
+            child.encode_into(child_slot, derivation, preimages, storage_entries);
+        }
+    }
+
     pub fn add_link(&mut self, link: HashLink) {
         match link {
             HashLink::Leaf { value } => {
@@ -88,6 +130,32 @@ impl StorageNode {
             },
         }
     }
+
+    /// Same as [`add_link`](Self::add_link), but replaces the word at the end of the link. A zero
+    /// word removes it, along with the nodes left empty.
+    pub fn set_link(&mut self, link: HashLink) {
+        match link {
+            HashLink::Leaf { value } => self.value = (!value.is_zero()).then_some(value),
+            HashLink::Inner {
+                key,
+                remaining_chain,
+            } => match self.children.entry(key) {
+                btree_map::Entry::Vacant(vacant_entry) => {
+                    let mut child = StorageStructure::default();
+                    child.set_chain(*remaining_chain);
+                    if !child.is_empty() {
+                        vacant_entry.insert(child);
+                    }
+                }
+                btree_map::Entry::Occupied(mut occupied_entry) => {
+                    occupied_entry.get_mut().set_chain(*remaining_chain);
+                    if occupied_entry.get().is_empty() {
+                        occupied_entry.remove();
+                    }
+                }
+            },
+        }
+    }
 }
 
 impl StorageStructure {
@@ -95,6 +163,26 @@ impl StorageStructure {
         Self(vec![node])
     }
 
+    /// Whether all the nodes of this structure only hold zero words.
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(StorageNode::is_zero)
+    }
+
+    /// Flattens this structure, starting at `slot`, into storage entries.
+    pub fn encode_into<D: SlotDerivation + ?Sized>(
+        &self,
+        slot: B256,
+        derivation: &D,
+        preimages: &mut MemoryPreimagesProvider,
+        storage_entries: &mut StorageEntries,
+    ) {
+        let slot_u256 = b256_to_u256(slot);
+        for (i, node) in self.0.iter().enumerate() {
+            let node_slot = B256::from(slot_u256.wrapping_add(U256::from(i)));
+            node.encode_into(node_slot, derivation, preimages, storage_entries);
+        }
+    }
+
     pub fn from_chain(chain: HashChain) -> Self {
         let mut nodes = vec![StorageNode::empty(); chain.offset];
         let last_node = StorageNode::from_link(chain.link);
@@ -102,6 +190,19 @@ impl StorageStructure {
         Self(nodes)
     }
 
+    /// Same as [`add_chain`](Self::add_chain), but replaces the word at the end of the chain. The
+    /// trailing empty nodes are removed.
+    pub fn set_chain(&mut self, chain: HashChain) {
+        if chain.offset >= self.0.len() {
+            self.0.resize(chain.offset + 1, StorageNode::empty());
+        }
+        self.0[chain.offset].set_link(chain.link);
+
+        while self.0.last().is_some_and(StorageNode::is_empty) {
+            self.0.pop();
+        }
+    }
+
     pub fn add_chain(&mut self, chain: HashChain) {
         if let Some(delta) = chain.offset.checked_sub(self.0.len()) {
             self.0.extend(vec![StorageNode::empty(); delta]);
@@ -1,15 +1,113 @@
-use alloy_primitives::{B256, Bytes, FixedBytes};
+use std::collections::BTreeMap;
+
+use alloy_primitives::{B256, Bytes, FixedBytes, U256};
 use quick_impl::quick_impl;
 
-use crate::{StorageNode, StorageNodeChildren, utils::slice_is_zero};
+use crate::{
+    DecodeMode, LayoutPathSegment, MappingEntryLocation, StorageDiagnostics, StorageNode,
+    StorageNodeChildren, StorageStructure, StorageViolation,
+    utils::{b256_to_u256, slice_is_zero},
+};
 
-#[auto_impl::auto_impl(&mut)]
 pub trait StorageReader {
     fn next<B: SubB256>(&mut self) -> Result<StorageReaderNext<B>, RemainingBytes>;
 
     fn next_or_default<B: SubB256>(&mut self) -> StorageReaderNext<B>;
 
     fn consume_remaining(&mut self) -> RemainingBytes;
+
+    /// Skips the `slots` words following the current one, whatever they hold. The current word
+    /// must have been consumed.
+    fn skip_slots(&mut self, slots: usize) {
+        for _ in 0..slots {
+            self.next_or_default::<B256>();
+        }
+    }
+
+    /// In [`DecodeMode::Lenient`], the remaining bytes returned by the reader are always zero: the
+    /// dirty ones are recorded instead.
+    fn mode(&self) -> DecodeMode {
+        DecodeMode::Strict
+    }
+
+    /// Handles a violation of the layout found on the last word read. It is returned in strict
+    /// mode, and recorded in lenient mode.
+    fn report(&mut self, violation: StorageViolation) -> Result<(), StorageViolation> {
+        Err(violation)
+    }
+
+    /// Handles hashed values found under the last word read by a value that cannot have any. They
+    /// are returned as a violation in strict mode, and kept as leftovers in lenient mode.
+    fn reject_children(&mut self, children: StorageNodeChildren) -> Result<(), StorageViolation> {
+        if children.is_empty() {
+            Ok(())
+        } else {
+            self.report(StorageViolation::unexpected_children(children.keys()))
+        }
+    }
+
+    /// Reader of the structure stored under `key` of the last word read, in the same mode.
+    fn child_reader(&self, _key: &Bytes, structure: StorageStructure) -> impl StorageReader {
+        structure.into_storage_reader()
+    }
+
+    /// Original value of a mapping key hashed into the raw `key`, such as the `Bytes` and `String`
+    /// keys of Vyper, if its preimage is known.
+    fn key_preimage(&self, _key: &Bytes) -> Option<Bytes> {
+        None
+    }
+
+    /// Locates the violations reported until the matching [`leave`](Self::leave) under
+    /// `segment`. The segment is only built in lenient mode.
+    fn enter(&mut self, _segment: impl FnOnce() -> LayoutPathSegment) {}
+
+    fn leave(&mut self) {}
+}
+
+impl<R: StorageReader + ?Sized> StorageReader for &mut R {
+    fn next<B: SubB256>(&mut self) -> Result<StorageReaderNext<B>, RemainingBytes> {
+        (**self).next()
+    }
+
+    fn next_or_default<B: SubB256>(&mut self) -> StorageReaderNext<B> {
+        (**self).next_or_default()
+    }
+
+    fn consume_remaining(&mut self) -> RemainingBytes {
+        (**self).consume_remaining()
+    }
+
+    fn skip_slots(&mut self, slots: usize) {
+        (**self).skip_slots(slots)
+    }
+
+    fn mode(&self) -> DecodeMode {
+        (**self).mode()
+    }
+
+    fn report(&mut self, violation: StorageViolation) -> Result<(), StorageViolation> {
+        (**self).report(violation)
+    }
+
+    fn reject_children(&mut self, children: StorageNodeChildren) -> Result<(), StorageViolation> {
+        (**self).reject_children(children)
+    }
+
+    fn child_reader(&self, key: &Bytes, structure: StorageStructure) -> impl StorageReader {
+        (**self).child_reader(key, structure)
+    }
+
+    fn key_preimage(&self, key: &Bytes) -> Option<Bytes> {
+        (**self).key_preimage(key)
+    }
+
+    fn enter(&mut self, segment: impl FnOnce() -> LayoutPathSegment) {
+        (**self).enter(segment)
+    }
+
+    fn leave(&mut self) {
+        (**self).leave()
+    }
 }
 
 pub trait IntoStorageReader {
@@ -48,62 +146,116 @@ impl<B> StorageReaderNext<B> {
 }
 
 #[derive(Debug, Clone)]
-pub struct StorageReaderImpl<I> {
+pub struct StorageReaderImpl<'k, I> {
     iterator: I,
     current: Option<(B256Reader<true>, StorageNodeChildren)>,
+    lenient: Option<LenientState>,
+    key_preimages: Option<&'k BTreeMap<B256, Bytes>>,
 }
 
-impl<I> StorageReaderImpl<I> {
+#[derive(Debug, Clone)]
+struct LenientState {
+    diagnostics: StorageDiagnostics,
+    /// Slot of the current node.
+    slot: B256,
+    next_slot: B256,
+}
+
+impl<'k, I> StorageReaderImpl<'k, I> {
     pub const fn new(iterator: I) -> Self {
         Self {
             iterator,
             current: None,
+            lenient: None,
+            key_preimages: None,
+        }
+    }
+
+    /// Reader in [`DecodeMode::Lenient`] of nodes starting at `slot`.
+    pub const fn lenient(iterator: I, slot: B256, diagnostics: StorageDiagnostics) -> Self {
+        Self {
+            iterator,
+            current: None,
+            lenient: Some(LenientState {
+                diagnostics,
+                slot,
+                next_slot: slot,
+            }),
+            key_preimages: None,
+        }
+    }
+
+    /// Resolves the hashed mapping keys with `key_preimages`, down to the child readers.
+    pub fn with_key_preimages(mut self, key_preimages: &'k BTreeMap<B256, Bytes>) -> Self {
+        self.key_preimages = Some(key_preimages);
+        self
+    }
+
+    /// In lenient mode, records the non-zero remaining bytes of the current node and ignores them.
+    fn check_remaining(&self, remaining: RemainingBytes) -> RemainingBytes {
+        match &self.lenient {
+            Some(lenient) if remaining.is_not_zero() => {
+                lenient.diagnostics.record(
+                    lenient.slot,
+                    StorageViolation::RemainingBytes {
+                        remaining: remaining.0,
+                    },
+                );
+                RemainingBytes::default()
+            }
+            _ => remaining,
         }
     }
 }
 
-impl<I> StorageReaderImpl<I>
+impl<I> StorageReaderImpl<'_, I>
 where
     I: Iterator<Item = StorageNode>,
 {
+    fn load_next(&mut self) {
+        if let Some(next) = self.iterator.next() {
+            let value = next.value();
+            let reader = B256Reader::new(value);
+            self.current = Some((reader, next.children));
+        } else {
+            let reader = B256Reader::new(B256::ZERO);
+            self.current = Some((reader, Default::default()));
+        }
+
+        if let Some(lenient) = &mut self.lenient {
+            lenient.slot = lenient.next_slot;
+            lenient.next_slot = b256_to_u256(lenient.slot)
+                .wrapping_add(U256::from(1))
+                .into();
+        }
+    }
+
     fn next_with_remaining<B: SubB256>(
         &mut self,
         remaining: RemainingBytes,
     ) -> Result<StorageReaderNext<B>, RemainingBytes> {
-        let Some((ref mut reader, ref children)) = self.current else {
-            if let Some(next) = self.iterator.next() {
-                let value = next.value();
-                let reader = B256Reader::new(value);
-                self.current = Some((reader, next.children));
-            } else {
-                let reader = B256Reader::new(B256::ZERO);
-                self.current = Some((reader, Default::default()));
-            };
+        let Some((ref mut reader, ref mut children)) = self.current else {
+            self.load_next();
             return self.next_with_remaining(remaining);
         };
 
         match reader.next::<B>() {
+            // The children are only handed out with the first value read from the node.
             Ok(word) => Ok(StorageReaderNext {
                 word,
-                children: children.clone(),
+                children: std::mem::take(children),
                 remaining,
             }),
             Err(remaining) => {
-                if let Some(next) = self.iterator.next() {
-                    let value = next.value();
-                    let reader = B256Reader::new(value);
-                    self.current = Some((reader, next.children));
-                } else {
-                    let reader = B256Reader::new(B256::ZERO);
-                    self.current = Some((reader, Default::default()));
-                }
+                let remaining = self.check_remaining(remaining);
+                self.load_next();
                 self.next_with_remaining(remaining)
             }
         }
     }
 }
 
-impl<I> StorageReader for StorageReaderImpl<I>
+impl<I> StorageReader for StorageReaderImpl<'_, I>
 where
     I: Iterator<Item = StorageNode>,
 {
@@ -129,11 +281,89 @@ where
                 Bytes::default();
             }
 
-            reader.consume_remaining()
+            let remaining = reader.consume_remaining();
+            self.check_remaining(remaining)
         } else {
             RemainingBytes::default()
         }
     }
+
+    fn skip_slots(&mut self, slots: usize) {
+        for _ in 0..slots {
+            if self.iterator.next().is_none() {
+                break;
+            }
+        }
+
+        if let Some(lenient) = &mut self.lenient {
+            lenient.next_slot = b256_to_u256(lenient.next_slot)
+                .wrapping_add(U256::from(slots))
+                .into();
+        }
+    }
+
+    fn mode(&self) -> DecodeMode {
+        if self.lenient.is_some() {
+            DecodeMode::Lenient
+        } else {
+            DecodeMode::Strict
+        }
+    }
+
+    fn report(&mut self, violation: StorageViolation) -> Result<(), StorageViolation> {
+        let Some(lenient) = &self.lenient else {
+            return Err(violation);
+        };
+        lenient.diagnostics.record(lenient.slot, violation);
+        Ok(())
+    }
+
+    fn reject_children(&mut self, children: StorageNodeChildren) -> Result<(), StorageViolation> {
+        if children.is_empty() {
+            return Ok(());
+        }
+        self.report(StorageViolation::unexpected_children(children.keys()))?;
+        if let Some(lenient) = &self.lenient {
+            lenient.diagnostics.keep_children(lenient.slot, children);
+        }
+        Ok(())
+    }
+
+    fn child_reader(&self, key: &Bytes, structure: StorageStructure) -> impl StorageReader {
+        let iterator = structure.0.into_iter();
+        let reader = match &self.lenient {
+            Some(lenient) => {
+                let location = MappingEntryLocation {
+                    entry_key: key.clone(),
+                    mapping_slot: lenient.slot,
+                };
+                let slot = lenient.diagnostics.entry_slot(&location);
+                StorageReaderImpl::lenient(iterator, slot, lenient.diagnostics.clone())
+            }
+            None => StorageReaderImpl::new(iterator),
+        };
+        StorageReaderImpl {
+            key_preimages: self.key_preimages,
+            ..reader
+        }
+    }
+
+    fn key_preimage(&self, key: &Bytes) -> Option<Bytes> {
+        let hash = B256::try_from(key.as_ref()).ok()?;
+        self.key_preimages?.get(&hash).cloned()
+    }
+
+    fn enter(&mut self, segment: impl FnOnce() -> LayoutPathSegment) {
+        if let Some(lenient) = &self.lenient {
+            lenient.diagnostics.enter(segment());
+        }
+    }
+
+    fn leave(&mut self) {
+        if let Some(lenient) = &self.lenient {
+            lenient.diagnostics.leave();
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -196,6 +426,8 @@ pub trait SubB256: Default {
     ///
     /// If the length of `src` and the number of bytes in `Self` do not match.
     fn from_slice(slice: &[u8]) -> Self;
+
+    fn as_slice(&self) -> &[u8];
 }
 
 macro_rules! impl_subb256 {
@@ -207,6 +439,10 @@ macro_rules! impl_subb256 {
                 fn from_slice(slice: &[u8]) -> Self {
                     Self::from_slice(slice)
                 }
+
+                fn as_slice(&self) -> &[u8] {
+                    self.as_slice()
+                }
             }
         )*
     };
@@ -274,4 +510,17 @@ mod tests {
             fixed_bytes!("1b9f")
         );
     }
+
+    #[test]
+    fn test_lenient_reader_is_send() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let diagnostics = StorageDiagnostics::new(crate::MappingKeySide::SOLIDITY);
+        let reader = StorageReaderImpl::lenient(
+            Vec::<StorageNode>::new().into_iter(),
+            B256::ZERO,
+            diagnostics,
+        );
+        assert_send(&reader);
+    }
 }
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, btree_map};
+
+use alloy_primitives::{B256, Bytes};
+
+use crate::{
+    MappingKeySide, Storage, StorageDiagnostic, StorageDiagnostics, StorageEntries, StorageNode,
+    StorageNodeChildren, StorageStructure,
+};
+
+/// Outcome of a decoding, along with what the layout did not account for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageReport<T> {
+    pub value: T,
+    /// Violations of the layout skipped in [`DecodeMode::Lenient`](crate::DecodeMode::Lenient).
+    pub diagnostics: Vec<StorageDiagnostic>,
+    pub leftovers: StorageLeftovers,
+}
+
+/// Storage entries that were not claimed by the layout.
+///
+/// Non-empty leftovers usually mean that the layout is incomplete, or that the contract has
+/// storage outside of it, such as namespaced storage behind a proxy.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct StorageLeftovers {
+    /// Anchors from which no value was read.
+    pub anchors: BTreeMap<B256, StorageNode>,
+    /// Hashed values found under a value that cannot have any, by slot of that value. They are
+    /// only skipped in [`DecodeMode::Lenient`](crate::DecodeMode::Lenient).
+    pub children: BTreeMap<B256, StorageNodeChildren>,
+    /// Values stored at a hashed slot whose preimage is not a mapping location.
+    pub undecoded: BTreeMap<B256, (Bytes, StorageStructure)>,
+}
+
+impl<T> StorageReport<T> {
+    /// Report of a decoding that left `storage` unread.
+    pub fn new(value: T, storage: Storage, diagnostics: Option<StorageDiagnostics>) -> Self {
+        let (diagnostics, children) = diagnostics
+            .map(|diagnostics| (diagnostics.take(), diagnostics.take_children()))
+            .unwrap_or_default();
+
+        Self {
+            value,
+            diagnostics,
+            leftovers: StorageLeftovers {
+                anchors: storage.anchors,
+                children,
+                undecoded: storage.undecoded,
+            },
+        }
+    }
+}
+
+impl StorageLeftovers {
+    pub fn is_empty(&self) -> bool {
+        self.anchors.values().all(StorageNode::is_zero)
+            && self
+                .children
+                .values()
+                .flatten()
+                .all(|(_, child)| child.is_zero())
+            && self
+                .undecoded
+                .values()
+                .all(|(_, structure)| structure.is_zero())
+    }
+
+    /// Raw storage entries of the leftovers.
+    pub fn entries(&self, side: MappingKeySide) -> StorageEntries {
+        let mut storage = Storage {
+            anchors: self.anchors.clone(),
+            undecoded: self.undecoded.clone(),
+            ..Default::default()
+        };
+
+        for (slot, children) in &self.children {
+            match storage.anchors.entry(*slot) {
+                btree_map::Entry::Vacant(vacant_entry) => {
+                    vacant_entry.insert(StorageNode {
+                        value: None,
+                        children: children.clone(),
+                    });
+                }
+                btree_map::Entry::Occupied(mut occupied_entry) => {
+                    occupied_entry.get_mut().children.extend(children.clone());
+                }
+            }
+        }
+
+        storage.encode(side).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdecode_preimages::MemoryPreimagesProvider;
+
+    use super::*;
+    use crate::StorageReader;
+
+    #[test]
+    fn test_leftovers() {
+        let side = MappingKeySide::SOLIDITY;
+        let mut preimages = MemoryPreimagesProvider::new();
+
+        // A value read by the layout, a value after it, and a value at a hashed slot which is
+        // not a mapping location.
+        let unclaimed_slot = B256::with_last_byte(7);
+        let namespace_slot = preimages.insert(Bytes::from_static(b"namespace"));
+        let mut entries = BTreeMap::from([
+            (B256::ZERO, B256::with_last_byte(1)),
+            (unclaimed_slot, B256::with_last_byte(2)),
+            (namespace_slot, B256::with_last_byte(3)),
+        ]);
+
+        let mut storage = Storage::decode(&preimages, entries.clone(), side).unwrap();
+        let word = storage.reader_at(B256::ZERO).next::<B256>().unwrap();
+        assert_eq!(word.word, B256::with_last_byte(1));
+
+        let report = StorageReport::new((), storage, None);
+        assert!(report.diagnostics.is_empty());
+
+        let leftovers = report.leftovers;
+        assert!(!leftovers.is_empty());
+        assert_eq!(
+            leftovers.anchors.keys().collect::<Vec<_>>(),
+            [&unclaimed_slot]
+        );
+        assert_eq!(
+            leftovers.undecoded.keys().collect::<Vec<_>>(),
+            [&namespace_slot]
+        );
+        assert!(leftovers.children.is_empty());
+        assert_eq!(
+            leftovers.entries(side),
+            BTreeMap::from([
+                (unclaimed_slot, B256::with_last_byte(2)),
+                (namespace_slot, B256::with_last_byte(3)),
+            ])
+        );
+
+        // A hashed value under a value that cannot have any is only skipped in lenient mode.
+        let child_slot = preimages.insert(Bytes::copy_from_slice(B256::ZERO.as_slice()));
+        entries.insert(child_slot, B256::with_last_byte(4));
+
+        let diagnostics = StorageDiagnostics::new(side);
+        let mut storage = Storage::decode(&preimages, entries, side).unwrap();
+        let mut reader = storage.reader_at_with(B256::ZERO, Some(diagnostics.clone()));
+        let word = reader.next::<B256>().unwrap();
+        reader.reject_children(word.children).unwrap();
+        drop(reader);
+
+        let report = StorageReport::new((), storage, Some(diagnostics));
+        assert_eq!(report.diagnostics.len(), 1);
+
+        let leftovers = report.leftovers;
+        assert_eq!(leftovers.children.keys().collect::<Vec<_>>(), [&B256::ZERO]);
+        assert_eq!(
+            leftovers.entries(side),
+            BTreeMap::from([
+                (unclaimed_slot, B256::with_last_byte(2)),
+                (namespace_slot, B256::with_last_byte(3)),
+                (child_slot, B256::with_last_byte(4)),
+            ])
+        );
+    }
+}
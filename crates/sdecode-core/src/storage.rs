@@ -1,42 +1,51 @@
 use std::collections::{BTreeMap, btree_map};
 
-use alloy_primitives::{B256, Bytes, U256};
+use alloy_primitives::{B256, Bytes, U256, keccak256};
 use overf::checked;
-use sdecode_preimages::{PreimagesProvider, PreimagesProviderMut, caches::StoragePreimagesCache};
+use sdecode_preimages::{
+    MemoryPreimagesProvider, PreimagesProvider, PreimagesProviderMut, caches::StoragePreimagesCache,
+};
 
 use crate::{
-    AnchorKind, MAX_STORAGE_OFFSET, MappingKeySide, StorageItem, StorageNode, StorageReader,
-    StorageStructure, reader::StorageReaderImpl, utils::b256_to_u256,
+    AnchorKind, HashLink, MAX_STORAGE_OFFSET, SlotDerivation, StorageDiagnostics, StorageEntries,
+    StorageItem, StorageNode, StorageReader, StorageStructure, reader::StorageReaderImpl,
+    utils::b256_to_u256,
 };
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Storage {
     pub anchors: BTreeMap<B256, StorageNode>,
     pub undecoded: BTreeMap<B256, (Bytes, StorageStructure)>,
+    /// Original values of the mapping keys hashed into their raw key, such as the `Bytes` and
+    /// `String` keys of Vyper, by raw key. Only looked up when the slot derivation
+    /// [hashes keys](SlotDerivation::hashes_keys).
+    #[serde(default)]
+    pub key_preimages: BTreeMap<B256, Bytes>,
 }
 
 impl Storage {
     pub fn decode<P: PreimagesProvider>(
         provider: P,
         storage_entries: impl IntoIterator<Item = (B256, B256)>,
-        side: MappingKeySide,
+        derivation: impl SlotDerivation,
     ) -> Result<Self, P::Error> {
         Self::decode_mut(
             &mut StoragePreimagesCache::new(provider, U256::from(MAX_STORAGE_OFFSET)),
             storage_entries,
-            side,
+            derivation,
         )
     }
 
     pub fn decode_mut<P: PreimagesProviderMut>(
         provider: &mut P,
         storage_entries: impl IntoIterator<Item = (B256, B256)>,
-        side: MappingKeySide,
+        derivation: impl SlotDerivation,
     ) -> Result<Self, P::Error> {
         let mut layout = Self::default();
 
         for (slot, value) in storage_entries {
-            let item = StorageItem::decode_mut(provider, side, slot, value)?;
+            let item = StorageItem::decode_mut(provider, &derivation, slot, value)?;
+            layout.resolve_key_preimages(provider, &derivation, &item.kind)?;
 
             match item.kind {
                 AnchorKind::UnknownPreimage { link } => match layout.anchors.entry(item.anchor) {
@@ -67,6 +76,154 @@ impl Storage {
         Ok(layout)
     }
 
+    /// Applies new slot values, such as the ones of a state diff, to the decoded storage in place.
+    /// A zero value removes the word it replaces, along with the nodes left empty.
+    pub fn update<P: PreimagesProvider>(
+        &mut self,
+        provider: P,
+        storage_diff: impl IntoIterator<Item = (B256, B256)>,
+        derivation: impl SlotDerivation,
+    ) -> Result<(), P::Error> {
+        self.update_mut(
+            &mut StoragePreimagesCache::new(provider, U256::from(MAX_STORAGE_OFFSET)),
+            storage_diff,
+            derivation,
+        )
+    }
+
+    pub fn update_mut<P: PreimagesProviderMut>(
+        &mut self,
+        provider: &mut P,
+        storage_diff: impl IntoIterator<Item = (B256, B256)>,
+        derivation: impl SlotDerivation,
+    ) -> Result<(), P::Error> {
+        for (slot, value) in storage_diff {
+            let item = StorageItem::decode_mut(provider, &derivation, slot, value)?;
+            self.resolve_key_preimages(provider, &derivation, &item.kind)?;
+
+            match item.kind {
+                AnchorKind::UnknownPreimage { link } => match self.anchors.entry(item.anchor) {
+                    btree_map::Entry::Vacant(vacant_entry) => {
+                        let mut node = StorageNode::empty();
+                        node.set_link(link);
+                        if !node.is_empty() {
+                            vacant_entry.insert(node);
+                        }
+                    }
+                    btree_map::Entry::Occupied(mut occupied_entry) => {
+                        occupied_entry.get_mut().set_link(link);
+                        if occupied_entry.get().is_empty() {
+                            occupied_entry.remove();
+                        }
+                    }
+                },
+                AnchorKind::UndecodablePreimage { preimage, chain } => {
+                    match self.undecoded.entry(item.anchor) {
+                        btree_map::Entry::Vacant(vacant_entry) => {
+                            let mut structure = StorageStructure::default();
+                            structure.set_chain(chain);
+                            if !structure.is_empty() {
+                                vacant_entry.insert((preimage, structure));
+                            }
+                        }
+                        btree_map::Entry::Occupied(mut occupied_entry) => {
+                            let (current_preimage, structure) = occupied_entry.get_mut();
+                            debug_assert_eq!(*current_preimage, preimage);
+                            structure.set_chain(chain);
+                            if structure.is_empty() {
+                                occupied_entry.remove();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the preimages of the 32-byte keys leading to `kind`, which Vyper may have hashed.
+    fn resolve_key_preimages<P: PreimagesProviderMut>(
+        &mut self,
+        provider: &mut P,
+        derivation: &impl SlotDerivation,
+        kind: &AnchorKind,
+    ) -> Result<(), P::Error> {
+        if !derivation.hashes_keys() {
+            return Ok(());
+        }
+
+        let mut link = match kind {
+            AnchorKind::UnknownPreimage { link } => link,
+            AnchorKind::UndecodablePreimage { chain, .. } => &chain.link,
+        };
+        while let HashLink::Inner {
+            key,
+            remaining_chain,
+        } = link
+        {
+            let hash = B256::try_from(key.as_ref())
+                .ok()
+                .filter(|hash| !self.key_preimages.contains_key(hash));
+            if let Some(hash) = hash {
+                let preimage = provider.exact_preimage_mut(hash)?;
+                self.key_preimages
+                    .extend(preimage.map(|preimage| (hash, preimage)));
+            }
+            link = &remaining_chain.link;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts the nodes of `structure` as anchors, starting at `slot`. Empty nodes are skipped.
+    pub fn insert_structure(&mut self, slot: B256, structure: StorageStructure) {
+        let slot_u256 = b256_to_u256(slot);
+        for (i, node) in structure.0.into_iter().enumerate() {
+            if node.is_zero() {
+                continue;
+            }
+            let node_slot = B256::from(slot_u256.wrapping_add(U256::from(i)));
+            self.anchors.insert(node_slot, node);
+        }
+    }
+
+    /// Flattens the storage into its raw entries, along with the preimages needed to decode it
+    /// again.
+    pub fn encode(
+        &self,
+        derivation: impl SlotDerivation,
+    ) -> (StorageEntries, MemoryPreimagesProvider) {
+        let mut preimages = MemoryPreimagesProvider::new();
+        let mut storage_entries = StorageEntries::new();
+        self.encode_into(derivation, &mut preimages, &mut storage_entries);
+        (storage_entries, preimages)
+    }
+
+    pub fn encode_into(
+        &self,
+        derivation: impl SlotDerivation,
+        preimages: &mut MemoryPreimagesProvider,
+        storage_entries: &mut StorageEntries,
+    ) {
+        for (slot, node) in &self.anchors {
+            node.encode_into(*slot, &derivation, preimages, storage_entries);
+        }
+
+        for preimage in self.key_preimages.values() {
+            preimages.insert(preimage.clone());
+        }
+
+        for (anchor, (preimage, structure)) in &self.undecoded {
+            if structure.is_zero() {
+                continue;
+            }
+            debug_assert_eq!(*anchor, keccak256(preimage));
+            preimages.insert(preimage.clone());
+            structure.encode_into(*anchor, &derivation, preimages, storage_entries);
+        }
+    }
+
     pub fn anchor(&self, slot: B256) -> &StorageNode {
         self.anchors
             .get(&slot)
@@ -74,6 +231,16 @@ impl Storage {
     }
 
     pub fn reader_at(&mut self, slot: B256) -> impl StorageReader {
+        self.reader_at_with(slot, None)
+    }
+
+    /// Same as [`reader_at`](Self::reader_at). With `diagnostics`, the reader decodes in
+    /// [`DecodeMode::Lenient`](crate::DecodeMode::Lenient) and records the violations into it.
+    pub fn reader_at_with(
+        &mut self,
+        slot: B256,
+        diagnostics: Option<StorageDiagnostics>,
+    ) -> impl StorageReader {
         let slot_u256 = b256_to_u256(slot);
 
         // todo: use MAX_STORAGE_OFFSET
@@ -90,10 +257,120 @@ impl Storage {
         // Should not panic with the above computations
         let max_delta = usize::try_from(checked! { upper_u256 - slot_u256 }).unwrap();
 
-        StorageReaderImpl::new((0..=max_delta).filter_map(move |i| {
+        let anchors = &mut self.anchors;
+        let nodes = (0..=max_delta).filter_map(move |i| {
             let i = U256::from(i);
             let slot = B256::from(slot_u256.checked_add(i)?);
-            Some(self.anchors.remove(&slot).unwrap_or_default())
-        }))
+            Some(anchors.remove(&slot).unwrap_or_default())
+        });
+
+        let reader = match diagnostics {
+            Some(diagnostics) => StorageReaderImpl::lenient(nodes, slot, diagnostics),
+            None => StorageReaderImpl::new(nodes),
+        };
+        reader.with_key_preimages(&self.key_preimages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MappingEntryLocation, MappingKeySide};
+
+    #[test]
+    fn test_storage_update() {
+        let side = MappingKeySide::SOLIDITY;
+        let key = Bytes::from_static(&[0x11; 32]);
+        let other_key = Bytes::from_static(&[0x22; 32]);
+
+        let mut storage = Storage::default();
+        storage
+            .anchors
+            .insert(B256::ZERO, StorageNode::word(B256::with_last_byte(1)));
+        storage.anchors.insert(
+            B256::with_last_byte(1),
+            StorageNode::single_child(
+                key.clone(),
+                [B256::with_last_byte(2), B256::with_last_byte(3)]
+                    .iter()
+                    .collect(),
+            ),
+        );
+        let (_, mut preimages) = storage.encode(side);
+
+        let entry_slot = |key: &Bytes| {
+            let location = MappingEntryLocation {
+                entry_key: key.clone(),
+                mapping_slot: B256::with_last_byte(1),
+            };
+            keccak256(location.into_preimage(side))
+        };
+        preimages.insert(
+            MappingEntryLocation {
+                entry_key: other_key.clone(),
+                mapping_slot: B256::with_last_byte(1),
+            }
+            .into_preimage(side),
+        );
+        let entry = b256_to_u256(entry_slot(&key));
+
+        let storage_diff = [
+            (B256::ZERO, B256::with_last_byte(4)),
+            (B256::from(entry + U256::from(1)), B256::ZERO),
+            (entry_slot(&other_key), B256::with_last_byte(5)),
+        ];
+        storage.update(&preimages, storage_diff, side).unwrap();
+
+        let mut expected = Storage::default();
+        expected
+            .anchors
+            .insert(B256::ZERO, StorageNode::word(B256::with_last_byte(4)));
+        expected.anchors.insert(
+            B256::with_last_byte(1),
+            StorageNode::single_child(key.clone(), [B256::with_last_byte(2)].iter().collect())
+                .with_child(other_key, [B256::with_last_byte(5)].iter().collect()),
+        );
+        assert_eq!(storage, expected);
+
+        // Zeroing the last word of an entry removes it.
+        storage
+            .update(&preimages, [(entry_slot(&key), B256::ZERO)], side)
+            .unwrap();
+        assert_eq!(storage.anchors[&B256::with_last_byte(1)].children.len(), 1);
+
+        let (storage_entries, _) = storage.encode(side);
+        assert_eq!(
+            Storage::decode(&preimages, storage_entries, side).unwrap(),
+            storage
+        );
+    }
+
+    #[test]
+    fn test_vyper_key_preimages() {
+        let name = Bytes::from_static(b"tricrypto");
+        let key = keccak256(&name);
+
+        let mut preimages = MemoryPreimagesProvider::new();
+        preimages.insert(name.clone());
+        let location = MappingEntryLocation {
+            entry_key: key.into(),
+            mapping_slot: B256::with_last_byte(1),
+        };
+        let entry_slot = preimages.insert(location.into_preimage(MappingKeySide::VYPER));
+        let storage_entries = [(entry_slot, B256::with_last_byte(7))];
+
+        let mut storage =
+            Storage::decode(&preimages, storage_entries, MappingKeySide::VYPER).unwrap();
+        assert_eq!(storage.key_preimages, BTreeMap::from([(key, name.clone())]));
+
+        let mut reader = storage.reader_at(B256::with_last_byte(1));
+        let next = reader.next_or_default::<B256>();
+        let (raw_key, _) = next.children.into_iter().next().unwrap();
+        assert_eq!(reader.key_preimage(&raw_key), Some(name));
+
+        // Solidity never hashes its keys.
+        let storage =
+            Storage::decode(&preimages, storage_entries, MappingKeySide::SOLIDITY).unwrap();
+        assert!(storage.key_preimages.is_empty());
     }
 }
@@ -0,0 +1,151 @@
+use alloy_primitives::B256;
+
+use crate::{StorageNode, StorageNodeChildren, StorageStructure, SubB256};
+
+#[auto_impl::auto_impl(&mut)]
+pub trait StorageWriter {
+    fn write<B: SubB256>(&mut self, word: B, children: StorageNodeChildren);
+
+    fn skip_remaining(&mut self);
+
+    /// Leaves the `slots` words following the current one empty.
+    fn skip_slots(&mut self, slots: usize) {
+        for _ in 0..slots {
+            self.skip_remaining();
+            self.write(B256::ZERO, Default::default());
+        }
+        self.skip_remaining();
+    }
+}
+
+/// Writes packed words into a [`StorageStructure`], mirroring the way a [`StorageReader`] reads
+/// them.
+///
+/// [`StorageReader`]: crate::StorageReader
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageStructureWriter {
+    nodes: Vec<StorageNode>,
+
+    /// Number of bytes already used in the last node. `None` if the next write must start a new
+    /// node.
+    used: Option<usize>,
+}
+
+impl StorageStructureWriter {
+    pub const fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            used: None,
+        }
+    }
+
+    pub fn into_structure(self) -> StorageStructure {
+        StorageStructure(self.nodes)
+    }
+}
+
+impl StorageWriter for StorageStructureWriter {
+    fn write<B: SubB256>(&mut self, word: B, children: StorageNodeChildren) {
+        const { assert!(B::SIZE <= 32) };
+
+        let used = match self.used {
+            Some(used) if used + B::SIZE <= 32 => used,
+            _ => {
+                self.nodes.push(StorageNode::empty());
+                0
+            }
+        };
+
+        let node = self.nodes.last_mut().expect("pushed above");
+        let mut value = node.value();
+        value[(32 - used - B::SIZE)..(32 - used)].copy_from_slice(word.as_slice());
+        node.value = Some(value);
+        node.children.extend(children);
+
+        self.used = Some(used + B::SIZE);
+    }
+
+    fn skip_remaining(&mut self) {
+        self.used = None;
+    }
+
+    fn skip_slots(&mut self, slots: usize) {
+        self.nodes
+            .extend(std::iter::repeat_n(StorageNode::empty(), slots));
+        self.used = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{FixedBytes, b256, fixed_bytes};
+
+    use super::*;
+    use crate::{IntoStorageReader, StorageReader};
+
+    #[test]
+    fn test_structure_writer() {
+        let mut writer = StorageStructureWriter::new();
+
+        writer.write(fixed_bytes!("9f"), Default::default());
+        writer.write(fixed_bytes!("f4e81b"), Default::default());
+        writer.write(
+            fixed_bytes!("9586f58ca950436ec70e2b81075732ed8144283b3eb8c1a53be0"),
+            Default::default(),
+        );
+        writer.write(fixed_bytes!("17d5"), Default::default());
+        writer.write(fixed_bytes!("01"), Default::default());
+        writer.skip_remaining();
+        writer.write(fixed_bytes!("02"), Default::default());
+
+        let structure = writer.into_structure();
+        assert_eq!(
+            structure,
+            StorageStructure(vec![
+                StorageNode::word(b256!(
+                    "0x17d59586f58ca950436ec70e2b81075732ed8144283b3eb8c1a53be0f4e81b9f"
+                )),
+                StorageNode::word(b256!(
+                    "0x0000000000000000000000000000000000000000000000000000000000000001"
+                )),
+                StorageNode::word(b256!(
+                    "0x0000000000000000000000000000000000000000000000000000000000000002"
+                )),
+            ])
+        );
+
+        let mut reader = structure.into_storage_reader();
+        assert_eq!(
+            reader.next::<FixedBytes<1>>().unwrap().word,
+            fixed_bytes!("9f")
+        );
+        assert_eq!(
+            reader.next::<FixedBytes<29>>().unwrap().word,
+            fixed_bytes!("9586f58ca950436ec70e2b81075732ed8144283b3eb8c1a53be0f4e81b")
+        );
+    }
+
+    #[test]
+    fn test_skip_slots() {
+        let mut writer = StorageStructureWriter::new();
+
+        writer.write(fixed_bytes!("01"), Default::default());
+        writer.skip_slots(2);
+        writer.write(fixed_bytes!("02"), Default::default());
+
+        let structure = writer.into_structure();
+        assert_eq!(structure.0.len(), 4);
+
+        let mut reader = structure.into_storage_reader();
+        assert_eq!(
+            reader.next::<FixedBytes<1>>().unwrap().word,
+            fixed_bytes!("01")
+        );
+        assert!(reader.consume_remaining().is_zero());
+        reader.skip_slots(2);
+        assert_eq!(
+            reader.next::<FixedBytes<1>>().unwrap().word,
+            fixed_bytes!("02")
+        );
+    }
+}
@@ -11,7 +11,7 @@ use revm_interpreter::{
     InstructionResult, Interpreter, InterpreterTypes, Stack,
     interpreter_types::{InputsTr, Jumps, LoopControl, MemoryTr, StackTr},
 };
-use sdecode_preimages::{Image, MemoryPreimagesProvider, Preimage};
+use sdecode_preimages::{Image, MemoryPreimagesProvider, Preimage, PreimageEntry, PreimagesSink};
 
 /// Preimages inspector.
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -125,6 +125,17 @@ impl PreimagesInspector {
     pub fn into_provider(self) -> MemoryPreimagesProvider {
         MemoryPreimagesProvider::from_iter_unchecked(self.into_preimages())
     }
+
+    /// Moves the preimages collected so far into `sink`, and flushes it. Calling it after each
+    /// transaction or block streams the preimages to their destination.
+    pub fn flush_into<S: PreimagesSink>(&mut self, sink: &mut S) -> Result<(), S::Error> {
+        sink.extend(
+            self.take_preimages()
+                .into_iter()
+                .map(|(image, preimage)| PreimageEntry::new_unchecked(image, preimage)),
+        )?;
+        sink.flush()
+    }
 }
 
 pub trait PeekableStack: StackTr {
@@ -136,3 +147,56 @@ impl PeekableStack for Stack {
         self.peek(no_from_top)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use sdecode_preimages::{FilePreimagesBuilder, FilePreimagesProvider};
+
+    use super::*;
+
+    fn inspector_with(preimages: &MemoryPreimagesProvider) -> PreimagesInspector {
+        let mut inspector = PreimagesInspector::new();
+        inspector.preimages.extend(
+            preimages
+                .clone()
+                .into_iter()
+                .map(|entry| (entry.image(), entry.preimage().clone())),
+        );
+        inspector
+    }
+
+    #[test]
+    fn test_flush_into() {
+        let preimages = MemoryPreimagesProvider::random_filled(20);
+
+        let mut inspector = inspector_with(&preimages);
+        let mut memory = MemoryPreimagesProvider::new();
+        let Ok(()) = inspector.flush_into(&mut memory);
+        assert!(inspector.preimages().is_empty());
+        assert_eq!(memory, preimages);
+
+        // Flushing an emptied inspector leaves the sink untouched.
+        let Ok(()) = inspector.flush_into(&mut memory);
+        assert_eq!(memory, preimages);
+
+        let path = std::env::temp_dir().join(format!(
+            "sdecode-inspector-{}-{}.db",
+            std::process::id(),
+            B256::random()
+        ));
+        let mut builder = FilePreimagesBuilder::open(&path).unwrap();
+        inspector_with(&preimages).flush_into(&mut builder).unwrap();
+        assert_eq!(builder.pending_len(), 0);
+        assert_eq!(
+            FilePreimagesProvider::open(&path)
+                .unwrap()
+                .to_memory()
+                .unwrap(),
+            preimages
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
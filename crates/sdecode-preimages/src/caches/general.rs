@@ -1,17 +1,79 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, btree_map};
 
 use alloy_primitives::U256;
 use quick_impl::quick_impl;
 
-use crate::{Image, PreimageEntry, PreimagesProvider, PreimagesProviderMut, WrapPreimagesProvider};
+use crate::{
+    Image, PreimageEntry, PreimagesProvider, PreimagesProviderMut, WrapPreimagesProvider,
+    utils::b256_to_u256,
+};
+
+/// Approximate memory taken by a cached interval, besides its preimage.
+const INTERVAL_OVERHEAD: usize = 160;
 
 /// Cache for general purpose use.
+///
+/// Each answer of the provider tells that no preimage lies between the queried image and the
+/// returned one. The cache remembers these intervals, for both lower and upper queries, so any
+/// query falling into one of them is answered from memory. Once the memory budget is exceeded,
+/// the least recently used intervals are evicted.
+///
+/// The underlying provider is assumed not to change while it is cached.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[quick_impl]
 pub struct GeneralPreimagesCache<P> {
     #[quick_impl(get = "{}", get_mut = "{}_mut", into)]
     provider: P,
-    cache: BTreeMap<U256, Option<PreimageEntry>>,
+
+    /// Intervals `[start, bound]` whose nearest lower preimage is `entry`, by `start`, which is
+    /// the image of the entry, or below all images if there is none.
+    lower: BTreeMap<IntervalKey, CachedInterval>,
+
+    /// Intervals `[bound, end]` whose nearest upper preimage is `entry`, by `end`, which is the
+    /// image of the entry, or above all images if there is none.
+    upper: BTreeMap<IntervalKey, CachedInterval>,
+
+    /// Intervals by last use.
+    usage: BTreeMap<u64, (Side, IntervalKey)>,
+    tick: u64,
+
+    #[quick_impl(pub get_clone = "{}")]
+    size: usize,
+
+    #[quick_impl(pub get_clone = "{}")]
+    max_size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CachedInterval {
+    bound: U256,
+    entry: Option<PreimageEntry>,
+    tick: u64,
+}
+
+impl CachedInterval {
+    fn size(&self) -> usize {
+        INTERVAL_OVERHEAD
+            + self
+                .entry
+                .as_ref()
+                .map_or(0, |entry| entry.preimage().len())
+    }
+}
+
+/// Image delimiting a cached interval. Intervals without preimage end beyond all the images, so
+/// that they never share their key with the interval of an actual preimage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum IntervalKey {
+    Min,
+    Image(U256),
+    Max,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    Lower,
+    Upper,
 }
 
 impl<P: PreimagesProvider> GeneralPreimagesCache<WrapPreimagesProvider<P>> {
@@ -24,7 +86,92 @@ impl<P: PreimagesProviderMut> GeneralPreimagesCache<P> {
     pub const fn new_mut(preimages_provider: P) -> Self {
         Self {
             provider: preimages_provider,
-            cache: BTreeMap::new(),
+            lower: BTreeMap::new(),
+            upper: BTreeMap::new(),
+            usage: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            max_size: Self::DEFAULT_MAX_SIZE,
+        }
+    }
+}
+
+impl<P> GeneralPreimagesCache<P> {
+    /// Default memory budget, in bytes.
+    pub const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+    /// Sets the memory budget, in bytes.
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self.evict();
+        self
+    }
+
+    /// Empties the cache, for instance after the underlying provider changed.
+    pub fn clear(&mut self) {
+        self.lower.clear();
+        self.upper.clear();
+        self.usage.clear();
+        self.size = 0;
+    }
+
+    fn intervals_mut(&mut self, side: Side) -> &mut BTreeMap<IntervalKey, CachedInterval> {
+        match side {
+            Side::Lower => &mut self.lower,
+            Side::Upper => &mut self.upper,
+        }
+    }
+
+    /// Marks the interval at `key` as the most recently used one.
+    fn touch(&mut self, side: Side, key: IntervalKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        let interval = self
+            .intervals_mut(side)
+            .get_mut(&key)
+            .expect("touched intervals are cached");
+        let old_tick = std::mem::replace(&mut interval.tick, tick);
+        self.usage.remove(&old_tick);
+        self.usage.insert(tick, (side, key));
+    }
+
+    /// Caches that the queries in the interval between `key` and `bound` resolve to `entry`.
+    fn insert(&mut self, side: Side, key: IntervalKey, bound: U256, entry: Option<PreimageEntry>) {
+        let intervals = match side {
+            Side::Lower => &mut self.lower,
+            Side::Upper => &mut self.upper,
+        };
+        match intervals.entry(key) {
+            btree_map::Entry::Occupied(mut occupied_entry) => {
+                let interval = occupied_entry.get_mut();
+                interval.bound = match side {
+                    Side::Lower => interval.bound.max(bound),
+                    Side::Upper => interval.bound.min(bound),
+                };
+            }
+            btree_map::Entry::Vacant(vacant_entry) => {
+                let interval = CachedInterval {
+                    bound,
+                    entry,
+                    tick: 0,
+                };
+                self.size += interval.size();
+                vacant_entry.insert(interval);
+            }
+        }
+        self.touch(side, key);
+        self.evict();
+    }
+
+    /// Evicts the least recently used intervals until the cache fits in its budget.
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((_, (side, key))) = self.usage.pop_first() else {
+                break;
+            };
+            if let Some(interval) = self.intervals_mut(side).remove(&key) {
+                self.size -= interval.size();
+            }
         }
     }
 }
@@ -36,13 +183,99 @@ impl<P: PreimagesProviderMut> PreimagesProviderMut for GeneralPreimagesCache<P>
         &mut self,
         image: Image,
     ) -> Result<Option<PreimageEntry>, Self::Error> {
-        self.provider.nearest_lower_preimage_mut(image)
+        let image_u256 = b256_to_u256(image);
+        let cached = self
+            .lower
+            .range(..=IntervalKey::Image(image_u256))
+            .next_back()
+            .filter(|(_, interval)| interval.bound >= image_u256)
+            .map(|(start, interval)| (*start, interval.entry.clone()));
+        if let Some((start, entry)) = cached {
+            self.touch(Side::Lower, start);
+            return Ok(entry);
+        }
+
+        let entry = self.provider.nearest_lower_preimage_mut(image)?;
+        let start = entry.as_ref().map_or(IntervalKey::Min, |entry| {
+            IntervalKey::Image(entry.image_u256())
+        });
+        self.insert(Side::Lower, start, image_u256, entry.clone());
+        Ok(entry)
     }
 
     fn nearest_upper_preimage_mut(
         &mut self,
         image: Image,
     ) -> Result<Option<PreimageEntry>, Self::Error> {
-        self.provider.nearest_upper_preimage_mut(image)
+        let image_u256 = b256_to_u256(image);
+        let cached = self
+            .upper
+            .range(IntervalKey::Image(image_u256)..)
+            .next()
+            .filter(|(_, interval)| interval.bound <= image_u256)
+            .map(|(end, interval)| (*end, interval.entry.clone()));
+        if let Some((end, entry)) = cached {
+            self.touch(Side::Upper, end);
+            return Ok(entry);
+        }
+
+        let entry = self.provider.nearest_upper_preimage_mut(image)?;
+        let end = entry.as_ref().map_or(IntervalKey::Max, |entry| {
+            IntervalKey::Image(entry.image_u256())
+        });
+        self.insert(Side::Upper, end, image_u256, entry.clone());
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+
+    use crate::{MemoryPreimagesProvider, misc::CounterPreimagesProviderMut, utils::B256_MAX};
+
+    use super::*;
+
+    #[test]
+    fn test_general_preimages_cache() {
+        let db = MemoryPreimagesProvider::random_filled(20);
+        let images: Vec<_> = [B256::ZERO, B256_MAX]
+            .into_iter()
+            .chain(db.iter().map(|(image, _)| *image))
+            .chain((0..50).map(|_| B256::random()))
+            .collect();
+
+        let mut cache = GeneralPreimagesCache::new_mut(CounterPreimagesProviderMut::new(&db));
+        check_cache(&mut cache, &db, &images);
+        let accesses = cache.provider().accesses();
+        assert!(accesses <= 2 * images.len());
+
+        // The second round is served from memory.
+        check_cache(&mut cache, &db, &images);
+        assert_eq!(cache.provider().accesses(), accesses);
+
+        // With a tiny budget, the answers stay correct.
+        let mut cache = GeneralPreimagesCache::new_mut(CounterPreimagesProviderMut::new(&db))
+            .with_max_size(4 * INTERVAL_OVERHEAD);
+        check_cache(&mut cache, &db, &images);
+        check_cache(&mut cache, &db, &images);
+        assert!(cache.size() <= cache.max_size());
+    }
+
+    fn check_cache<P: PreimagesProviderMut<Error = std::convert::Infallible>>(
+        cache: &mut GeneralPreimagesCache<P>,
+        db: &MemoryPreimagesProvider,
+        images: &[B256],
+    ) {
+        for image in images {
+            assert_eq!(
+                cache.nearest_lower_preimage_mut(*image).unwrap(),
+                db.nearest_lower_preimage(*image).unwrap()
+            );
+            assert_eq!(
+                cache.nearest_upper_preimage_mut(*image).unwrap(),
+                db.nearest_upper_preimage(*image).unwrap()
+            );
+        }
     }
 }
@@ -0,0 +1,260 @@
+//! Compact binary format to exchange preimages.
+//!
+//! A file starts with a header: the magic bytes `SDPREIMG`, the version and flags as
+//! little-endian `u32`s, and the number of entries as a little-endian `u64`. Each entry is a
+//! 32-byte image followed by the length of its preimage as a little-endian `u32`, at most
+//! [`MAX_PREIMAGE_LEN`], and the preimage. The entries are sorted by image, without duplicates.
+//! When [`CHECKSUM_FLAG`] is set, the entries are followed by the Keccak256 hash of all their
+//! bytes.
+
+use std::io::{self, Read, Write};
+
+use alloy_primitives::{B256, Keccak256, keccak256};
+
+use crate::{Image, Preimage, PreimageEntry, utils::invalid_data};
+
+pub const MAGIC: [u8; 8] = *b"SDPREIMG";
+pub const VERSION: u32 = 1;
+
+/// The entries are followed by a checksum.
+pub const CHECKSUM_FLAG: u32 = 1;
+
+/// Longest preimage of an entry, in bytes.
+pub const MAX_PREIMAGE_LEN: u32 = 16 * 1024 * 1024;
+
+/// Streaming reader of preimages in the binary format.
+#[derive(Debug)]
+pub struct PreimagesReader<R> {
+    reader: R,
+    remaining: u64,
+    last_image: Option<Image>,
+    checksum: Option<Keccak256>,
+    verify: bool,
+}
+
+impl<R: Read> PreimagesReader<R> {
+    /// Reads the header. The images are verified against their preimages by default.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            return Err(invalid_data("not a preimages file"));
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported preimages file version {version}"
+            )));
+        }
+        let flags = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        if flags & !CHECKSUM_FLAG != 0 {
+            return Err(invalid_data(format!(
+                "unknown preimages file flags {flags:#x}"
+            )));
+        }
+
+        Ok(Self {
+            reader,
+            remaining: u64::from_le_bytes(header[16..].try_into().unwrap()),
+            last_image: None,
+            checksum: (flags & CHECKSUM_FLAG != 0).then(Keccak256::new),
+            verify: true,
+        })
+    }
+
+    /// Whether `keccak256(preimage) == image` is checked for every entry.
+    pub const fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Number of entries left to read.
+    pub const fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Next entry, or `None` once all of them have been read and the checksum matched.
+    pub fn read_entry(&mut self) -> io::Result<Option<PreimageEntry>> {
+        if self.remaining == 0 {
+            if let Some(checksum) = self.checksum.take() {
+                let mut expected = B256::ZERO;
+                self.reader.read_exact(expected.as_mut_slice())?;
+                if checksum.finalize() != expected {
+                    return Err(invalid_data("preimages checksum mismatch"));
+                }
+            }
+            return Ok(None);
+        }
+
+        let mut head = [0; 36];
+        self.reader.read_exact(&mut head)?;
+        let image = B256::from_slice(&head[..32]);
+        let len = u32::from_le_bytes(head[32..].try_into().unwrap());
+        if len > MAX_PREIMAGE_LEN {
+            return Err(invalid_data(format!(
+                "preimage of {image} too long: {len} bytes"
+            )));
+        }
+        // The buffer grows with the bytes actually read, a truncated file does not allocate the
+        // announced length.
+        let mut preimage = Vec::new();
+        (&mut self.reader)
+            .take(len.into())
+            .read_to_end(&mut preimage)?;
+        if preimage.len() != len as usize {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        if self
+            .last_image
+            .is_some_and(|last_image| last_image >= image)
+        {
+            return Err(invalid_data(format!("unsorted preimage {image}")));
+        }
+        if self.verify && keccak256(&preimage) != image {
+            return Err(invalid_data(format!("invalid preimage for {image}")));
+        }
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(head);
+            checksum.update(&preimage);
+        }
+
+        self.last_image = Some(image);
+        self.remaining -= 1;
+        Ok(Some(PreimageEntry::new_unchecked(image, preimage.into())))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for PreimagesReader<R> {
+    type Item = io::Result<PreimageEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// Streaming writer of preimages in the binary format.
+#[derive(Debug)]
+pub struct PreimagesWriter<W> {
+    writer: W,
+    remaining: u64,
+    last_image: Option<Image>,
+    checksum: Option<Keccak256>,
+}
+
+impl<W: Write> PreimagesWriter<W> {
+    /// Writes the header of a file holding `len` entries, followed by a checksum if `checksum`.
+    pub fn new(mut writer: W, len: u64, checksum: bool) -> io::Result<Self> {
+        let flags = if checksum { CHECKSUM_FLAG } else { 0 };
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&flags.to_le_bytes())?;
+        writer.write_all(&len.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            remaining: len,
+            last_image: None,
+            checksum: checksum.then(Keccak256::new),
+        })
+    }
+
+    /// Writes an entry. The entries must be written in the order of their images.
+    pub fn write_entry(&mut self, image: Image, preimage: &Preimage) -> io::Result<()> {
+        if self.remaining == 0 {
+            return Err(invalid_input("more preimages than announced"));
+        }
+        if self
+            .last_image
+            .is_some_and(|last_image| last_image >= image)
+        {
+            return Err(invalid_input(format!("unsorted preimage {image}")));
+        }
+        let len = u32::try_from(preimage.len())
+            .ok()
+            .filter(|len| *len <= MAX_PREIMAGE_LEN)
+            .ok_or_else(|| {
+                invalid_input(format!(
+                    "preimage of {image} too long: {} bytes",
+                    preimage.len()
+                ))
+            })?;
+
+        let mut head = [0; 36];
+        head[..32].copy_from_slice(image.as_slice());
+        head[32..].copy_from_slice(&len.to_le_bytes());
+        self.writer.write_all(&head)?;
+        self.writer.write_all(preimage)?;
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(head);
+            checksum.update(preimage);
+        }
+
+        self.last_image = Some(image);
+        self.remaining -= 1;
+        Ok(())
+    }
+
+    /// Writes the checksum, after all the announced entries.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.remaining != 0 {
+            return Err(invalid_input(format!(
+                "{} announced preimages were not written",
+                self.remaining
+            )));
+        }
+        if let Some(checksum) = self.checksum.take() {
+            self.writer.write_all(checksum.finalize().as_slice())?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn invalid_input(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MemoryPreimagesProvider;
+
+    use super::*;
+
+    #[test]
+    fn test_binary_format() {
+        let memory = MemoryPreimagesProvider::random_filled(50);
+
+        for checksum in [false, true] {
+            let mut bytes = Vec::new();
+            memory.export(&mut bytes, checksum).unwrap();
+            assert_eq!(
+                MemoryPreimagesProvider::import(bytes.as_slice()).unwrap(),
+                memory
+            );
+
+            // A corrupted preimage is rejected.
+            let last = bytes.len() - if checksum { 33 } else { 1 };
+            bytes[last] ^= 1;
+            let err = MemoryPreimagesProvider::import(bytes.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        // An oversized length is rejected before reading the preimage.
+        let mut bytes = Vec::new();
+        memory.export(&mut bytes, false).unwrap();
+        bytes[24 + 32..24 + 36].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = MemoryPreimagesProvider::import(bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut writer = PreimagesWriter::new(Vec::new(), 2, false).unwrap();
+        let entry = PreimageEntry::new(Preimage::from_static(b"preimage"));
+        writer.write_entry(entry.image(), entry.preimage()).unwrap();
+        assert!(writer.write_entry(entry.image(), entry.preimage()).is_err());
+        assert!(writer.finish().is_err());
+    }
+}
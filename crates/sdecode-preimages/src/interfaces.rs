@@ -53,6 +53,43 @@ pub trait PreimagesProviderMut {
     }
 }
 
+/// Destination of new preimages.
+#[auto_impl::auto_impl(&mut, Box)]
+pub trait PreimagesSink {
+    type Error: Error;
+
+    /// Insert a preimage entry. Returns whether the image was new.
+    fn insert_entry(&mut self, entry: PreimageEntry) -> Result<bool, Self::Error>;
+
+    /// Insert a preimage.
+    fn insert(&mut self, preimage: Preimage) -> Result<Image, Self::Error> {
+        let entry = PreimageEntry::new(preimage);
+        let image = entry.image();
+        self.insert_entry(entry)?;
+        Ok(image)
+    }
+
+    /// Insert preimage entries.
+    #[auto_impl(keep_default_for(&mut, Box))]
+    fn extend(
+        &mut self,
+        entries: impl IntoIterator<Item = PreimageEntry>,
+    ) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        for entry in entries {
+            self.insert_entry(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Persists the inserted preimages, for sinks buffering them.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[quick_impl]
 pub struct WrapPreimagesProvider<P>(#[quick_impl(impl From, impl Deref, impl DerefMut)] pub P);
@@ -86,5 +123,6 @@ impl<P: PreimagesProvider> PreimagesProviderMut for WrapPreimagesProvider<P> {
 fn _assert_dyn_compatible<E: Error>(
     _: &dyn PreimagesProvider<Error = E>,
     _: &dyn PreimagesProviderMut<Error = E>,
+    _: &dyn PreimagesSink<Error = E>,
 ) {
 }
@@ -2,16 +2,23 @@
 
 pub mod caches;
 
+pub mod format;
+pub use format::{PreimagesReader, PreimagesWriter};
+
 mod interfaces;
 pub use interfaces::{
     BoxedPreimagesProvider, BoxedPreimagesProviderMut, PreimagesProvider, PreimagesProviderMut,
-    WrapPreimagesProvider,
+    PreimagesSink, WrapPreimagesProvider,
 };
 
 pub mod misc;
 
 mod providers;
-pub use providers::{EmptyPreimagesProvider, MemoryPreimagesProvider};
+pub use providers::{
+    EmptyPreimagesProvider, FilePreimagesBuilder, FilePreimagesProvider, MemoryPreimagesProvider,
+};
+#[cfg(feature = "lmdb")]
+pub use providers::{LmdbPreimagesProvider, LmdbPreimagesTransaction};
 
 mod types;
 pub use types::{Image, Preimage, PreimageEntry};
@@ -1,19 +1,24 @@
+use std::{convert::Infallible, error::Error, fmt, io};
+
 use quick_impl::quick_impl;
 
 use crate::{
     Image, MemoryPreimagesProvider, PreimageEntry, PreimagesProvider, PreimagesProviderMut,
-    WrapPreimagesProvider,
+    PreimagesSink, WrapPreimagesProvider,
 };
 
+/// A [`PreimagesProviderMut`] inserting the preimages it provides into a [`PreimagesSink`], a
+/// [`MemoryPreimagesProvider`] by default.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 #[quick_impl]
-pub struct PreimagesProviderFiller<P> {
+pub struct PreimagesProviderFiller<P, S = MemoryPreimagesProvider> {
     #[quick_impl(pub get = "inner_{}", pub get_mut = "inner_{}_mut", pub into = "into_inner_{}")]
     provider: P,
 
-    #[quick_impl(pub get = "{}", pub into)]
-    result: MemoryPreimagesProvider,
+    #[quick_impl(pub get = "{}", pub get_mut = "{}_mut", pub into)]
+    result: S,
 }
+
 impl<P: PreimagesProvider> PreimagesProviderFiller<WrapPreimagesProvider<P>> {
     pub const fn new(preimages_provider: P) -> Self {
         Self::new_mut(WrapPreimagesProvider(preimages_provider))
@@ -22,37 +27,250 @@ impl<P: PreimagesProvider> PreimagesProviderFiller<WrapPreimagesProvider<P>> {
 
 impl<P: PreimagesProviderMut> PreimagesProviderFiller<P> {
     pub const fn new_mut(preimages_provider: P) -> Self {
+        Self::with_sink_mut(preimages_provider, MemoryPreimagesProvider::new())
+    }
+}
+
+impl<P: PreimagesProvider, S: PreimagesSink> PreimagesProviderFiller<WrapPreimagesProvider<P>, S> {
+    /// Inserts the provided preimages into `sink`.
+    pub const fn with_sink(preimages_provider: P, sink: S) -> Self {
+        Self::with_sink_mut(WrapPreimagesProvider(preimages_provider), sink)
+    }
+}
+
+impl<P: PreimagesProviderMut, S: PreimagesSink> PreimagesProviderFiller<P, S> {
+    /// Inserts the provided preimages into `sink`.
+    pub const fn with_sink_mut(preimages_provider: P, sink: S) -> Self {
         Self {
             provider: preimages_provider,
-            result: MemoryPreimagesProvider::new(),
+            result: sink,
         }
     }
 }
 
-impl<P: PreimagesProviderMut> PreimagesProviderMut for PreimagesProviderFiller<P> {
-    type Error = P::Error;
-
-    fn nearest_lower_preimage_mut(
+impl<P, S> PreimagesProviderFiller<P, S>
+where
+    P: PreimagesProviderMut,
+    S: PreimagesSink<Error: IntoPreimagesFillerError<P::Error>>,
+{
+    fn fill(
         &mut self,
-        image: Image,
-    ) -> Result<Option<PreimageEntry>, Self::Error> {
-        let entry = self.provider.nearest_lower_preimage_mut(image)?;
+        entry: Option<PreimageEntry>,
+    ) -> Result<Option<PreimageEntry>, FillerError<P, S>> {
         if let Some(entry) = &entry {
             self.result
-                .insert_unchecked_with(entry.image(), || entry.preimage().clone());
+                .insert_entry(entry.clone())
+                .map_err(IntoPreimagesFillerError::into_filler_error)?;
         }
         Ok(entry)
     }
+}
+
+type FillerError<P, S> = <<S as PreimagesSink>::Error as IntoPreimagesFillerError<
+    <P as PreimagesProviderMut>::Error,
+>>::Error;
+
+/// Filling a sink which cannot fail, such as the default [`MemoryPreimagesProvider`], keeps the
+/// errors of the provider.
+impl<P, S> PreimagesProviderMut for PreimagesProviderFiller<P, S>
+where
+    P: PreimagesProviderMut,
+    S: PreimagesSink<Error: IntoPreimagesFillerError<P::Error>>,
+{
+    type Error = FillerError<P, S>;
+
+    fn nearest_lower_preimage_mut(
+        &mut self,
+        image: Image,
+    ) -> Result<Option<PreimageEntry>, Self::Error> {
+        let entry = self
+            .provider
+            .nearest_lower_preimage_mut(image)
+            .map_err(S::Error::from_provider_error)?;
+        self.fill(entry)
+    }
 
     fn nearest_upper_preimage_mut(
         &mut self,
         image: Image,
     ) -> Result<Option<PreimageEntry>, Self::Error> {
-        let entry = self.provider.nearest_upper_preimage_mut(image)?;
-        if let Some(entry) = &entry {
-            self.result
-                .insert_unchecked_with(entry.image(), || entry.preimage().clone());
+        let entry = self
+            .provider
+            .nearest_upper_preimage_mut(image)
+            .map_err(S::Error::from_provider_error)?;
+        self.fill(entry)
+    }
+}
+
+/// Error of a [`PreimagesSink`] filled by a [`PreimagesProviderFiller`], merged with the error `E`
+/// of its provider.
+///
+/// Sinks which cannot fail keep `E`, the others are merged into a [`PreimagesFillerError`]. Custom
+/// sinks implement it for their error to be filled.
+pub trait IntoPreimagesFillerError<E>: Error {
+    type Error: Error;
+
+    fn from_provider_error(err: E) -> Self::Error;
+
+    fn into_filler_error(self) -> Self::Error;
+}
+
+impl<E: Error> IntoPreimagesFillerError<E> for Infallible {
+    type Error = E;
+
+    fn from_provider_error(err: E) -> Self::Error {
+        err
+    }
+
+    fn into_filler_error(self) -> Self::Error {
+        match self {}
+    }
+}
+
+macro_rules! impl_into_preimages_filler_error {
+    ($($(#[$attr:meta])* $error:ty),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            impl<E: Error + 'static> IntoPreimagesFillerError<E> for $error {
+                type Error = PreimagesFillerError<E, Self>;
+
+                fn from_provider_error(err: E) -> Self::Error {
+                    PreimagesFillerError::Provider(err)
+                }
+
+                fn into_filler_error(self) -> Self::Error {
+                    PreimagesFillerError::Sink(self)
+                }
+            }
+        )*
+    };
+}
+
+impl_into_preimages_filler_error!(
+    io::Error,
+    #[cfg(feature = "lmdb")]
+    heed::Error,
+);
+
+/// Error of a [`PreimagesProviderFiller`] into a sink which can fail.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PreimagesFillerError<P, S> {
+    Provider(P),
+    Sink(S),
+}
+
+impl<P: fmt::Display, S: fmt::Display> fmt::Display for PreimagesFillerError<P, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider(err) => write!(f, "preimages provider error: {err}"),
+            Self::Sink(err) => write!(f, "preimages sink error: {err}"),
         }
-        Ok(entry)
+    }
+}
+
+impl<P: Error + 'static, S: Error + 'static> Error for PreimagesFillerError<P, S> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Provider(err) => Some(err),
+            Self::Sink(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+
+    use crate::FilePreimagesBuilder;
+
+    use super::*;
+
+    /// Provider failing on every lookup.
+    struct FailingProvider;
+
+    impl PreimagesProviderMut for FailingProvider {
+        type Error = io::Error;
+
+        fn nearest_lower_preimage_mut(
+            &mut self,
+            _image: Image,
+        ) -> Result<Option<PreimageEntry>, Self::Error> {
+            Err(io::Error::other("unavailable"))
+        }
+
+        fn nearest_upper_preimage_mut(
+            &mut self,
+            _image: Image,
+        ) -> Result<Option<PreimageEntry>, Self::Error> {
+            Err(io::Error::other("unavailable"))
+        }
+    }
+
+    /// Sink refusing every preimage.
+    struct FullSink;
+
+    impl PreimagesSink for FullSink {
+        type Error = io::Error;
+
+        fn insert_entry(&mut self, _entry: PreimageEntry) -> Result<bool, Self::Error> {
+            Err(io::Error::other("full"))
+        }
+    }
+
+    #[test]
+    fn test_preimages_provider_filler() {
+        let memory = MemoryPreimagesProvider::random_filled(20);
+        let images: Vec<_> = memory
+            .clone()
+            .into_iter()
+            .map(|entry| entry.image())
+            .collect();
+
+        // The default sink keeps the errors of the provider.
+        let mut filler = PreimagesProviderFiller::new(memory.clone());
+        for &image in &images {
+            let Ok(entry) = filler.nearest_upper_preimage_mut(image);
+            assert_eq!(entry.unwrap().image(), image);
+        }
+        assert_eq!(filler.into_result(), memory);
+
+        let mut filler = PreimagesProviderFiller::new_mut(FailingProvider);
+        let err: io::Error = filler.nearest_lower_preimage_mut(B256::ZERO).unwrap_err();
+        assert_eq!(err.to_string(), "unavailable");
+        assert!(filler.result().is_empty());
+    }
+
+    #[test]
+    fn test_preimages_provider_filler_sink() {
+        let memory = MemoryPreimagesProvider::random_filled(20);
+        let images: Vec<_> = memory
+            .clone()
+            .into_iter()
+            .map(|entry| entry.image())
+            .collect();
+
+        let mut filler =
+            PreimagesProviderFiller::with_sink(memory.clone(), FilePreimagesBuilder::new());
+        for &image in images.iter().step_by(2) {
+            assert!(filler.nearest_lower_preimage_mut(image).unwrap().is_some());
+        }
+        assert_eq!(filler.result().pending_len(), 10);
+
+        let mut filler = PreimagesProviderFiller::with_sink(memory, FullSink);
+        assert!(matches!(
+            filler.nearest_lower_preimage_mut(images[0]),
+            Err(PreimagesFillerError::Sink(_))
+        ));
+        // Nothing is inserted when nothing is found.
+        assert!(matches!(
+            filler.nearest_lower_preimage_mut(B256::ZERO),
+            Ok(None)
+        ));
+
+        let mut filler = PreimagesProviderFiller::with_sink_mut(FailingProvider, FullSink);
+        assert!(matches!(
+            filler.nearest_upper_preimage_mut(B256::ZERO),
+            Err(PreimagesFillerError::Provider(_))
+        ));
     }
 }
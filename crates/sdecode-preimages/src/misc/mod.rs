@@ -2,4 +2,4 @@ mod counter;
 pub use counter::{CounterPreimagesProvider, CounterPreimagesProviderMut};
 
 mod fill;
-pub use fill::PreimagesProviderFiller;
+pub use fill::{IntoPreimagesFillerError, PreimagesFillerError, PreimagesProviderFiller};
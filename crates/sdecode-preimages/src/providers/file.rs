@@ -0,0 +1,530 @@
+use std::{
+    cmp::Ordering,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+};
+
+use alloy_primitives::B256;
+
+use crate::{
+    Image, MemoryPreimagesProvider, Preimage, PreimageEntry, PreimagesProvider, PreimagesSink,
+    utils::invalid_data,
+};
+
+const MAGIC: [u8; 8] = *b"SDPREIDX";
+const VERSION: u32 = 1;
+
+/// Magic, version, reserved word and number of entries.
+const HEADER_LEN: u64 = 24;
+
+/// Image, offset of the preimage in the data section and length of the preimage.
+const RECORD_LEN: u64 = 48;
+
+/// Preimages database stored in a file, sorted by image.
+///
+/// The file starts with a fixed-size index of the images, followed by the preimages. Lookups
+/// binary search the index and only read the pages they need, so the database never has to fit
+/// in memory. Files are written with a [`FilePreimagesBuilder`].
+#[derive(Debug)]
+pub struct FilePreimagesProvider {
+    file: File,
+    path: PathBuf,
+    len: u64,
+
+    /// Offset of the preimages, after the index.
+    data_start: u64,
+
+    /// Length of the file, which bounds the records read from the index.
+    file_len: u64,
+}
+
+/// Entry of the index.
+#[derive(Debug, Clone, Copy)]
+struct IndexRecord {
+    image: Image,
+    offset: u64,
+    len: u64,
+}
+
+impl IndexRecord {
+    fn from_bytes(bytes: &[u8; RECORD_LEN as usize]) -> Self {
+        let (image, rest) = bytes.split_at(32);
+        let (offset, len) = rest.split_at(8);
+        Self {
+            image: B256::from_slice(image),
+            offset: u64::from_le_bytes(offset.try_into().unwrap()),
+            len: u64::from_le_bytes(len.try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_LEN as usize] {
+        let mut bytes = [0; RECORD_LEN as usize];
+        bytes[..32].copy_from_slice(self.image.as_slice());
+        bytes[32..40].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[40..].copy_from_slice(&self.len.to_le_bytes());
+        bytes
+    }
+}
+
+impl FilePreimagesProvider {
+    /// Opens a database written by a [`FilePreimagesBuilder`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+
+        let mut header = [0; HEADER_LEN as usize];
+        read_exact_at(&file, &mut header, 0)?;
+        if header[..8] != MAGIC {
+            return Err(invalid_data("not a preimages database"));
+        }
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported preimages database version {version}"
+            )));
+        }
+        let len = u64::from_le_bytes(header[16..].try_into().unwrap());
+
+        let file_len = file.metadata()?.len();
+        let data_start = len
+            .checked_mul(RECORD_LEN)
+            .and_then(|index_len| index_len.checked_add(HEADER_LEN))
+            .filter(|data_start| *data_start <= file_len)
+            .ok_or_else(|| invalid_data("truncated preimages database"))?;
+        Ok(Self {
+            file,
+            path,
+            len,
+            data_start,
+            file_len,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub const fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Entry at `index`, in the order of the images.
+    pub fn entry(&self, index: u64) -> io::Result<PreimageEntry> {
+        let record = self.record(index)?;
+        let start = self
+            .data_start
+            .checked_add(record.offset)
+            .filter(|start| {
+                start
+                    .checked_add(record.len)
+                    .is_some_and(|end| end <= self.file_len)
+            })
+            .ok_or_else(|| invalid_data(format!("preimage of {} out of bounds", record.image)))?;
+        let mut preimage = vec![0; usize::try_from(record.len).map_err(invalid_data)?];
+        read_exact_at(&self.file, &mut preimage, start)?;
+        Ok(PreimageEntry::new_unchecked(
+            record.image,
+            Preimage::from(preimage),
+        ))
+    }
+
+    /// Reads all the entries, in the order of the images.
+    pub fn to_memory(&self) -> io::Result<MemoryPreimagesProvider> {
+        (0..self.len).map(|index| self.entry(index)).collect()
+    }
+
+    /// Record at `index`, which is lower than `len`.
+    fn record(&self, index: u64) -> io::Result<IndexRecord> {
+        let mut bytes = [0; RECORD_LEN as usize];
+        read_exact_at(&self.file, &mut bytes, HEADER_LEN + index * RECORD_LEN)?;
+        Ok(IndexRecord::from_bytes(&bytes))
+    }
+
+    /// Number of leading entries whose image satisfies `pred`.
+    fn partition_point(&self, mut pred: impl FnMut(Image) -> bool) -> io::Result<u64> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred(self.record(mid)?.image) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Sequential reader of the index, through a new handle to keep lookups independent.
+    fn index_reader(&self) -> io::Result<impl Iterator<Item = io::Result<IndexRecord>>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(HEADER_LEN))?;
+        Ok((0..self.len).map(move |_| {
+            let mut bytes = [0; RECORD_LEN as usize];
+            reader.read_exact(&mut bytes)?;
+            Ok(IndexRecord::from_bytes(&bytes))
+        }))
+    }
+
+    /// Sequential reader of the preimages, which are stored in the order of the index.
+    fn data_reader(&self) -> io::Result<BufReader<File>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(self.data_start))?;
+        Ok(reader)
+    }
+}
+
+impl PreimagesProvider for FilePreimagesProvider {
+    type Error = io::Error;
+
+    fn nearest_lower_preimage(&self, image: Image) -> Result<Option<PreimageEntry>, Self::Error> {
+        match self.partition_point(|current| current <= image)? {
+            0 => Ok(None),
+            index => self.entry(index - 1).map(Some),
+        }
+    }
+
+    fn nearest_upper_preimage(&self, image: Image) -> Result<Option<PreimageEntry>, Self::Error> {
+        let index = self.partition_point(|current| current < image)?;
+        if index == self.len {
+            return Ok(None);
+        }
+        self.entry(index).map(Some)
+    }
+}
+
+/// Writes a [`FilePreimagesProvider`], merging new preimages into an existing database.
+///
+/// The new preimages are kept in memory until [`write`](Self::write) compacts them with the
+/// entries of the base database into a new sorted file. As a [`PreimagesSink`], flushing compacts
+/// them in place, into the database the builder was opened from.
+#[derive(Debug, Default)]
+pub struct FilePreimagesBuilder {
+    base: Option<FilePreimagesProvider>,
+    path: Option<PathBuf>,
+    pending: MemoryPreimagesProvider,
+}
+
+impl FilePreimagesBuilder {
+    pub const fn new() -> Self {
+        Self {
+            base: None,
+            path: None,
+            pending: MemoryPreimagesProvider::new(),
+        }
+    }
+
+    /// Appends to the entries of `base`.
+    pub fn from_provider(base: FilePreimagesProvider) -> Self {
+        Self {
+            path: Some(base.path.clone()),
+            base: Some(base),
+            pending: MemoryPreimagesProvider::new(),
+        }
+    }
+
+    /// Appends to the database at `path`, if it exists.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        match FilePreimagesProvider::open(&path) {
+            Ok(base) => Ok(Self::from_provider(base)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self {
+                path: Some(path.as_ref().to_path_buf()),
+                ..Self::new()
+            }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Number of preimages waiting to be written.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Insert a preimage.
+    pub fn insert(&mut self, preimage: Preimage) -> Image {
+        self.pending.insert(preimage)
+    }
+
+    /// Insert a preimage entry.
+    pub fn insert_entry(&mut self, entry: PreimageEntry) -> bool {
+        self.pending.insert_entry(entry)
+    }
+
+    /// Writes the base entries and the new preimages to `path`, and opens the new database.
+    ///
+    /// The database is first written next to `path`, then moved over it, so `path` can be the
+    /// one of the base database.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<FilePreimagesProvider> {
+        let path = path.as_ref();
+        let mut tmp_path = OsString::from(path);
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let result = self.write_to(&tmp_path);
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result?;
+
+        fs::rename(&tmp_path, path)?;
+        FilePreimagesProvider::open(path)
+    }
+
+    fn write_to(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut len = 0;
+        for merged in self.merged()? {
+            merged?;
+            len += 1;
+        }
+
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 4])?;
+        writer.write_all(&u64::to_le_bytes(len))?;
+
+        let mut offset = 0;
+        for merged in self.merged()? {
+            let (image, entry) = merged?;
+            let record = IndexRecord {
+                image,
+                offset,
+                len: entry.len(),
+            };
+            writer.write_all(&record.to_bytes())?;
+            offset += record.len;
+        }
+
+        let mut base_data = self
+            .base
+            .as_ref()
+            .map(FilePreimagesProvider::data_reader)
+            .transpose()?;
+        for merged in self.merged()? {
+            match merged?.1 {
+                MergedEntry::Base(len) => {
+                    let reader = base_data.as_mut().expect("base entry without a base");
+                    if io::copy(&mut reader.take(len), &mut writer)? != len {
+                        return Err(invalid_data("truncated preimages database"));
+                    }
+                }
+                MergedEntry::Pending(preimage) => writer.write_all(preimage)?,
+            }
+        }
+
+        writer.into_inner()?.sync_all()
+    }
+
+    /// Entries of the new database, in the order of the images.
+    fn merged(&self) -> io::Result<Merge<'_, impl Iterator<Item = io::Result<IndexRecord>>>> {
+        let base = match &self.base {
+            Some(base) => Some(base.index_reader()?),
+            None => None,
+        };
+        Ok(Merge {
+            base: base.into_iter().flatten().peekable(),
+            pending: self.pending.iter().peekable(),
+        })
+    }
+}
+
+impl PreimagesSink for FilePreimagesBuilder {
+    type Error = io::Error;
+
+    fn insert_entry(&mut self, entry: PreimageEntry) -> Result<bool, Self::Error> {
+        Ok(Self::insert_entry(self, entry))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the builder was not opened from a path",
+            ));
+        };
+        self.base = Some(self.write(path)?);
+        self.pending = MemoryPreimagesProvider::new();
+        Ok(())
+    }
+}
+
+enum MergedEntry<'a> {
+    /// Preimage of the base database, of the given length.
+    Base(u64),
+    Pending(&'a Preimage),
+}
+
+impl MergedEntry<'_> {
+    fn len(&self) -> u64 {
+        match self {
+            Self::Base(len) => *len,
+            Self::Pending(preimage) => preimage.len() as u64,
+        }
+    }
+}
+
+/// Merges the index of the base database with the new preimages. The entries of the base win
+/// over the new preimages with the same image.
+struct Merge<'a, I: Iterator> {
+    base: Peekable<I>,
+    pending: Peekable<std::collections::btree_map::Iter<'a, Image, Preimage>>,
+}
+
+impl<'a, I: Iterator<Item = io::Result<IndexRecord>>> Iterator for Merge<'a, I> {
+    type Item = io::Result<(Image, MergedEntry<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(Err(_)) = self.base.peek() {
+            return self.base.next().and_then(Result::err).map(Err);
+        }
+        let base_image = self
+            .base
+            .peek()
+            .and_then(|record| record.as_ref().ok())
+            .map(|record| record.image);
+        let pending_image = self.pending.peek().map(|(image, _)| **image);
+
+        let ordering = match (base_image, pending_image) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(base_image), Some(pending_image)) => base_image.cmp(&pending_image),
+        };
+        if ordering == Ordering::Equal {
+            self.pending.next();
+        }
+        if ordering == Ordering::Greater {
+            let (image, preimage) = self.pending.next()?;
+            return Some(Ok((*image, MergedEntry::Pending(preimage))));
+        }
+        let record = self.base.next()?;
+        Some(record.map(|record| (record.image, MergedEntry::Base(record.len))))
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::B256_MAX;
+
+    use super::*;
+
+    #[test]
+    fn test_file_preimages_provider() {
+        let path = std::env::temp_dir().join(format!(
+            "sdecode-preimages-{}-{}.db",
+            std::process::id(),
+            B256::random()
+        ));
+
+        let memory = MemoryPreimagesProvider::random_filled(100);
+        let mut builder = FilePreimagesBuilder::open(&path).unwrap();
+        for entry in memory.clone() {
+            builder.insert_entry(entry);
+        }
+        let provider = builder.write(&path).unwrap();
+        assert_eq!(provider.len(), 100);
+        assert_eq!(provider.to_memory().unwrap(), memory);
+
+        // Appending compacts the new preimages with the existing ones.
+        let mut builder = FilePreimagesBuilder::open(&path).unwrap();
+        let mut memory = memory;
+        for entry in MemoryPreimagesProvider::random_filled(50)
+            .into_iter()
+            .chain(memory.clone().into_iter().take(10))
+        {
+            memory.insert_entry(entry.clone());
+            builder.insert_entry(entry);
+        }
+        let provider = builder.write(&path).unwrap();
+        assert_eq!(provider.len(), 150);
+
+        for image in [B256::ZERO, B256_MAX]
+            .into_iter()
+            .chain((0..100).map(|_| B256::random()))
+        {
+            assert_eq!(
+                provider.nearest_lower_preimage(image).unwrap(),
+                memory.nearest_lower_preimage(image).unwrap()
+            );
+            assert_eq!(
+                provider.nearest_upper_preimage(image).unwrap(),
+                memory.nearest_upper_preimage(image).unwrap()
+            );
+        }
+        for entry in memory {
+            assert_eq!(
+                provider.exact_preimage(entry.image()).unwrap().as_ref(),
+                Some(entry.preimage())
+            );
+        }
+
+        // A corrupted number of entries is rejected.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[16..HEADER_LEN as usize].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let err = FilePreimagesProvider::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_preimages_sink() {
+        let path = std::env::temp_dir().join(format!(
+            "sdecode-preimages-{}-{}.db",
+            std::process::id(),
+            B256::random()
+        ));
+
+        let memory = MemoryPreimagesProvider::random_filled(20);
+        let mut builder = FilePreimagesBuilder::open(&path).unwrap();
+        PreimagesSink::extend(&mut builder, memory.clone()).unwrap();
+        builder.flush().unwrap();
+        assert_eq!(builder.pending_len(), 0);
+        assert_eq!(
+            FilePreimagesProvider::open(&path)
+                .unwrap()
+                .to_memory()
+                .unwrap(),
+            memory
+        );
+
+        assert!(FilePreimagesBuilder::new().flush().is_ok());
+        let mut builder = FilePreimagesBuilder::new();
+        builder.insert(Preimage::from_static(b"preimage"));
+        assert!(builder.flush().is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
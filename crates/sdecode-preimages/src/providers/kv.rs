@@ -0,0 +1,257 @@
+use std::{fs, path::Path};
+
+use alloy_primitives::B256;
+use heed::{Database, Env, EnvOpenOptions, RwTxn, types::Bytes};
+
+use crate::{Image, Preimage, PreimageEntry, PreimagesProvider, PreimagesSink};
+
+/// Preimages database backed by [LMDB](heed), an embedded ordered key-value store.
+///
+/// Preimages are inserted through [`LmdbPreimagesTransaction`]s: the preimages of a transaction
+/// are all visible once it is committed, and none of them if it is dropped before. Lookups read
+/// the last committed state, so the database can be shared between the threads and the processes
+/// collecting preimages and the ones decoding storage. Writers take turns, while readers are never
+/// blocked.
+///
+/// A process must open a given database only once, and share the provider between its threads.
+pub struct LmdbPreimagesProvider {
+    env: Env,
+    db: Database<Bytes, Bytes>,
+}
+
+impl LmdbPreimagesProvider {
+    /// Maximum size of the databases opened by [`open`](Self::open). Only address space is
+    /// reserved, the files grow with their content.
+    pub const DEFAULT_MAP_SIZE: usize = if cfg!(target_pointer_width = "64") {
+        1 << 40
+    } else {
+        1 << 30
+    };
+
+    /// Opens the database in the directory at `path`, creating both if needed.
+    ///
+    /// # Safety
+    ///
+    /// The files of the database are memory-mapped, so they must not be modified or truncated by
+    /// anything but LMDB while they are open. See [`EnvOpenOptions::open`].
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, heed::Error> {
+        let path = path.as_ref();
+        fs::create_dir_all(path)?;
+        // SAFETY: upheld by the caller.
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(Self::DEFAULT_MAP_SIZE)
+                .open(path)?
+        };
+        Self::from_env(env)
+    }
+
+    pub fn from_env(env: Env) -> Result<Self, heed::Error> {
+        let mut txn = env.write_txn()?;
+        let db = env.create_database(&mut txn, None)?;
+        txn.commit()?;
+        Ok(Self { env, db })
+    }
+
+    pub const fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Number of committed preimages.
+    pub fn len(&self) -> Result<u64, heed::Error> {
+        let txn = self.env.read_txn()?;
+        self.db.len(&txn)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, heed::Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Starts inserting preimages. Only one transaction can be open at a time across all the
+    /// processes, the others wait for it to end.
+    pub fn begin(&self) -> Result<LmdbPreimagesTransaction<'_>, heed::Error> {
+        Ok(LmdbPreimagesTransaction {
+            txn: self.env.write_txn()?,
+            db: self.db,
+        })
+    }
+}
+
+impl PreimagesProvider for LmdbPreimagesProvider {
+    type Error = heed::Error;
+
+    fn nearest_lower_preimage(&self, image: Image) -> Result<Option<PreimageEntry>, Self::Error> {
+        let txn = self.env.read_txn()?;
+        let entry = self.db.get_lower_than_or_equal_to(&txn, image.as_slice())?;
+        Ok(entry.map(preimage_entry))
+    }
+
+    fn nearest_upper_preimage(&self, image: Image) -> Result<Option<PreimageEntry>, Self::Error> {
+        let txn = self.env.read_txn()?;
+        let entry = self
+            .db
+            .get_greater_than_or_equal_to(&txn, image.as_slice())?;
+        Ok(entry.map(preimage_entry))
+    }
+}
+
+fn preimage_entry((image, preimage): (&[u8], &[u8])) -> PreimageEntry {
+    PreimageEntry::new_unchecked(B256::from_slice(image), Preimage::copy_from_slice(preimage))
+}
+
+/// Each insertion is committed on its own, and the entries of [`extend`](PreimagesSink::extend) all
+/// at once.
+impl PreimagesSink for LmdbPreimagesProvider {
+    type Error = heed::Error;
+
+    fn insert_entry(&mut self, entry: PreimageEntry) -> Result<bool, Self::Error> {
+        let mut txn = self.begin()?;
+        let inserted = txn.insert_entry(entry)?;
+        txn.commit()?;
+        Ok(inserted)
+    }
+
+    fn extend(
+        &mut self,
+        entries: impl IntoIterator<Item = PreimageEntry>,
+    ) -> Result<(), Self::Error> {
+        let mut txn = self.begin()?;
+        txn.extend(entries)?;
+        txn.commit()
+    }
+}
+
+/// Preimages inserted into a [`LmdbPreimagesProvider`] all at once. They are discarded if the
+/// transaction is dropped without being committed.
+pub struct LmdbPreimagesTransaction<'a> {
+    txn: RwTxn<'a>,
+    db: Database<Bytes, Bytes>,
+}
+
+impl LmdbPreimagesTransaction<'_> {
+    /// Makes the inserted preimages visible.
+    pub fn commit(self) -> Result<(), heed::Error> {
+        self.txn.commit()
+    }
+
+    /// Discards the inserted preimages.
+    pub fn abort(self) {
+        self.txn.abort()
+    }
+}
+
+impl PreimagesSink for LmdbPreimagesTransaction<'_> {
+    type Error = heed::Error;
+
+    fn insert_entry(&mut self, entry: PreimageEntry) -> Result<bool, Self::Error> {
+        // The preimage of an image never changes, so a known image is left untouched.
+        if self.db.get(&self.txn, entry.image().as_slice())?.is_some() {
+            return Ok(false);
+        }
+        self.db
+            .put(&mut self.txn, entry.image().as_slice(), entry.preimage())?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::PathBuf, process::Command};
+
+    use crate::{MemoryPreimagesProvider, utils::B256_MAX};
+
+    use super::*;
+
+    /// Set on the child process of [`test_lmdb_preimages_provider_processes`] to the database it
+    /// appends to.
+    const CHILD_DB_VAR: &str = "SDECODE_PREIMAGES_LMDB_CHILD";
+
+    fn temp_dir() -> PathBuf {
+        env::temp_dir().join(format!(
+            "sdecode-preimages-{}-{}.lmdb",
+            std::process::id(),
+            B256::random()
+        ))
+    }
+
+    fn child_preimages() -> impl Iterator<Item = Preimage> {
+        (0..50u64).map(|i| Preimage::copy_from_slice(&i.to_be_bytes()))
+    }
+
+    #[test]
+    fn test_lmdb_preimages_provider() {
+        let path = temp_dir();
+        let provider = unsafe { LmdbPreimagesProvider::open(&path) }.unwrap();
+        let memory = MemoryPreimagesProvider::random_filled(100);
+
+        // An aborted transaction leaves nothing behind.
+        let mut txn = provider.begin().unwrap();
+        txn.insert(Preimage::from_static(b"aborted")).unwrap();
+        txn.abort();
+        assert!(provider.is_empty().unwrap());
+
+        let mut txn = provider.begin().unwrap();
+        for entry in memory.clone() {
+            assert!(txn.insert_entry(entry).unwrap());
+        }
+        txn.commit().unwrap();
+        assert_eq!(provider.len().unwrap(), 100);
+
+        for image in [B256::ZERO, B256_MAX]
+            .into_iter()
+            .chain((0..100).map(|_| B256::random()))
+        {
+            assert_eq!(
+                provider.nearest_lower_preimage(image).unwrap(),
+                memory.nearest_lower_preimage(image).unwrap()
+            );
+            assert_eq!(
+                provider.nearest_upper_preimage(image).unwrap(),
+                memory.nearest_upper_preimage(image).unwrap()
+            );
+        }
+
+        drop(provider);
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Another process appends to the database while this one keeps it open.
+    #[test]
+    fn test_lmdb_preimages_provider_processes() {
+        if let Some(path) = env::var_os(CHILD_DB_VAR) {
+            let provider = unsafe { LmdbPreimagesProvider::open(path) }.unwrap();
+            assert_eq!(provider.len().unwrap(), 100);
+            let mut txn = provider.begin().unwrap();
+            for preimage in child_preimages() {
+                txn.insert(preimage).unwrap();
+            }
+            txn.commit().unwrap();
+            return;
+        }
+
+        let path = temp_dir();
+        let mut provider = unsafe { LmdbPreimagesProvider::open(&path) }.unwrap();
+        let memory = MemoryPreimagesProvider::random_filled(100);
+        provider.extend(memory).unwrap();
+
+        let status = Command::new(env::current_exe().unwrap())
+            .args([
+                "providers::kv::tests::test_lmdb_preimages_provider_processes",
+                "--exact",
+                "--quiet",
+            ])
+            .env(CHILD_DB_VAR, &path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        assert_eq!(provider.len().unwrap(), 150);
+        for preimage in child_preimages() {
+            let image = PreimageEntry::new(preimage.clone()).image();
+            assert_eq!(provider.exact_preimage(image).unwrap(), Some(preimage));
+        }
+
+        drop(provider);
+        fs::remove_dir_all(&path).unwrap();
+    }
+}
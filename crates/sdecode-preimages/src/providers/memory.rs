@@ -1,12 +1,16 @@
 use std::{
     collections::{BTreeMap, btree_map},
     convert::Infallible,
+    io::{self, Read, Write},
 };
 
 use alloy_primitives::keccak256;
 use quick_impl::quick_impl;
 
-use crate::{Image, Preimage, PreimageEntry, PreimagesProvider};
+use crate::{
+    Image, Preimage, PreimageEntry, PreimagesProvider, PreimagesSink,
+    format::{PreimagesReader, PreimagesWriter},
+};
 
 /// Preimages database in memory only.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
@@ -32,6 +36,11 @@ impl MemoryPreimagesProvider {
         self.preimages.is_empty()
     }
 
+    /// Iterates over the preimages, in the order of the images.
+    pub fn iter(&self) -> btree_map::Iter<'_, Image, Preimage> {
+        self.preimages.iter()
+    }
+
     /// New structure filled with `n` random preimages.
     pub fn random_filled(n: usize) -> Self {
         let mut res = Self::default();
@@ -73,6 +82,22 @@ impl MemoryPreimagesProvider {
         }
     }
 
+    /// Reads preimages in the [binary format](crate::format), verifying them.
+    pub fn import(reader: impl Read) -> io::Result<Self> {
+        PreimagesReader::new(reader)?.collect()
+    }
+
+    /// Writes the preimages in the [binary format](crate::format), followed by a checksum if
+    /// `checksum`.
+    pub fn export(&self, writer: impl Write, checksum: bool) -> io::Result<()> {
+        let mut writer = PreimagesWriter::new(writer, self.len() as u64, checksum)?;
+        for (image, preimage) in &self.preimages {
+            writer.write_entry(*image, preimage)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
     pub fn from_iter_unchecked(iter: impl IntoIterator<Item = (Image, Preimage)>) -> Self {
         iter.into_iter()
             .map(|(image, preimage)| PreimageEntry::new_unchecked(image, preimage))
@@ -114,6 +139,14 @@ impl PreimagesProvider for MemoryPreimagesProvider {
     }
 }
 
+impl PreimagesSink for MemoryPreimagesProvider {
+    type Error = Infallible;
+
+    fn insert_entry(&mut self, entry: PreimageEntry) -> Result<bool, Self::Error> {
+        Ok(Self::insert_entry(self, entry))
+    }
+}
+
 impl IntoIterator for MemoryPreimagesProvider {
     type Item = PreimageEntry;
 
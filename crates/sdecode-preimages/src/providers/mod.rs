@@ -1,5 +1,13 @@
 mod empty;
 pub use empty::EmptyPreimagesProvider;
 
+mod file;
+pub use file::{FilePreimagesBuilder, FilePreimagesProvider};
+
 mod memory;
 pub use memory::MemoryPreimagesProvider;
+
+#[cfg(feature = "lmdb")]
+mod kv;
+#[cfg(feature = "lmdb")]
+pub use kv::{LmdbPreimagesProvider, LmdbPreimagesTransaction};
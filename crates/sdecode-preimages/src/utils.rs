@@ -1,3 +1,5 @@
+use std::io;
+
 use alloy_primitives::{B256, U256, b256};
 
 pub const B256_MAX: B256 =
@@ -7,3 +9,7 @@ pub const B256_MAX: B256 =
 pub fn b256_to_u256(value: B256) -> U256 {
     From::from(value)
 }
+
+pub fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
@@ -7,6 +7,7 @@ use crate::{
 
 pub type ArraySize = usize;
 
+#[derive(Debug, Clone, Copy, Default)]
 pub struct ArraySizeEvaluator {}
 
 impl ArraySizeEvaluator {
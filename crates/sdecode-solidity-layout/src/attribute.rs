@@ -1,6 +1,15 @@
-use proc_macro2::TokenStream;
+use std::str::FromStr;
+
+use alloy_primitives::{B256, U256, keccak256};
+use proc_macro2::{Span, TokenStream};
 use quote::quote;
-use syn::{Attribute, Ident, Lit, LitStr, Path, meta::ParseNestedMeta, parse::Parse};
+use syn::{
+    Attribute, Expr, Ident, Lit, LitInt, LitStr, Path,
+    meta::ParseNestedMeta,
+    parse::{Parse, ParseStream},
+};
+
+use crate::erc7201_slot;
 
 pub const SDECODE_MACRO_HELPER: &str = "sdecode";
 
@@ -10,22 +19,52 @@ pub const REMOTE_ATTR: &str = "remote";
 pub const SLOT_ATTR: &str = "slot";
 pub const LANGUAGE_ATTR: &str = "language";
 pub const REEXPORT_ATTR: &str = "reexport";
+pub const ROOT_ATTR: &str = "root";
+pub const NAMESPACE_ATTR: &str = "namespace";
+pub const LAYOUT_AT_ATTR: &str = "layout_at";
+pub const MAX_LEN_ATTR: &str = "max_len";
+pub const DERIVATION_ATTR: &str = "derivation";
+
+const KECCAK256_FN: &str = "keccak256";
 
 #[derive(Debug, Clone, Default)]
 pub struct StorageVariableAttrs {
     pub typ: Option<Path>,
-    pub slot: Option<LitStr>,
+    pub slot: Option<SlotAttr>,
+    /// Bound of a Vyper `DynArray`, `Bytes` or `String`.
+    pub max_len: Option<LitInt>,
+    /// Scheme deriving the slots of the entries of a mapping, as a `MappingSlotDerivation`.
+    pub derivation: Option<Expr>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct StructureAttrs {
     pub rename: Option<Ident>,
     pub remote: Option<Path>,
+    /// The structure can be decoded on its own, as a namespaced root.
+    pub root: bool,
+    /// Base slot of the namespaced root.
+    pub slot: Option<SlotAttr>,
+}
+
+/// Slot given by a `slot`, `namespace` or `layout_at` attribute, computed at compile time.
+#[derive(Debug, Clone)]
+pub enum SlotAttr {
+    /// `slot = "0x..."`.
+    Literal(LitStr),
+    /// `slot = 42`, in any base.
+    Number(LitInt),
+    /// `slot = keccak256("...")`, as in diamond storage.
+    Keccak256(LitStr),
+    /// `namespace = "..."`, located following ERC-7201.
+    Namespace(LitStr),
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct StructureFieldAttrs {
     pub typ: Option<Path>,
+    /// Bound of a Vyper `DynArray`, `Bytes` or `String`.
+    pub max_len: Option<LitInt>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -40,6 +79,8 @@ pub struct ContractAttrs {
     pub rename: Option<Ident>,
     pub remote: Option<Path>,
     pub language: Option<LitStr>,
+    /// Base slot of the layout, as set by `layout at` in Solidity.
+    pub layout_at: Option<SlotAttr>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -64,12 +105,20 @@ impl StorageVariableAttrs {
                     return check_duplicate(old, &meta);
                 }
 
-                if meta.path.is_ident(SLOT_ATTR) {
-                    let Lit::Str(lit_str) = Lit::parse(meta.value()?)? else {
-                        return Err(meta.error("expected string literal"));
-                    };
+                if let Some(slot) = SlotAttr::parse_meta(&meta)? {
+                    let old = res.slot.replace(slot);
+                    return check_duplicate(old, &meta);
+                }
 
-                    let old = res.slot.replace(lit_str);
+                if meta.path.is_ident(MAX_LEN_ATTR) {
+                    let lit_int = LitInt::parse(meta.value()?)?;
+                    let old = res.max_len.replace(lit_int);
+                    return check_duplicate(old, &meta);
+                }
+
+                if meta.path.is_ident(DERIVATION_ATTR) {
+                    let expr = Expr::parse(meta.value()?)?;
+                    let old = res.derivation.replace(expr);
                     return check_duplicate(old, &meta);
                 }
 
@@ -78,6 +127,19 @@ impl StorageVariableAttrs {
         }
         Ok((res, attrs_tokens(remaining)))
     }
+
+    /// Value of the `slot` or `namespace` attribute, if any.
+    pub fn slot_value(&self) -> syn::Result<Option<B256>> {
+        self.slot.as_ref().map(SlotAttr::value).transpose()
+    }
+
+    /// Value of the `max_len` attribute, if any.
+    pub fn max_len_value(&self) -> syn::Result<Option<usize>> {
+        self.max_len
+            .as_ref()
+            .map(LitInt::base10_parse::<usize>)
+            .transpose()
+    }
 }
 
 impl StructureAttrs {
@@ -103,11 +165,31 @@ impl StructureAttrs {
                     return check_duplicate(old, &meta);
                 }
 
+                if meta.path.is_ident(ROOT_ATTR) {
+                    let old = std::mem::replace(&mut res.root, true);
+                    return check_duplicate(old.then_some(()), &meta);
+                }
+
+                if let Some(slot) = SlotAttr::parse_meta(&meta)? {
+                    let old = res.slot.replace(slot);
+                    return check_duplicate(old, &meta);
+                }
+
                 Err(meta.error("unrecognized attribute"))
             })?;
         }
         Ok((res, attrs_tokens(remaining)))
     }
+
+    /// Whether the structure is declared as a namespaced root, explicitly or through its slot.
+    pub fn is_root(&self) -> bool {
+        self.root || self.slot.is_some()
+    }
+
+    /// Value of the `slot` or `namespace` attribute, if any.
+    pub fn slot_value(&self) -> syn::Result<Option<B256>> {
+        self.slot.as_ref().map(SlotAttr::value).transpose()
+    }
 }
 
 impl StructureFieldAttrs {
@@ -127,11 +209,34 @@ impl StructureFieldAttrs {
                     return check_duplicate(old, &meta);
                 }
 
+                if meta.path.is_ident(MAX_LEN_ATTR) {
+                    let lit_int = LitInt::parse(meta.value()?)?;
+                    let old = res.max_len.replace(lit_int);
+                    return check_duplicate(old, &meta);
+                }
+
                 Err(meta.error("unrecognized attribute"))
             })?;
         }
         Ok((res, attrs_tokens(remaining)))
     }
+
+    /// Value of the `max_len` attribute, if any.
+    pub fn max_len_value(&self) -> syn::Result<Option<usize>> {
+        self.max_len
+            .as_ref()
+            .map(LitInt::base10_parse::<usize>)
+            .transpose()
+    }
+}
+
+/// Error for a `max_len` attribute on a type that is neither a dynamic array, `bytes` nor `string`,
+/// nor a mapping to one of them.
+pub fn max_len_error(span: Span) -> syn::Error {
+    syn::Error::new(
+        span,
+        "`max_len` only applies to dynamic arrays, `bytes` and `string`",
+    )
 }
 
 impl UdtAttrs {
@@ -192,6 +297,10 @@ impl ContractAttrs {
 
                     let old = res.language.replace(lit_str);
                     check_duplicate(old, &meta)
+                } else if meta.path.is_ident(LAYOUT_AT_ATTR) {
+                    let slot = SlotAttr::parse_slot(meta.value()?)?;
+                    let old = res.layout_at.replace(slot);
+                    check_duplicate(old, &meta)
                 } else {
                     Err(meta.error("unrecognized attribute"))
                 }
@@ -199,6 +308,18 @@ impl ContractAttrs {
         }
         Ok((res, attrs_tokens(remaining)))
     }
+
+    /// Whether the `language` attribute is set to Vyper.
+    pub fn is_vyper(&self) -> bool {
+        self.language
+            .as_ref()
+            .is_some_and(|language| language.value().eq_ignore_ascii_case("vyper"))
+    }
+
+    /// Value of the `layout_at` attribute, if any.
+    pub fn layout_at_value(&self) -> syn::Result<Option<B256>> {
+        self.layout_at.as_ref().map(SlotAttr::value).transpose()
+    }
 }
 
 impl GlobalAttrs {
@@ -225,6 +346,67 @@ impl GlobalAttrs {
     }
 }
 
+impl SlotAttr {
+    /// Parses a `slot` or `namespace` attribute, or returns `None` for other attributes.
+    fn parse_meta(meta: &ParseNestedMeta<'_>) -> syn::Result<Option<Self>> {
+        if meta.path.is_ident(NAMESPACE_ATTR) {
+            let Lit::Str(lit_str) = Lit::parse(meta.value()?)? else {
+                return Err(meta.error("expected string literal"));
+            };
+            return Ok(Some(Self::Namespace(lit_str)));
+        }
+
+        if meta.path.is_ident(SLOT_ATTR) {
+            return Self::parse_slot(meta.value()?).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn parse_slot(input: ParseStream<'_>) -> syn::Result<Self> {
+        if input.peek(LitStr) {
+            return input.parse::<LitStr>().map(Self::Literal);
+        }
+
+        if input.peek(LitInt) {
+            return LitInt::parse(input).map(Self::Number);
+        }
+
+        let func = Ident::parse(input)?;
+        if func != KECCAK256_FN {
+            return Err(syn::Error::new(
+                func.span(),
+                "expected string literal, integer literal or `keccak256(\"...\")`",
+            ));
+        }
+
+        let content;
+        syn::parenthesized!(content in input);
+        let preimage = content.parse::<LitStr>()?;
+        if !content.is_empty() {
+            return Err(content.error("expected a single string literal"));
+        }
+        Ok(Self::Keccak256(preimage))
+    }
+
+    pub fn value(&self) -> syn::Result<B256> {
+        match self {
+            Self::Literal(slot) => B256::from_str(&slot.value())
+                .map_err(|_| syn::Error::new(slot.span(), "invalid slot")),
+            Self::Number(slot) => {
+                if !slot.suffix().is_empty() {
+                    return Err(syn::Error::new(slot.span(), "unexpected suffix"));
+                }
+                U256::from_str(slot.base10_digits())
+                    .map(Into::into)
+                    .map_err(|_| syn::Error::new(slot.span(), "invalid slot"))
+            }
+            Self::Keccak256(preimage) => Ok(keccak256(preimage.value())),
+            Self::Namespace(id) => Ok(erc7201_slot(&id.value())),
+        }
+    }
+}
+
 fn check_duplicate<T>(old: Option<T>, meta: &ParseNestedMeta<'_>) -> syn::Result<()> {
     old.map_or(Ok(()), |_| Err(meta.error("duplicate attribute")))
 }
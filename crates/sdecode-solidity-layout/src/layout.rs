@@ -0,0 +1,245 @@
+use alloy_primitives::{B256, U256, keccak256};
+
+use crate::SolStorageDynType;
+
+/// A state variable of a [`SolStorageDynLayout`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct SolStorageDynVariable {
+    pub name: String,
+    /// Contract in which the variable is declared.
+    pub contract: String,
+    pub slot: U256,
+    /// Offset in bytes of the variable in its slot, starting from the right.
+    pub offset: usize,
+    #[serde(rename = "type")]
+    pub ty: SolStorageDynType,
+}
+
+/// Storage layout of a contract only known at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct SolStorageDynLayout {
+    pub name: String,
+    /// State variables, in storage order.
+    pub variables: Vec<SolStorageDynVariable>,
+}
+
+impl SolStorageDynLayout {
+    pub fn variable(&self, name: &str) -> Option<&SolStorageDynVariable> {
+        self.variables.iter().find(|variable| variable.name == name)
+    }
+}
+
+/// Space taken by a value in storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SolStorageFootprint {
+    /// Size in bytes of a value type, packed with its neighbours when they fit in the same slot.
+    Packed(usize),
+    /// Number of slots of a structure, an array, a mapping, `bytes` or `string`. Such values start
+    /// and end on a new slot.
+    Slots(U256),
+}
+
+impl SolStorageFootprint {
+    /// Footprint of a structure with fields of the given footprints.
+    pub fn structure(fields: impl IntoIterator<Item = Self>) -> Self {
+        let mut allocator = SlotAllocator::default();
+        for field in fields {
+            allocator.allocate(field);
+        }
+        allocator.align();
+        Self::Slots(allocator.slot())
+    }
+
+    /// Footprint of a fixed-size array of `len` elements.
+    pub fn fixed_array(element: Self, len: usize) -> Self {
+        let len = U256::from(len);
+        match element {
+            Self::Packed(size) => Self::Slots(len.div_ceil(U256::from(32 / size))),
+            Self::Slots(slots) => Self::Slots(len.saturating_mul(slots)),
+        }
+    }
+
+    /// Footprint of a Vyper `DynArray` of at most `max_len` elements: the length, followed by the
+    /// elements, each starting on a new slot.
+    pub fn vyper_dynamic_array(element: Self, max_len: usize) -> Self {
+        Self::Slots(
+            U256::from(max_len)
+                .saturating_mul(element.slots())
+                .saturating_add(U256::from(1)),
+        )
+    }
+
+    /// Footprint of a Vyper `Bytes` or `String` of at most `max_len` bytes: the length, followed
+    /// by the data.
+    pub fn vyper_bytes(max_len: usize) -> Self {
+        Self::Slots(U256::from(max_len.div_ceil(32)) + U256::from(1))
+    }
+
+    /// Number of slots taken by a value starting on a new slot.
+    pub fn slots(&self) -> U256 {
+        match self {
+            Self::Packed(_) => U256::from(1),
+            Self::Slots(slots) => *slots,
+        }
+    }
+
+    /// Size in bytes.
+    pub fn size(&self) -> U256 {
+        match self {
+            Self::Packed(size) => U256::from(*size),
+            Self::Slots(slots) => slots.saturating_mul(U256::from(32)),
+        }
+    }
+}
+
+/// Assigns storage locations to consecutive values, following the
+/// [Solidity packing rules](https://docs.soliditylang.org/en/latest/internals/layout_in_storage.html).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SlotAllocator {
+    slot: U256,
+    /// Number of bytes already used in `slot`.
+    offset: usize,
+}
+
+impl SlotAllocator {
+    pub const fn new(slot: U256) -> Self {
+        Self { slot, offset: 0 }
+    }
+
+    pub const fn slot(&self) -> U256 {
+        self.slot
+    }
+
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the location (slot and offset) of the next value, and moves past it.
+    pub fn allocate(&mut self, footprint: SolStorageFootprint) -> (U256, usize) {
+        match footprint {
+            SolStorageFootprint::Packed(size) => {
+                if self.offset + size > 32 {
+                    self.next_slot();
+                }
+                let location = (self.slot, self.offset);
+                self.offset += size;
+                location
+            }
+            SolStorageFootprint::Slots(slots) => {
+                self.align();
+                let location = (self.slot, 0);
+                self.slot = self.slot.wrapping_add(slots);
+                location
+            }
+        }
+    }
+
+    /// Moves to the beginning of the next slot, unless already there.
+    pub fn align(&mut self) {
+        if self.offset != 0 {
+            self.next_slot();
+        }
+    }
+
+    fn next_slot(&mut self) {
+        self.slot = self.slot.wrapping_add(U256::from(1));
+        self.offset = 0;
+    }
+}
+
+/// Base slot of the namespace `id`, following [ERC-7201](https://eips.ethereum.org/EIPS/eip-7201):
+/// `keccak256(abi.encode(uint256(keccak256(id)) - 1)) & ~bytes32(uint256(0xff))`.
+pub fn erc7201_slot(id: &str) -> B256 {
+    let location = U256::from_be_bytes(keccak256(id).0).wrapping_sub(U256::from(1));
+    let mut slot = keccak256(location.to_be_bytes::<32>());
+    slot.0[31] = 0;
+    slot
+}
+
+impl SolStorageDynType {
+    /// Space taken by a value of this type in storage.
+    pub fn footprint(&self) -> SolStorageFootprint {
+        let size = match self {
+            Self::Bool => 1,
+            Self::Uint { bits } | Self::Int { bits } => bits / 8,
+            Self::Address => 20,
+            Self::FixedBytes { size } => *size,
+            Self::Function => 24,
+            Self::Enum { .. } => 1,
+            Self::Struct { fields, .. } => {
+                return SolStorageFootprint::structure(
+                    fields.iter().map(|field| field.ty.footprint()),
+                );
+            }
+            Self::StructRef { slots, .. } => return SolStorageFootprint::Slots(*slots),
+            Self::FixedArray { element, len } => {
+                return SolStorageFootprint::fixed_array(element.footprint(), *len);
+            }
+            Self::Bytes
+            | Self::String
+            | Self::Array { .. }
+            | Self::Mapping { .. }
+            | Self::VyHashedBytes
+            | Self::VyHashedString => {
+                return SolStorageFootprint::Slots(U256::from(1));
+            }
+            Self::VyDynArray { element, max_len } => {
+                return SolStorageFootprint::vyper_dynamic_array(element.footprint(), *max_len);
+            }
+            Self::VyBytes { max_len } | Self::VyString { max_len } => {
+                return SolStorageFootprint::vyper_bytes(*max_len);
+            }
+        };
+        SolStorageFootprint::Packed(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::b256;
+
+    use super::*;
+
+    #[test]
+    fn test_erc7201_slot() {
+        assert_eq!(
+            erc7201_slot("openzeppelin.storage.ERC20"),
+            b256!("0x52c63247e1f47db19d5ce0460030c497f067ca4cebf71ba98eeadabe20bace00")
+        );
+    }
+
+    #[test]
+    fn test_slot_allocator() {
+        let uint = |bits| SolStorageDynType::Uint { bits };
+
+        let mut allocator = SlotAllocator::default();
+        assert_eq!(
+            allocator.allocate(uint(128).footprint()),
+            (U256::from(0), 0)
+        );
+        assert_eq!(
+            allocator.allocate(SolStorageDynType::Address.footprint()),
+            (U256::from(1), 0)
+        );
+        assert_eq!(
+            allocator.allocate(SolStorageDynType::Bool.footprint()),
+            (U256::from(1), 20)
+        );
+        assert_eq!(
+            allocator.allocate(
+                SolStorageDynType::FixedArray {
+                    element: Box::new(uint(64)),
+                    len: 5,
+                }
+                .footprint()
+            ),
+            (U256::from(2), 0)
+        );
+        assert_eq!(allocator.allocate(uint(8).footprint()), (U256::from(4), 0));
+        assert_eq!(
+            allocator.allocate(uint(256).footprint()),
+            (U256::from(5), 0)
+        );
+        assert_eq!(allocator.allocate(uint(8).footprint()), (U256::from(6), 0));
+    }
+}
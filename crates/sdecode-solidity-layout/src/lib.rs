@@ -0,0 +1,44 @@
+#![cfg_attr(not(test), warn(unused_crate_dependencies))]
+
+mod array_size;
+pub use array_size::{ArraySize, ArraySizeEvaluator};
+
+mod attribute;
+pub use attribute::{
+    ContractAttrs, GlobalAttrs, SlotAttr, StorageVariableAttrs, StructureAttrs,
+    StructureFieldAttrs, UdtAttrs, attrs_tokens, max_len_error,
+};
+
+mod layout;
+pub use layout::{
+    SlotAllocator, SolStorageDynLayout, SolStorageDynVariable, SolStorageFootprint, erc7201_slot,
+};
+
+mod linearize;
+pub use linearize::c3_linearize;
+
+mod pp;
+pub use pp::{PPContract, PPEnum, PPFile, PPStruct, PPUdt, PPVariableDef, UserDefinedItem};
+
+mod scope;
+pub use scope::{Scope, Scoped};
+
+mod solc;
+pub use solc::{
+    SolcLayoutError, SolcStorageEncoding, SolcStorageItem, SolcStorageLayout, SolcStorageTypeDef,
+};
+
+mod source;
+pub use source::{
+    contract_storage_layout, parse_storage_layouts, sol_storage_dyn_type, sol_storage_footprint,
+    structure_fields_footprints,
+};
+
+mod types;
+pub use types::{SolStorageDynField, SolStorageDynType};
+
+mod upgrade;
+pub use upgrade::{STORAGE_GAP_PREFIX, SolStorageUpgradeError};
+
+mod utils;
+pub use utils::to_snake_case;
@@ -26,7 +26,6 @@ pub struct PPFile<'a> {
 
     pub contracts: Vec<Rc<PPContract<'a>>>,
 
-    #[expect(unused)]
     pub raw: &'a File,
 }
 
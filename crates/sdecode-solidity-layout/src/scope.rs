@@ -53,6 +53,12 @@ impl<'a> Scope<'a> {
         }
     }
 
+    /// Whether the scope is a contract declared as Vyper code with the `language` attribute.
+    pub fn is_vyper(&self) -> bool {
+        self.contract
+            .is_some_and(|contract| contract.attrs.is_vyper())
+    }
+
     pub fn super_kw(&self) -> TokenStream {
         if self.contract.is_some() {
             quote! { super:: }
@@ -0,0 +1,279 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use alloy_primitives::U256;
+
+use crate::{SolStorageDynField, SolStorageDynLayout, SolStorageDynType, SolStorageDynVariable};
+
+/// The `storageLayout` output of `solc`.
+///
+/// See the [Solidity documentation](https://docs.soliditylang.org/en/latest/internals/layout_in_storage.html#json-output).
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SolcStorageLayout {
+    pub storage: Vec<SolcStorageItem>,
+    /// `null` when the contract has no state variable.
+    #[serde(default)]
+    pub types: Option<BTreeMap<String, SolcStorageTypeDef>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolcStorageItem {
+    #[serde(default)]
+    pub ast_id: Option<u64>,
+    pub contract: String,
+    pub label: String,
+    pub offset: usize,
+    /// Decimal number.
+    pub slot: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolcStorageTypeDef {
+    pub encoding: SolcStorageEncoding,
+    pub label: String,
+    /// Decimal number.
+    pub number_of_bytes: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub members: Option<Vec<SolcStorageItem>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SolcStorageEncoding {
+    Inplace,
+    Mapping,
+    DynamicArray,
+    Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SolcLayoutError {
+    #[error("type {0} is not defined")]
+    UndefinedType(String),
+
+    #[error("type {id} is not supported: {label}")]
+    UnsupportedType { id: String, label: String },
+
+    #[error("type {id} is missing field {field}")]
+    MissingField { id: String, field: &'static str },
+
+    /// A structure containing itself other than through a mapping or a dynamic array.
+    #[error("type {0} is recursive")]
+    RecursiveType(String),
+
+    #[error("invalid number {0}")]
+    InvalidNumber(String),
+}
+
+impl SolcStorageLayout {
+    /// Converts the `solc` output into a [`SolStorageDynLayout`] named `name`.
+    pub fn to_dyn_layout(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<SolStorageDynLayout, SolcLayoutError> {
+        let mut resolver = TypeResolver {
+            types: self.types.as_ref(),
+            stack: Vec::new(),
+            apart: 0,
+        };
+
+        let variables = self
+            .storage
+            .iter()
+            .map(|item| {
+                Ok(SolStorageDynVariable {
+                    name: item.label.clone(),
+                    contract: item.contract.clone(),
+                    slot: U256::from_str(&item.slot)
+                        .map_err(|_| SolcLayoutError::InvalidNumber(item.slot.clone()))?,
+                    offset: item.offset,
+                    ty: resolver.resolve(&item.ty)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(SolStorageDynLayout {
+            name: name.into(),
+            variables,
+        })
+    }
+}
+
+struct TypeResolver<'a> {
+    types: Option<&'a BTreeMap<String, SolcStorageTypeDef>>,
+    /// Types being resolved, to detect recursive structs.
+    stack: Vec<&'a str>,
+    /// Number of types of `stack` entered before the innermost mapping or dynamic array, whose
+    /// values are stored apart.
+    apart: usize,
+}
+
+impl<'a> TypeResolver<'a> {
+    fn resolve(&mut self, id: &'a str) -> Result<SolStorageDynType, SolcLayoutError> {
+        let Some(def) = self.types.and_then(|types| types.get(id)) else {
+            return Err(SolcLayoutError::UndefinedType(id.to_string()));
+        };
+
+        if self.stack[..self.apart].contains(&id) && def.members.is_some() {
+            // A structure containing itself through a mapping or a dynamic array.
+            let size = U256::from_str(&def.number_of_bytes)
+                .map_err(|_| SolcLayoutError::InvalidNumber(def.number_of_bytes.clone()))?;
+            return Ok(SolStorageDynType::StructRef {
+                name: strip_scope(&def.label, "struct ").to_string(),
+                slots: size.div_ceil(U256::from(32)),
+            });
+        }
+        if self.stack.contains(&id) {
+            return Err(SolcLayoutError::RecursiveType(id.to_string()));
+        }
+
+        self.stack.push(id);
+        let res = self.resolve_def(id, def);
+        self.stack.pop();
+
+        res
+    }
+
+    fn resolve_def(
+        &mut self,
+        id: &'a str,
+        def: &'a SolcStorageTypeDef,
+    ) -> Result<SolStorageDynType, SolcLayoutError> {
+        let missing = |field| SolcLayoutError::MissingField {
+            id: id.to_string(),
+            field,
+        };
+        let unsupported = || SolcLayoutError::UnsupportedType {
+            id: id.to_string(),
+            label: def.label.clone(),
+        };
+
+        let res = match def.encoding {
+            SolcStorageEncoding::Bytes => {
+                if def.label == "string" {
+                    SolStorageDynType::String
+                } else {
+                    SolStorageDynType::Bytes
+                }
+            }
+            SolcStorageEncoding::DynamicArray => {
+                let base = def.base.as_deref().ok_or_else(|| missing("base"))?;
+                SolStorageDynType::Array {
+                    element: Box::new(self.apart(|this| this.resolve(base))?),
+                }
+            }
+            SolcStorageEncoding::Mapping => {
+                let key = def.key.as_deref().ok_or_else(|| missing("key"))?;
+                let value = def.value.as_deref().ok_or_else(|| missing("value"))?;
+                SolStorageDynType::Mapping {
+                    key: Box::new(self.resolve(key)?),
+                    value: Box::new(self.apart(|this| this.resolve(value))?),
+                }
+            }
+            SolcStorageEncoding::Inplace => {
+                if let Some(members) = &def.members {
+                    let fields = members
+                        .iter()
+                        .map(|member| {
+                            Ok(SolStorageDynField {
+                                name: member.label.clone(),
+                                ty: self.resolve(&member.ty)?,
+                            })
+                        })
+                        .collect::<Result<_, _>>()?;
+                    SolStorageDynType::Struct {
+                        name: strip_scope(&def.label, "struct ").to_string(),
+                        fields,
+                    }
+                } else if let Some(base) = &def.base {
+                    // The label of `T[N]` ends with `[N]`.
+                    let len = def
+                        .label
+                        .strip_suffix(']')
+                        .and_then(|label| label.rsplit_once('['))
+                        .and_then(|(_, len)| len.parse().ok())
+                        .ok_or_else(unsupported)?;
+                    SolStorageDynType::FixedArray {
+                        element: Box::new(self.resolve(base)?),
+                        len,
+                    }
+                } else {
+                    resolve_value_type(id, def).ok_or_else(unsupported)?
+                }
+            }
+        };
+
+        Ok(res)
+    }
+
+    /// Resolves a mapping value or a dynamic array element, stored apart from the enclosing
+    /// structures.
+    fn apart<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let apart = std::mem::replace(&mut self.apart, self.stack.len());
+        let res = f(self);
+        self.apart = apart;
+        res
+    }
+}
+
+fn resolve_value_type(id: &str, def: &SolcStorageTypeDef) -> Option<SolStorageDynType> {
+    let label = def.label.as_str();
+    let number_of_bytes = def.number_of_bytes.parse::<usize>().ok()?;
+
+    let res = if label == "bool" {
+        SolStorageDynType::Bool
+    } else if label == "address" || label == "address payable" || label.starts_with("contract ") {
+        SolStorageDynType::Address
+    } else if let Some(name) = label.strip_prefix("enum ") {
+        SolStorageDynType::Enum {
+            name: strip_scope(name, "").to_string(),
+            variants: Vec::new(),
+        }
+    } else if label.starts_with("function") {
+        // External function pointers hold an address and a selector. Internal function pointers
+        // are offsets in the bytecode.
+        if number_of_bytes == 24 {
+            SolStorageDynType::Function
+        } else {
+            SolStorageDynType::Uint {
+                bits: number_of_bytes * 8,
+            }
+        }
+    } else if id.starts_with("t_userDefinedValueType") {
+        // `solc` does not expose the underlying type of user defined value types.
+        SolStorageDynType::Uint {
+            bits: number_of_bytes * 8,
+        }
+    } else if let Some(bits) = label.strip_prefix("uint") {
+        SolStorageDynType::Uint {
+            bits: bits.parse().ok()?,
+        }
+    } else if let Some(bits) = label.strip_prefix("int") {
+        SolStorageDynType::Int {
+            bits: bits.parse().ok()?,
+        }
+    } else if let Some(size) = label.strip_prefix("bytes") {
+        SolStorageDynType::FixedBytes {
+            size: size.parse().ok()?,
+        }
+    } else {
+        return None;
+    };
+
+    Some(res)
+}
+
+/// Removes `prefix`, and the name of the contract the type is declared in.
+fn strip_scope<'a>(label: &'a str, prefix: &str) -> &'a str {
+    let label = label.strip_prefix(prefix).unwrap_or(label);
+    label.rsplit_once('.').map_or(label, |(_, name)| name)
+}
@@ -0,0 +1,708 @@
+use std::num::NonZero;
+
+use alloy_primitives::U256;
+use syn_solidity::{File, ItemStruct, SolPath, Spanned, Type, VariableDeclaration};
+
+use crate::{
+    ArraySizeEvaluator, PPContract, PPFile, PPStruct, Scope, Scoped, SlotAllocator,
+    SolStorageDynField, SolStorageDynLayout, SolStorageDynType, SolStorageDynVariable,
+    SolStorageFootprint, StructureFieldAttrs, UserDefinedItem, max_len_error,
+};
+
+/// Parses Solidity source code and computes the storage layout of each of its contracts.
+///
+/// The source is accepted with the same restrictions as the `sol_storage!` macro: it is tokenized
+/// as Rust code, so single-quoted string literals are not supported, and every inherited contract
+/// must be declared in the same source.
+///
+/// Interfaces and libraries are skipped.
+pub fn parse_storage_layouts(source: &str) -> syn::Result<Vec<SolStorageDynLayout>> {
+    let file = syn::parse_str::<File>(source)?;
+    let file = PPFile::pre_process(&file)?;
+    let sc = Scope::top_level(&file);
+
+    file.contracts
+        .iter()
+        .filter(|contract| !contract.raw.is_interface() && !contract.raw.is_library())
+        .map(|contract| contract_storage_layout(&sc, contract))
+        .collect()
+}
+
+/// Computes the storage layout of a contract, including the variables of the contracts it inherits
+/// from. The variables start at the `layout_at` slot of the contract, if any.
+pub fn contract_storage_layout(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+) -> syn::Result<SolStorageDynLayout> {
+    let base_slot = contract.attrs.layout_at_value()?.unwrap_or_default();
+    let mut allocator = SlotAllocator::new(base_slot.into());
+    let mut variables = Vec::new();
+
+    for parent in sc.file.parents_of(contract) {
+        let parent_sc = sc.in_contract(parent);
+        for var in parent
+            .udis
+            .values()
+            .filter_map(UserDefinedItem::as_variable)
+        {
+            if var.raw.attributes.has_immutable() || var.raw.attributes.has_constant() {
+                continue;
+            }
+
+            if let Some(slot) = var.attrs.slot_value()? {
+                allocator = SlotAllocator::new(slot.into());
+            }
+
+            let ty = sol_storage_dyn_type(&parent_sc, &var.raw.ty, var.attrs.max_len_value()?)?;
+            let (slot, offset) = allocator.allocate(ty.footprint());
+
+            variables.push(SolStorageDynVariable {
+                name: var.raw.name.to_string(),
+                contract: parent.raw.name.to_string(),
+                slot,
+                offset,
+                ty,
+            });
+        }
+    }
+
+    Ok(SolStorageDynLayout {
+        name: contract.raw.name.to_string(),
+        variables,
+    })
+}
+
+/// Resolves a Solidity type in the scope `sc` into its runtime description.
+///
+/// With a `max_len`, the dynamic array, `bytes` or `string`, possibly as the value of (nested)
+/// mappings, is resolved into its Vyper counterpart. Structures containing themselves through a
+/// mapping or a dynamic array refer back to themselves with [`SolStorageDynType::StructRef`].
+pub fn sol_storage_dyn_type(
+    sc: &Scope<'_>,
+    ty: &Type,
+    max_len: Option<usize>,
+) -> syn::Result<SolStorageDynType> {
+    TypeResolver::default().resolve_bounded(sc, ty, max_len)
+}
+
+/// Computes the space taken in storage by a Solidity type, resolved in the scope `sc`.
+///
+/// The types of mapping values and dynamic array elements are not resolved, as they are stored
+/// apart.
+pub fn sol_storage_footprint(
+    sc: &Scope<'_>,
+    ty: &Type,
+    max_len: Option<usize>,
+) -> syn::Result<SolStorageFootprint> {
+    TypeResolver::default().footprint_bounded(sc, ty, max_len)
+}
+
+/// Computes the footprint of each field of a structure, in declaration order.
+pub fn structure_fields_footprints(
+    sc: &Scope<'_>,
+    structure: &PPStruct<'_>,
+) -> syn::Result<Vec<SolStorageFootprint>> {
+    structure
+        .raw
+        .fields
+        .iter()
+        .map(|field| sol_storage_footprint(sc, &field.ty, field_max_len(field)?))
+        .collect()
+}
+
+/// Value of the `max_len` attribute of a structure field, if any.
+fn field_max_len(field: &VariableDeclaration) -> syn::Result<Option<usize>> {
+    StructureFieldAttrs::parse(&field.attrs)?.0.max_len_value()
+}
+
+#[derive(Default)]
+struct TypeResolver<'a> {
+    /// Structures being resolved, to detect recursive definitions.
+    stack: Vec<&'a ItemStruct>,
+    /// Number of structures of `stack` entered before the innermost mapping or dynamic array,
+    /// whose values are stored apart.
+    apart: usize,
+}
+
+impl<'a> TypeResolver<'a> {
+    fn resolve(&mut self, sc: &Scope<'a>, ty: &Type) -> syn::Result<SolStorageDynType> {
+        let result = match ty {
+            Type::Address(..) => SolStorageDynType::Address,
+            Type::Bool(_) => SolStorageDynType::Bool,
+            Type::String(_) => SolStorageDynType::String,
+            Type::Bytes(_) => SolStorageDynType::Bytes,
+            Type::FixedBytes(_, size) => SolStorageDynType::FixedBytes {
+                size: size.get().into(),
+            },
+            Type::Int(_, size) => SolStorageDynType::Int {
+                bits: size.map_or(256, NonZero::get).into(),
+            },
+            Type::Uint(_, size) => SolStorageDynType::Uint {
+                bits: size.map_or(256, NonZero::get).into(),
+            },
+            Type::Array(array) => {
+                if let Some(size) = &array.size {
+                    let element = Box::new(self.resolve(sc, &array.ty)?);
+                    let len = ArraySizeEvaluator::new().eval(sc, size)?;
+                    SolStorageDynType::FixedArray { element, len }
+                } else {
+                    let element = Box::new(self.apart(|this| this.resolve(sc, &array.ty))?);
+                    SolStorageDynType::Array { element }
+                }
+            }
+            Type::Tuple(tuple) => {
+                return Err(syn::Error::new(
+                    tuple.span(),
+                    "tuples are not supported as storage types",
+                ));
+            }
+            Type::Function(_) => SolStorageDynType::Function,
+            Type::Mapping(mapping) => SolStorageDynType::Mapping {
+                key: Box::new(self.resolve_key(sc, &mapping.key)?),
+                value: Box::new(self.apart(|this| this.resolve(sc, &mapping.value))?),
+            },
+            Type::Custom(path) => {
+                let item = storage_type_item(sc, path)?;
+                match item.inner {
+                    UserDefinedItem::Contract(_) => SolStorageDynType::Address,
+                    UserDefinedItem::Struct(structure) if self.is_apart(structure) => {
+                        SolStorageDynType::StructRef {
+                            name: structure.raw.name.to_string(),
+                            slots: TypeResolver::default().footprint(sc, ty)?.slots(),
+                        }
+                    }
+                    UserDefinedItem::Struct(structure) => {
+                        self.enter(structure, path)?;
+                        let fields = structure
+                            .raw
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                Ok(SolStorageDynField {
+                                    name: field
+                                        .name
+                                        .as_ref()
+                                        .map(ToString::to_string)
+                                        .unwrap_or_default(),
+                                    ty: self.resolve_bounded(
+                                        &item.scope,
+                                        &field.ty,
+                                        field_max_len(field)?,
+                                    )?,
+                                })
+                            })
+                            .collect::<syn::Result<_>>()?;
+                        self.stack.pop();
+                        SolStorageDynType::Struct {
+                            name: structure.raw.name.to_string(),
+                            fields,
+                        }
+                    }
+                    UserDefinedItem::Enum(enumm) => SolStorageDynType::Enum {
+                        name: enumm.raw.name.to_string(),
+                        variants: enumm
+                            .raw
+                            .variants
+                            .iter()
+                            .map(|variant| variant.ident.to_string())
+                            .collect(),
+                    },
+                    UserDefinedItem::Udt(udt) => self.resolve(&item.scope, &udt.raw.ty)?,
+                    _ => unreachable!(),
+                }
+            }
+        };
+
+        Ok(result)
+    }
+
+    fn footprint(&mut self, sc: &Scope<'a>, ty: &Type) -> syn::Result<SolStorageFootprint> {
+        let size = match ty {
+            Type::Address(..) => 20,
+            Type::Bool(_) => 1,
+            Type::FixedBytes(_, size) => size.get().into(),
+            Type::Int(_, size) | Type::Uint(_, size) => {
+                usize::from(size.map_or(256, NonZero::get)) / 8
+            }
+            Type::Function(_) => 24,
+            Type::String(_) | Type::Bytes(_) | Type::Mapping(_) => {
+                return Ok(SolStorageFootprint::Slots(U256::from(1)));
+            }
+            Type::Array(array) => {
+                let Some(size) = &array.size else {
+                    return Ok(SolStorageFootprint::Slots(U256::from(1)));
+                };
+                let len = ArraySizeEvaluator::new().eval(sc, size)?;
+                let element = self.footprint(sc, &array.ty)?;
+                return Ok(SolStorageFootprint::fixed_array(element, len));
+            }
+            Type::Tuple(tuple) => {
+                return Err(syn::Error::new(
+                    tuple.span(),
+                    "tuples are not supported as storage types",
+                ));
+            }
+            Type::Custom(path) => {
+                let item = storage_type_item(sc, path)?;
+                match item.inner {
+                    UserDefinedItem::Contract(_) => 20,
+                    UserDefinedItem::Enum(_) => 1,
+                    UserDefinedItem::Struct(structure) => {
+                        self.enter(structure, path)?;
+                        let fields = structure
+                            .raw
+                            .fields
+                            .iter()
+                            .map(|field| {
+                                self.footprint_bounded(
+                                    &item.scope,
+                                    &field.ty,
+                                    field_max_len(field)?,
+                                )
+                            })
+                            .collect::<syn::Result<Vec<_>>>()?;
+                        self.stack.pop();
+                        return Ok(SolStorageFootprint::structure(fields));
+                    }
+                    UserDefinedItem::Udt(udt) => return self.footprint(&item.scope, &udt.raw.ty),
+                    _ => unreachable!(),
+                }
+            }
+        };
+
+        Ok(SolStorageFootprint::Packed(size))
+    }
+
+    /// Resolves the key type of a mapping. Vyper hashes its `Bytes` and `String` keys.
+    fn resolve_key(&mut self, sc: &Scope<'a>, ty: &Type) -> syn::Result<SolStorageDynType> {
+        match ty {
+            Type::Bytes(_) if sc.is_vyper() => Ok(SolStorageDynType::VyHashedBytes),
+            Type::String(_) if sc.is_vyper() => Ok(SolStorageDynType::VyHashedString),
+            _ => self.resolve(sc, ty),
+        }
+    }
+
+    /// Resolves `ty`, turned into a Vyper `DynArray`, `Bytes` or `String` when bounded by
+    /// `max_len`.
+    fn resolve_bounded(
+        &mut self,
+        sc: &Scope<'a>,
+        ty: &Type,
+        max_len: Option<usize>,
+    ) -> syn::Result<SolStorageDynType> {
+        let Some(max_len) = max_len else {
+            return self.resolve(sc, ty);
+        };
+
+        let result = match ty {
+            Type::Array(array) if array.size.is_none() => SolStorageDynType::VyDynArray {
+                element: Box::new(self.resolve(sc, &array.ty)?),
+                max_len,
+            },
+            Type::Bytes(_) => SolStorageDynType::VyBytes { max_len },
+            Type::String(_) => SolStorageDynType::VyString { max_len },
+            Type::Mapping(mapping) => SolStorageDynType::Mapping {
+                key: Box::new(self.resolve_key(sc, &mapping.key)?),
+                value: Box::new(
+                    self.apart(|this| this.resolve_bounded(sc, &mapping.value, Some(max_len)))?,
+                ),
+            },
+            _ => return Err(max_len_error(ty.span())),
+        };
+
+        Ok(result)
+    }
+
+    /// Computes the footprint of `ty`, turned into a Vyper `DynArray`, `Bytes` or `String` when
+    /// bounded by `max_len`.
+    fn footprint_bounded(
+        &mut self,
+        sc: &Scope<'a>,
+        ty: &Type,
+        max_len: Option<usize>,
+    ) -> syn::Result<SolStorageFootprint> {
+        let Some(max_len) = max_len else {
+            return self.footprint(sc, ty);
+        };
+
+        match ty {
+            // Vyper arrays are stored in place, so their elements are resolved.
+            Type::Array(array) if array.size.is_none() => Ok(
+                SolStorageFootprint::vyper_dynamic_array(self.footprint(sc, &array.ty)?, max_len),
+            ),
+            Type::Bytes(_) | Type::String(_) => Ok(SolStorageFootprint::vyper_bytes(max_len)),
+            Type::Mapping(mapping) => {
+                // The bound applies to the value, stored apart from the mapping.
+                self.footprint_bounded(sc, &mapping.value, Some(max_len))?;
+                Ok(SolStorageFootprint::Slots(U256::from(1)))
+            }
+            _ => Err(max_len_error(ty.span())),
+        }
+    }
+
+    /// Resolves a mapping value or a dynamic array element, stored apart from the enclosing
+    /// structures.
+    fn apart<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let apart = std::mem::replace(&mut self.apart, self.stack.len());
+        let res = f(self);
+        self.apart = apart;
+        res
+    }
+
+    /// Whether `structure` is being resolved and encloses a mapping or a dynamic array leading to
+    /// the current type, so that it can be referenced.
+    fn is_apart(&self, structure: &PPStruct<'_>) -> bool {
+        self.stack[..self.apart]
+            .iter()
+            .any(|s| std::ptr::eq(*s, structure.raw))
+    }
+
+    /// Starts resolving the fields of `structure`, failing if it is already being resolved.
+    fn enter(&mut self, structure: &'a PPStruct<'a>, path: &SolPath) -> syn::Result<()> {
+        if self.stack.iter().any(|s| std::ptr::eq(*s, structure.raw)) {
+            return Err(syn::Error::new(
+                path.span(),
+                "structures can only contain themselves through a mapping or a dynamic array",
+            ));
+        }
+        self.stack.push(structure.raw);
+        Ok(())
+    }
+}
+
+/// Resolves the user defined item referenced by a storage type.
+fn storage_type_item<'a>(
+    sc: &Scope<'a>,
+    path: &SolPath,
+) -> syn::Result<Scoped<'a, &'a UserDefinedItem<'a>>> {
+    let item = sc
+        .user_defined_item_path(path)
+        .map_err(|failed_ident| syn::Error::new(failed_ident.span(), "item not found"))?;
+
+    let kind = match item.inner {
+        UserDefinedItem::Contract(_)
+        | UserDefinedItem::Struct(_)
+        | UserDefinedItem::Enum(_)
+        | UserDefinedItem::Udt(_) => return Ok(item),
+        UserDefinedItem::Variable(_) => "variable",
+        UserDefinedItem::Error => "error",
+        UserDefinedItem::Event => "event",
+    };
+
+    Err(syn::Error::new(
+        path.span(),
+        format!("{kind} cannot be resolved as a storage type"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{B256, b256};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_storage_layouts() {
+        let source = r#"
+            interface IERC20 {}
+
+            contract Ownable {
+                address owner;
+                bool paused;
+            }
+
+            contract Token is Ownable, IERC20 {
+                uint256 constant SIZE = 3;
+
+                enum Status {
+                    Active,
+                    Frozen
+                }
+
+                struct Account {
+                    uint128 balance;
+                    Status status;
+                    uint64[SIZE] history;
+                }
+
+                uint8 decimals;
+                mapping(address => Account) accounts;
+                Account treasury;
+                string name;
+
+                #[sdecode(slot = "0x0000000000000000000000000000000000000000000000000000000000000100")]
+                uint32 version;
+                uint16 flags;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        assert_eq!(layouts.len(), 2);
+
+        let token = &layouts[1];
+        assert_eq!(token.name, "Token");
+
+        let locations = token
+            .variables
+            .iter()
+            .map(|var| {
+                (
+                    var.name.as_str(),
+                    var.contract.as_str(),
+                    var.slot.to::<u64>(),
+                    var.offset,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            [
+                ("owner", "Ownable", 0, 0),
+                ("paused", "Ownable", 0, 20),
+                ("decimals", "Token", 0, 21),
+                ("accounts", "Token", 1, 0),
+                ("treasury", "Token", 2, 0),
+                ("name", "Token", 4, 0),
+                ("version", "Token", 0x100, 0),
+                ("flags", "Token", 0x100, 4),
+            ]
+        );
+
+        let SolStorageDynType::Struct { fields, .. } = &token.variable("treasury").unwrap().ty
+        else {
+            panic!("expected a structure");
+        };
+        assert_eq!(
+            fields[1].ty,
+            SolStorageDynType::Enum {
+                name: "Status".to_string(),
+                variants: vec!["Active".to_string(), "Frozen".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_slot_expressions() {
+        let source = r#"
+            contract Facet {
+                #[sdecode(slot = keccak256("diamond.standard.diamond.storage"))]
+                address owner;
+                bool paused;
+
+                #[sdecode(namespace = "openzeppelin.storage.ERC20")]
+                uint256 totalSupply;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        let slots = layouts[0]
+            .variables
+            .iter()
+            .map(|var| (var.name.as_str(), B256::from(var.slot), var.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slots,
+            [
+                (
+                    "owner",
+                    b256!("0xc8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c131c"),
+                    0
+                ),
+                (
+                    "paused",
+                    b256!("0xc8fcad8db84d3cc18b4c41d551ea0ee66dd599cde068d998e57d5e09332c131c"),
+                    20
+                ),
+                (
+                    "totalSupply",
+                    b256!("0x52c63247e1f47db19d5ce0460030c497f067ca4cebf71ba98eeadabe20bace00"),
+                    0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout_at() {
+        let source = r#"
+            contract Ownable {
+                address owner;
+            }
+
+            #[sdecode(layout_at = 0x1000)]
+            contract Vault is Ownable {
+                uint256 total;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        let slots = layouts
+            .iter()
+            .flat_map(|layout| {
+                layout.variables.iter().map(|var| {
+                    (
+                        layout.name.as_str(),
+                        var.name.as_str(),
+                        var.slot.to::<u64>(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slots,
+            [
+                ("Ownable", "owner", 0),
+                ("Vault", "owner", 0x1000),
+                ("Vault", "total", 0x1001),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_len() {
+        let source = r#"
+            #[sdecode(language = "vyper")]
+            contract Pool {
+                struct Route {
+                    #[sdecode(max_len = 4)]
+                    address[] hops;
+                    uint256 fee;
+                }
+
+                address owner;
+                #[sdecode(max_len = 3)]
+                uint256[] coins;
+                #[sdecode(max_len = 40)]
+                string name;
+                #[sdecode(max_len = 64)]
+                mapping(address => bytes) data;
+                Route route;
+                uint256 total;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        let pool = &layouts[0];
+        let slots = pool
+            .variables
+            .iter()
+            .map(|var| (var.name.as_str(), var.slot.to::<u64>()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slots,
+            [
+                ("owner", 0),
+                ("coins", 1),
+                ("name", 5),
+                ("data", 8),
+                ("route", 9),
+                ("total", 15),
+            ]
+        );
+
+        assert_eq!(
+            pool.variable("coins").unwrap().ty.to_string(),
+            "DynArray[uint256, 3]"
+        );
+        assert_eq!(
+            pool.variable("data").unwrap().ty.to_string(),
+            "mapping(address => Bytes[64])"
+        );
+
+        let source = r#"
+            contract Token {
+                #[sdecode(max_len = 3)]
+                uint256 total;
+            }
+        "#;
+        assert!(parse_storage_layouts(source).is_err());
+    }
+
+    #[test]
+    fn test_vyper_hashed_keys() {
+        let source = r#"
+            #[sdecode(language = "vyper")]
+            contract Registry {
+                mapping(string => uint256) ids;
+                mapping(bytes => mapping(address => bool)) approved;
+            }
+
+            contract Names {
+                mapping(string => uint256) ids;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        assert_eq!(
+            layouts[0].variable("ids").unwrap().ty.to_string(),
+            "mapping(String => uint256)"
+        );
+        assert_eq!(
+            layouts[0].variable("approved").unwrap().ty.to_string(),
+            "mapping(Bytes => mapping(address => bool))"
+        );
+        assert_eq!(
+            layouts[1].variable("ids").unwrap().ty.to_string(),
+            "mapping(string => uint256)"
+        );
+    }
+
+    #[test]
+    fn test_recursive_structures() {
+        let source = r#"
+            contract Tree {
+                struct Node {
+                    uint64 id;
+                    mapping(uint256 => Node) children;
+                    Node[] siblings;
+                }
+
+                Node root;
+            }
+        "#;
+
+        let layouts = parse_storage_layouts(source).unwrap();
+        let node = SolStorageDynType::StructRef {
+            name: "Node".to_string(),
+            slots: U256::from(3),
+        };
+        assert_eq!(
+            layouts[0].variable("root").unwrap().ty,
+            SolStorageDynType::Struct {
+                name: "Node".to_string(),
+                fields: vec![
+                    SolStorageDynField {
+                        name: "id".to_string(),
+                        ty: SolStorageDynType::Uint { bits: 64 },
+                    },
+                    SolStorageDynField {
+                        name: "children".to_string(),
+                        ty: SolStorageDynType::Mapping {
+                            key: Box::new(SolStorageDynType::Uint { bits: 256 }),
+                            value: Box::new(node.clone()),
+                        },
+                    },
+                    SolStorageDynField {
+                        name: "siblings".to_string(),
+                        ty: SolStorageDynType::Array {
+                            element: Box::new(node),
+                        },
+                    },
+                ],
+            }
+        );
+
+        // A structure containing itself in place would take infinite space.
+        let source = r#"
+            contract Tree {
+                struct Node {
+                    uint64 id;
+                    Node[2] children;
+                }
+
+                Node root;
+            }
+        "#;
+        assert!(parse_storage_layouts(source).is_err());
+    }
+}
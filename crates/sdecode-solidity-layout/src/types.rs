@@ -0,0 +1,130 @@
+use std::fmt;
+
+use alloy_primitives::U256;
+
+/// A Solidity storage type only known at runtime.
+///
+/// This is the runtime counterpart of the types implementing `SolStorageType` in
+/// `sdecode-solidity`, and is decoded using the exact same layout rules.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SolStorageDynType {
+    Bool,
+    Uint {
+        bits: usize,
+    },
+    Int {
+        bits: usize,
+    },
+    Address,
+    FixedBytes {
+        size: usize,
+    },
+    Function,
+    Bytes,
+    String,
+    Enum {
+        name: String,
+        /// Names of the variants, in declaration order. Might be empty when unknown.
+        #[serde(default)]
+        variants: Vec<String>,
+    },
+    FixedArray {
+        element: Box<SolStorageDynType>,
+        len: usize,
+    },
+    Array {
+        element: Box<SolStorageDynType>,
+    },
+    Mapping {
+        key: Box<SolStorageDynType>,
+        value: Box<SolStorageDynType>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<SolStorageDynField>,
+    },
+    /// The enclosing [`Struct`](Self::Struct) named `name`, for structures containing themselves
+    /// through a mapping or a dynamic array, such as `Node` in
+    /// `struct Node { mapping(uint256 => Node) children; }`.
+    StructRef {
+        name: String,
+        /// Number of slots of the structure.
+        slots: U256,
+    },
+    /// Vyper `DynArray[T, N]`, whose elements follow the length in place.
+    VyDynArray {
+        element: Box<SolStorageDynType>,
+        max_len: usize,
+    },
+    /// Vyper `Bytes[N]`, whose data follows the length in place.
+    VyBytes {
+        max_len: usize,
+    },
+    /// Vyper `String[N]`, whose data follows the length in place.
+    VyString {
+        max_len: usize,
+    },
+    /// Vyper `Bytes` as a mapping key, hashed before locating the entry.
+    VyHashedBytes,
+    /// Vyper `String` as a mapping key, hashed before locating the entry.
+    VyHashedString,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct SolStorageDynField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: SolStorageDynType,
+}
+
+impl SolStorageDynType {
+    /// Whether this type can be used as a mapping key.
+    pub fn is_mapping_key(&self) -> bool {
+        matches!(
+            self,
+            Self::Bool
+                | Self::Uint { .. }
+                | Self::Int { .. }
+                | Self::Address
+                | Self::FixedBytes { .. }
+                | Self::Function
+                | Self::Bytes
+                | Self::String
+                | Self::Enum { .. }
+                | Self::VyHashedBytes
+                | Self::VyHashedString
+        )
+    }
+
+    /// Name of the type, formatted like `SolStorageType::SOL_STORAGE_NAME`.
+    pub fn sol_storage_name(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for SolStorageDynType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool => f.write_str("bool"),
+            Self::Uint { bits } => write!(f, "uint{bits}"),
+            Self::Int { bits } => write!(f, "int{bits}"),
+            Self::Address => f.write_str("address"),
+            Self::FixedBytes { size } => write!(f, "bytes{size}"),
+            Self::Function => f.write_str("function"),
+            Self::Bytes => f.write_str("bytes"),
+            Self::String => f.write_str("string"),
+            Self::Enum { name, .. } | Self::Struct { name, .. } | Self::StructRef { name, .. } => {
+                f.write_str(name)
+            }
+            Self::FixedArray { element, len } => write!(f, "{element}[{len}]"),
+            Self::Array { element } => write!(f, "{element}[]"),
+            Self::Mapping { key, value } => write!(f, "mapping({key} => {value})"),
+            Self::VyDynArray { element, max_len } => write!(f, "DynArray[{element}, {max_len}]"),
+            Self::VyBytes { max_len } => write!(f, "Bytes[{max_len}]"),
+            Self::VyString { max_len } => write!(f, "String[{max_len}]"),
+            Self::VyHashedBytes => f.write_str("Bytes"),
+            Self::VyHashedString => f.write_str("String"),
+        }
+    }
+}
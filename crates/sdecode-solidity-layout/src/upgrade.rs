@@ -0,0 +1,457 @@
+use alloy_primitives::U256;
+
+use crate::{SolStorageDynLayout, SolStorageDynType, SolStorageDynVariable};
+
+/// Prefix of the names of the arrays reserving storage for future variables.
+pub const STORAGE_GAP_PREFIX: &str = "__gap";
+
+/// An incompatible change between two versions of a storage layout.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SolStorageUpgradeError {
+    #[error("variable `{name}` was removed")]
+    Removed { name: String },
+
+    #[error("variable `{name}` is now declared before `{previous}`")]
+    Reordered { name: String, previous: String },
+
+    #[error(
+        "variable `{name}` moved from slot {old_slot} offset {old_offset} to slot {new_slot} offset {new_offset}"
+    )]
+    Moved {
+        name: String,
+        old_slot: U256,
+        old_offset: usize,
+        new_slot: U256,
+        new_offset: usize,
+    },
+
+    #[error("`{path}` changed type from `{old}` to `{new}`")]
+    TypeChanged {
+        path: String,
+        old: SolStorageDynType,
+        new: SolStorageDynType,
+    },
+
+    #[error("`{path}` changed width from `{old}` to `{new}`")]
+    WidthChanged {
+        path: String,
+        old: SolStorageDynType,
+        new: SolStorageDynType,
+    },
+
+    #[error("`{path}` changed size from {old} to {new} bytes")]
+    SizeChanged { path: String, old: U256, new: U256 },
+
+    #[error("field `{field}` was inserted in `{path}` before existing fields")]
+    FieldInserted { path: String, field: String },
+
+    #[error("field `{field}` was removed from `{path}`")]
+    FieldRemoved { path: String, field: String },
+
+    #[error("key of mapping `{path}` changed from `{old}` to `{new}`")]
+    MappingKeyChanged {
+        path: String,
+        old: SolStorageDynType,
+        new: SolStorageDynType,
+    },
+
+    #[error("storage gap `{name}` now ends at slot {new_end} instead of {old_end}")]
+    GapMisconsumed {
+        name: String,
+        old_end: U256,
+        new_end: U256,
+    },
+}
+
+impl SolStorageDynLayout {
+    /// Checks that upgrading a contract from this layout to `new` keeps the existing storage
+    /// readable, and returns every incompatible change.
+    ///
+    /// Variables are matched by name and declaring contract. New variables can be appended, or
+    /// take the place of a storage gap (a fixed-size array whose name starts with `__gap`), as
+    /// long as the gap is shrunk accordingly.
+    pub fn upgrade_errors(&self, new: &Self) -> Vec<SolStorageUpgradeError> {
+        let mut errors = Vec::new();
+
+        // Index in `new.variables` of the last matched variable, with its name.
+        let mut previous: Option<(usize, &str)> = None;
+
+        for old_var in &self.variables {
+            let Some((index, new_var)) = new
+                .variables
+                .iter()
+                .enumerate()
+                .find(|(_, new_var)| same_variable(old_var, new_var))
+            else {
+                if !is_gap(old_var) {
+                    errors.push(SolStorageUpgradeError::Removed {
+                        name: old_var.name.clone(),
+                    });
+                }
+                continue;
+            };
+
+            if let Some((previous_index, previous_name)) = previous {
+                if index < previous_index {
+                    errors.push(SolStorageUpgradeError::Reordered {
+                        name: old_var.name.clone(),
+                        previous: previous_name.to_string(),
+                    });
+                }
+            }
+            previous = Some((index, &old_var.name));
+
+            if is_gap(old_var) && is_gap(new_var) {
+                let old_end = end_slot(old_var);
+                let new_end = end_slot(new_var);
+                if old_end != new_end {
+                    errors.push(SolStorageUpgradeError::GapMisconsumed {
+                        name: old_var.name.clone(),
+                        old_end,
+                        new_end,
+                    });
+                }
+                continue;
+            }
+
+            if (old_var.slot, old_var.offset) != (new_var.slot, new_var.offset) {
+                errors.push(SolStorageUpgradeError::Moved {
+                    name: old_var.name.clone(),
+                    old_slot: old_var.slot,
+                    old_offset: old_var.offset,
+                    new_slot: new_var.slot,
+                    new_offset: new_var.offset,
+                });
+            }
+
+            // A variable is allowed to grow: if it overlaps the next ones, they are reported as
+            // moved.
+            check_type(&old_var.name, &old_var.ty, &new_var.ty, true, &mut errors);
+        }
+
+        errors
+    }
+}
+
+fn same_variable(old: &SolStorageDynVariable, new: &SolStorageDynVariable) -> bool {
+    old.name == new.name && contract_name(&old.contract) == contract_name(&new.contract)
+}
+
+/// Name of a contract, without the source unit prefix used by `solc` (`path/File.sol:Name`).
+fn contract_name(contract: &str) -> &str {
+    contract.rsplit_once(':').map_or(contract, |(_, name)| name)
+}
+
+fn is_gap(variable: &SolStorageDynVariable) -> bool {
+    variable.name.starts_with(STORAGE_GAP_PREFIX)
+        && matches!(variable.ty, SolStorageDynType::FixedArray { .. })
+}
+
+/// First slot after the variable.
+fn end_slot(variable: &SolStorageDynVariable) -> U256 {
+    variable
+        .slot
+        .saturating_add(variable.ty.footprint().size().div_ceil(U256::from(32)))
+}
+
+/// Checks that a value of type `old` can be read as `new`. `can_grow` is set when nothing is
+/// stored right after the value, so that it can take more space.
+fn check_type(
+    path: &str,
+    old: &SolStorageDynType,
+    new: &SolStorageDynType,
+    can_grow: bool,
+    errors: &mut Vec<SolStorageUpgradeError>,
+) {
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (SolStorageDynType::Uint { .. }, SolStorageDynType::Uint { .. })
+        | (SolStorageDynType::Int { .. }, SolStorageDynType::Int { .. })
+        | (SolStorageDynType::FixedBytes { .. }, SolStorageDynType::FixedBytes { .. }) => {
+            errors.push(SolStorageUpgradeError::WidthChanged {
+                path: path.to_string(),
+                old: old.clone(),
+                new: new.clone(),
+            });
+        }
+        (
+            SolStorageDynType::Enum {
+                variants: old_variants,
+                ..
+            },
+            SolStorageDynType::Enum {
+                variants: new_variants,
+                ..
+            },
+        ) => {
+            // Variants can only be appended. Unknown variants are assumed to be compatible.
+            if !old_variants.is_empty()
+                && !new_variants.is_empty()
+                && !new_variants.starts_with(old_variants)
+            {
+                errors.push(SolStorageUpgradeError::TypeChanged {
+                    path: path.to_string(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+        (
+            SolStorageDynType::Struct {
+                fields: old_fields, ..
+            },
+            SolStorageDynType::Struct {
+                fields: new_fields, ..
+            },
+        ) => {
+            for (index, old_field) in old_fields.iter().enumerate() {
+                let Some(new_field) = new_fields.get(index) else {
+                    errors.push(SolStorageUpgradeError::FieldRemoved {
+                        path: path.to_string(),
+                        field: old_field.name.clone(),
+                    });
+                    continue;
+                };
+
+                if old_field.name != new_field.name {
+                    // The following fields are shifted, comparing them would only add noise.
+                    if new_fields[index..]
+                        .iter()
+                        .any(|field| field.name == old_field.name)
+                    {
+                        errors.push(SolStorageUpgradeError::FieldInserted {
+                            path: path.to_string(),
+                            field: new_field.name.clone(),
+                        });
+                    } else {
+                        errors.push(SolStorageUpgradeError::FieldRemoved {
+                            path: path.to_string(),
+                            field: old_field.name.clone(),
+                        });
+                    }
+                    return;
+                }
+
+                let is_last = index + 1 == old_fields.len();
+                check_type(
+                    &format!("{path}.{}", old_field.name),
+                    &old_field.ty,
+                    &new_field.ty,
+                    can_grow && is_last,
+                    errors,
+                );
+            }
+
+            check_size(path, old, new, can_grow, errors);
+        }
+        (
+            SolStorageDynType::FixedArray {
+                element: old_element,
+                ..
+            },
+            SolStorageDynType::FixedArray {
+                element: new_element,
+                ..
+            },
+        ) => {
+            check_type(
+                &format!("{path}[]"),
+                old_element,
+                new_element,
+                false,
+                errors,
+            );
+            check_size(path, old, new, can_grow, errors);
+        }
+        (
+            SolStorageDynType::Array {
+                element: old_element,
+            },
+            SolStorageDynType::Array {
+                element: new_element,
+            },
+        ) => {
+            check_type(
+                &format!("{path}[]"),
+                old_element,
+                new_element,
+                false,
+                errors,
+            );
+        }
+        (
+            SolStorageDynType::Mapping {
+                key: old_key,
+                value: old_value,
+            },
+            SolStorageDynType::Mapping {
+                key: new_key,
+                value: new_value,
+            },
+        ) => {
+            if old_key != new_key {
+                errors.push(SolStorageUpgradeError::MappingKeyChanged {
+                    path: path.to_string(),
+                    old: (**old_key).clone(),
+                    new: (**new_key).clone(),
+                });
+            }
+            // Mapping values are stored apart from each other, so they can always grow.
+            check_type(&format!("{path}[]"), old_value, new_value, true, errors);
+        }
+        _ => errors.push(SolStorageUpgradeError::TypeChanged {
+            path: path.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+/// Checks the size of a structure or a fixed-size array: it can never shrink, and can only grow
+/// when `can_grow` is set.
+fn check_size(
+    path: &str,
+    old: &SolStorageDynType,
+    new: &SolStorageDynType,
+    can_grow: bool,
+    errors: &mut Vec<SolStorageUpgradeError>,
+) {
+    let old_size = old.footprint().size();
+    let new_size = new.footprint().size();
+    if new_size < old_size || (new_size > old_size && !can_grow) {
+        errors.push(SolStorageUpgradeError::SizeChanged {
+            path: path.to_string(),
+            old: old_size,
+            new: new_size,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_storage_layouts;
+
+    fn upgrade_errors(old: &str, new: &str) -> Vec<SolStorageUpgradeError> {
+        let old = parse_storage_layouts(old).unwrap().pop().unwrap();
+        let new = parse_storage_layouts(new).unwrap().pop().unwrap();
+        old.upgrade_errors(&new)
+    }
+
+    const BASE: &str = r#"
+        contract Base {
+            struct Position {
+                uint128 amount;
+                uint64 since;
+            }
+
+            address owner;
+            uint128 fee;
+            mapping(address => Position) positions;
+            uint256[10] __gap;
+        }
+
+        contract Vault is Base {
+            uint256 total;
+        }
+    "#;
+
+    #[test]
+    fn test_upgrade_compatible() {
+        let new = r#"
+            contract Base {
+                struct Position {
+                    uint128 amount;
+                    uint64 since;
+                    bool locked;
+                    uint256 rewards;
+                }
+
+                address owner;
+                uint128 fee;
+                mapping(address => Position) positions;
+                uint256 cap;
+                address treasury;
+                uint256[8] __gap;
+            }
+
+            contract Vault is Base {
+                uint256 total;
+                uint256 extra;
+            }
+        "#;
+
+        assert_eq!(upgrade_errors(BASE, new), []);
+    }
+
+    #[test]
+    fn test_upgrade_incompatible() {
+        let new = r#"
+            contract Base {
+                struct Position {
+                    uint64 since;
+                    uint128 amount;
+                }
+
+                uint128 fee;
+                address owner;
+                mapping(uint256 => Position) positions;
+                uint256 cap;
+                uint256[10] __gap;
+            }
+
+            contract Vault is Base {
+                uint256 total;
+            }
+        "#;
+
+        let errors = upgrade_errors(BASE, new);
+        assert_eq!(
+            errors,
+            [
+                SolStorageUpgradeError::Moved {
+                    name: "owner".to_string(),
+                    old_slot: U256::from(0),
+                    old_offset: 0,
+                    new_slot: U256::from(1),
+                    new_offset: 0,
+                },
+                SolStorageUpgradeError::Reordered {
+                    name: "fee".to_string(),
+                    previous: "owner".to_string(),
+                },
+                SolStorageUpgradeError::Moved {
+                    name: "fee".to_string(),
+                    old_slot: U256::from(1),
+                    old_offset: 0,
+                    new_slot: U256::from(0),
+                    new_offset: 0,
+                },
+                SolStorageUpgradeError::MappingKeyChanged {
+                    path: "positions".to_string(),
+                    old: SolStorageDynType::Address,
+                    new: SolStorageDynType::Uint { bits: 256 },
+                },
+                SolStorageUpgradeError::FieldInserted {
+                    path: "positions[]".to_string(),
+                    field: "since".to_string(),
+                },
+                SolStorageUpgradeError::GapMisconsumed {
+                    name: "__gap".to_string(),
+                    old_end: U256::from(13),
+                    new_end: U256::from(14),
+                },
+                SolStorageUpgradeError::Moved {
+                    name: "total".to_string(),
+                    old_slot: U256::from(13),
+                    old_offset: 0,
+                    new_slot: U256::from(14),
+                    new_offset: 0,
+                },
+            ]
+        );
+    }
+}
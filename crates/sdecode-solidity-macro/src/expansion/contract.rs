@@ -1,15 +1,20 @@
-use proc_macro2::{Span, TokenStream};
-use quote::{ToTokens, quote};
-use syn_solidity::Spanned;
-
-use crate::{
-    get_sol_storage_type,
-    pp::{PPContract, PPVariableDef, UserDefinedItem},
-    scope::Scope,
-    types::get_default_rust_type,
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::{ToTokens, format_ident, quote};
+use syn::LitStr;
+use syn_solidity::{Spanned, Type};
+
+use sdecode_solidity_layout::{
+    PPContract, PPVariableDef, Scope, UserDefinedItem, sol_storage_footprint, to_snake_case,
+};
+
+use crate::types::{
+    get_bounded_sol_storage_type, get_default_rust_type, get_mapping_key_sol_storage_type,
 };
 
-use super::{EnumExpansion, StructureExpansion, UdtExpansion};
+use super::{
+    EnumExpansion, LayoutEntry, StructureExpansion, UdtExpansion, expand_b256,
+    expand_sol_storage_fields_impl, expand_sol_storage_layout_impl,
+};
 
 #[derive(Debug, Clone, Default)]
 pub struct ContractExpansion {
@@ -18,6 +23,10 @@ pub struct ContractExpansion {
     pub items: TokenStream,
     pub storage_structure_def: TokenStream,
     pub storage_decode_impl: TokenStream,
+    pub storage_encode_impl: TokenStream,
+    pub storage_diff_impl: TokenStream,
+    pub storage_layout_impl: TokenStream,
+    pub view_def: TokenStream,
 }
 
 impl ContractExpansion {
@@ -78,7 +87,12 @@ impl ContractExpansion {
             TokenStream::new()
         } else {
             let storage_structure_ident = contract.rust_path();
-            quote! { pub use #mod_name:: #storage_structure_ident; }
+            let view_ident = view_ident(contract);
+            quote! {
+                pub use #mod_name:: #storage_structure_ident;
+                #[allow(unused_imports)]
+                pub use #mod_name:: #view_ident;
+            }
         };
 
         Ok(Self {
@@ -87,6 +101,10 @@ impl ContractExpansion {
             items,
             storage_structure_def: expand_storage_structure_def(sc, contract, &storage_vars)?,
             storage_decode_impl: expand_storage_decode_impl(sc, contract, &storage_vars)?,
+            storage_encode_impl: expand_storage_encode_impl(sc, contract, &storage_vars)?,
+            storage_diff_impl: expand_storage_diff_impl(sc, contract, &storage_vars)?,
+            storage_layout_impl: expand_storage_layout_impl(sc, contract, &storage_vars)?,
+            view_def: expand_view_def(sc, contract, &storage_vars)?,
         })
     }
 
@@ -95,6 +113,10 @@ impl ContractExpansion {
         res.extend(self.items);
         res.extend(self.storage_structure_def);
         res.extend(self.storage_decode_impl);
+        res.extend(self.storage_encode_impl);
+        res.extend(self.storage_diff_impl);
+        res.extend(self.storage_layout_impl);
+        res.extend(self.view_def);
 
         let mod_name = self.mod_name;
         let pub_use = self.pub_use;
@@ -173,7 +195,8 @@ fn expand_storage_decode_impl(
     let mut struct_creation = TokenStream::new();
 
     for (contract, var) in vars {
-        if let Some(slot) = &var.attrs.slot {
+        if let Some(slot) = var.attrs.slot_value()? {
+            let slot = expand_b256(sc, slot);
             fields_decode.extend(quote! {
                 let remaining = #sdecode_core::StorageReader::consume_remaining(&mut storage_reader);
                 if remaining.is_not_zero() {
@@ -184,7 +207,10 @@ fn expand_storage_decode_impl(
                     );
                 }
                 ::core::mem::drop(storage_reader);
-                let mut storage_reader = layout.reader_at(#alloy_primitives ::b256!(#slot));
+                let mut storage_reader = layout.reader_at_with(
+                    #slot,
+                    ::core::clone::Clone::clone(&diagnostics),
+                );
             });
         }
 
@@ -196,28 +222,29 @@ fn expand_storage_decode_impl(
             get_default_rust_type(&sc.in_contract(contract), &var.raw.ty)?
         };
 
-        let field_sol_ty = get_sol_storage_type(&sc.in_contract(contract), &var.raw.ty)?;
+        let field_sol_ty = get_bounded_sol_storage_type(
+            &sc.in_contract(contract),
+            &var.raw.ty,
+            var.attrs.max_len_value()?,
+        )?;
+        let field_name_lit = LitStr::new(&var.raw.name.to_string(), Span::call_site());
 
         fields_decode.extend(quote! {
+            #sdecode_core::StorageReader::enter(&mut storage_reader, || {
+                #sdecode_core::LayoutPathSegment::Field(#field_name_lit.into())
+            });
             let #field_name = <
                 #field_ty
-                as ::sdecode_solidity ::SolStorageValue<#field_sol_ty>
+                as #sdecode_solidity::SolStorageValue<#field_sol_ty>
             >::decode_storage(&mut storage_reader)
-                .map_err(#sdecode_core ::StorageError::Layout)?;
+                .map_err(|err| #sdecode_core::StorageError::Layout(err.at_field(#field_name_lit)))?;
+            #sdecode_core::StorageReader::leave(&mut storage_reader);
         });
 
         struct_creation.extend(quote! { #field_name, });
     }
 
-    let language = if let Some(language) = &contract.attrs.language {
-        match language.value().to_lowercase().as_ref() {
-            "solidity" => quote! { SOLIDITY },
-            "vyper" => quote! { VYPER },
-            _ => return Err(syn::Error::new_spanned(language, "unknown language")),
-        }
-    } else {
-        quote! { SOLIDITY }
-    };
+    let derivations = expand_derivations(sc, contract, vars)?;
 
     let storage_structure_path = contract.rust_path();
 
@@ -232,28 +259,467 @@ fn expand_storage_decode_impl(
         impl #sdecode_core ::StorageDecode for #storage_structure_path {
             type LayoutError = #sdecode_solidity::SolLayoutError;
 
-            fn sdecode_mut<P, E>(
+            fn sdecode_at_with_mode_mut<P, E>(
+                base_slot: #alloy_primitives::B256,
                 preimages_provider: &mut P,
                 storage_entries: E,
-            ) -> ::core::result::Result<Self, #sdecode_core ::StorageError<P::Error, Self::LayoutError>>
+                mode: #sdecode_core ::DecodeMode,
+            ) -> ::core::result::Result<
+                #sdecode_core ::StorageReport<Self>,
+                #sdecode_core ::StorageError<P::Error, Self::LayoutError>,
+            >
             where
                 P: #sdecode_preimages ::PreimagesProviderMut,
                 E: ::core::iter::IntoIterator<Item = (#alloy_primitives::B256, #alloy_primitives::B256)>,
             {
-                let side = #sdecode_core::MappingKeySide:: #language;
-
-                let mut layout = #sdecode_core ::Storage::decode_mut(preimages_provider, storage_entries, side)
+                let derivations = #derivations;
+                let diagnostics = mode.is_lenient().then(|| {
+                    #sdecode_core ::StorageDiagnostics::with_derivations(
+                        ::core::clone::Clone::clone(&derivations),
+                    )
+                });
+
+                let mut layout = #sdecode_core ::Storage::decode_mut(preimages_provider, storage_entries, &derivations)
                     .map_err(#sdecode_core ::StorageError::Provider)?;
-                let mut storage_reader = layout.reader_at(#alloy_primitives::B256::ZERO);
+                let mut storage_reader = layout.reader_at_with(
+                    base_slot,
+                    ::core::clone::Clone::clone(&diagnostics),
+                );
 
                 #fields_decode
 
                 ::core::mem::drop(storage_reader);
 
-                ::core::result::Result::Ok(Self { #struct_creation })
+                ::core::result::Result::Ok(#sdecode_core ::StorageReport::new(
+                    Self { #struct_creation },
+                    layout,
+                    diagnostics,
+                ))
+            }
+
+            fn sdecode_with_mode_mut<P, E>(
+                preimages_provider: &mut P,
+                storage_entries: E,
+                mode: #sdecode_core ::DecodeMode,
+            ) -> ::core::result::Result<
+                #sdecode_core ::StorageReport<Self>,
+                #sdecode_core ::StorageError<P::Error, Self::LayoutError>,
+            >
+            where
+                P: #sdecode_preimages ::PreimagesProviderMut,
+                E: ::core::iter::IntoIterator<Item = (#alloy_primitives::B256, #alloy_primitives::B256)>,
+            {
+                Self::sdecode_at_with_mode_mut(
+                    <Self as #sdecode_solidity::SolStorageRoot>::BASE_SLOT,
+                    preimages_provider,
+                    storage_entries,
+                    mode,
+                )
             }
         }
     };
 
     Ok(storage_decode_impl)
 }
+
+fn expand_storage_encode_impl(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+    vars: &Vec<(&PPContract<'_>, &PPVariableDef<'_>)>,
+) -> syn::Result<TokenStream> {
+    if contract.raw.is_interface() || contract.raw.is_library() {
+        return Ok(TokenStream::new());
+    }
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let sdecode_preimages = sc.file.sdecode_preimages();
+    let alloy_primitives = sc.file.alloy_primitives();
+
+    let mut fields_encode = TokenStream::new();
+
+    for (contract, var) in vars {
+        if let Some(slot) = var.attrs.slot_value()? {
+            let slot = expand_b256(sc, slot);
+            fields_encode.extend(quote! {
+                layout.insert_structure(storage_slot, storage_writer.into_structure());
+                let storage_slot = #slot;
+                let mut storage_writer = #sdecode_core::StorageStructureWriter::new();
+            });
+        }
+
+        let field_name = var.raw.name.0.clone().with_span(Span::call_site());
+
+        let field_ty = if let Some(overriden_type) = &var.attrs.typ {
+            quote! { #overriden_type }
+        } else {
+            get_default_rust_type(&sc.in_contract(contract), &var.raw.ty)?
+        };
+
+        let field_sol_ty = get_bounded_sol_storage_type(
+            &sc.in_contract(contract),
+            &var.raw.ty,
+            var.attrs.max_len_value()?,
+        )?;
+        let field_name_lit = LitStr::new(&var.raw.name.to_string(), Span::call_site());
+
+        fields_encode.extend(quote! {
+            <
+                #field_ty
+                as #sdecode_solidity::SolStorageEncode<#field_sol_ty>
+            >::encode_storage(&self.#field_name, &mut storage_writer)
+                .map_err(|err| err.at_field(#field_name_lit))?;
+        });
+    }
+
+    let derivations = expand_derivations(sc, contract, vars)?;
+
+    let storage_structure_path = contract.rust_path();
+
+    let storage_encode_impl = quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_core ::StorageEncode for #storage_structure_path {
+            type EncodeError = #sdecode_solidity::SolLayoutError;
+
+            fn sencode_at_mut(
+                &self,
+                base_slot: #alloy_primitives::B256,
+                preimages: &mut #sdecode_preimages ::MemoryPreimagesProvider,
+                storage_entries: &mut #sdecode_core ::StorageEntries,
+            ) -> Result<(), Self::EncodeError> {
+                let derivations = #derivations;
+
+                let mut layout = #sdecode_core ::Storage::default();
+                let storage_slot = base_slot;
+                let mut storage_writer = #sdecode_core::StorageStructureWriter::new();
+
+                #fields_encode
+
+                layout.insert_structure(storage_slot, storage_writer.into_structure());
+                layout.encode_into(&derivations, preimages, storage_entries);
+                Ok(())
+            }
+
+            fn sencode_mut(
+                &self,
+                preimages: &mut #sdecode_preimages ::MemoryPreimagesProvider,
+                storage_entries: &mut #sdecode_core ::StorageEntries,
+            ) -> Result<(), Self::EncodeError> {
+                #sdecode_core ::StorageEncode::sencode_at_mut(
+                    self,
+                    <Self as #sdecode_solidity::SolStorageRoot>::BASE_SLOT,
+                    preimages,
+                    storage_entries,
+                )
+            }
+        }
+    };
+
+    Ok(storage_encode_impl)
+}
+
+fn expand_storage_diff_impl(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+    vars: &Vec<(&PPContract<'_>, &PPVariableDef<'_>)>,
+) -> syn::Result<TokenStream> {
+    if contract.raw.is_interface() || contract.raw.is_library() {
+        return Ok(TokenStream::new());
+    }
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+
+    let mut fields_diff = TokenStream::new();
+
+    for (contract, var) in vars {
+        let field_name = var.raw.name.0.clone().with_span(Span::call_site());
+
+        let field_ty = if let Some(overriden_type) = &var.attrs.typ {
+            quote! { #overriden_type }
+        } else {
+            get_default_rust_type(&sc.in_contract(contract), &var.raw.ty)?
+        };
+
+        let field_sol_ty = get_bounded_sol_storage_type(
+            &sc.in_contract(contract),
+            &var.raw.ty,
+            var.attrs.max_len_value()?,
+        )?;
+        let field_name_lit = LitStr::new(&var.raw.name.to_string(), Span::call_site());
+
+        fields_diff.extend(quote! {
+            #sdecode_solidity::SolStorageDynValue::diff_into(
+                &#sdecode_solidity::SolStorageDynValue::from_value::<#field_sol_ty, #field_ty>(
+                    &self.#field_name,
+                )
+                .map_err(|err| err.at_field(#field_name_lit))?,
+                &#sdecode_solidity::SolStorageDynValue::from_value::<#field_sol_ty, #field_ty>(
+                    &other.#field_name,
+                )
+                .map_err(|err| err.at_field(#field_name_lit))?,
+                &mut #sdecode_core::LayoutPath(::std::vec![
+                    #sdecode_core::LayoutPathSegment::Field(#field_name_lit.into()),
+                ]),
+                &mut changes,
+            );
+        });
+    }
+
+    let storage_structure_path = contract.rust_path();
+
+    let storage_diff_impl = quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_solidity::SolStorageDiff for #storage_structure_path {
+            fn sdiff(
+                &self,
+                other: &Self,
+            ) -> ::core::result::Result<
+                ::std::vec::Vec<#sdecode_solidity::SolStorageChange>,
+                #sdecode_solidity::SolLayoutError,
+            > {
+                let mut changes = ::std::vec::Vec::new();
+
+                #fields_diff
+
+                ::core::result::Result::Ok(changes)
+            }
+        }
+    };
+
+    Ok(storage_diff_impl)
+}
+
+fn expand_storage_layout_impl(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+    vars: &Vec<(&PPContract<'_>, &PPVariableDef<'_>)>,
+) -> syn::Result<TokenStream> {
+    if contract.raw.is_interface() || contract.raw.is_library() {
+        return Ok(TokenStream::new());
+    }
+
+    let mut entries = Vec::new();
+    for (contract, var) in vars {
+        let sc = sc.in_contract(contract);
+        let max_len = var.attrs.max_len_value()?;
+        entries.push(LayoutEntry {
+            label: var.raw.name.to_string(),
+            sol_ty: get_bounded_sol_storage_type(&sc, &var.raw.ty, max_len)?,
+            contract: contract.raw.name.to_string(),
+            footprint: sol_storage_footprint(&sc, &var.raw.ty, max_len)?,
+            slot: var.attrs.slot_value()?.map(Into::into),
+            derivation: var.attrs.derivation.clone(),
+        });
+    }
+
+    let storage_structure_path = contract.rust_path();
+    let storage_structure_ident = if let Some(remote) = &contract.attrs.remote {
+        remote.segments.last().unwrap().ident.clone()
+    } else if let Some(rename) = &contract.attrs.rename {
+        rename.clone()
+    } else {
+        format_ident!("{}Storage", contract.raw.name)
+    };
+    let fields_ident = format_ident!("{}Fields", storage_structure_ident);
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let alloy_primitives = sc.file.alloy_primitives();
+    let language = expand_language(contract)?;
+
+    let layout_at = contract.attrs.layout_at_value()?;
+    let base_slot = if let Some(slot) = layout_at {
+        let slot = expand_b256(sc, slot);
+        quote! {
+            const BASE_SLOT: #alloy_primitives::B256 = #slot;
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    let mut res = expand_sol_storage_layout_impl(
+        sc,
+        &storage_structure_path,
+        &contract.raw.name.to_string(),
+        &entries,
+        layout_at.unwrap_or_default().into(),
+    );
+    res.extend(expand_sol_storage_fields_impl(
+        sc,
+        &storage_structure_path,
+        &fields_ident,
+        &entries,
+    ));
+    res.extend(quote! {
+        #[automatically_derived]
+        impl #sdecode_solidity::SolStorageRoot for #storage_structure_path {
+            const MAPPING_KEY_SIDE: #sdecode_core::MappingKeySide =
+                #sdecode_core::MappingKeySide::#language;
+
+            #base_slot
+        }
+    });
+    Ok(res)
+}
+
+fn view_ident(contract: &PPContract<'_>) -> Ident {
+    format_ident!("{}View", contract.raw.name)
+}
+
+/// Expands the view, reading each state variable, or each entry of a mapping, on demand.
+fn expand_view_def(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+    vars: &Vec<(&PPContract<'_>, &PPVariableDef<'_>)>,
+) -> syn::Result<TokenStream> {
+    if contract.raw.is_interface() || contract.raw.is_library() {
+        return Ok(TokenStream::new());
+    }
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+
+    let storage_structure_path = contract.rust_path();
+    let view_ident = view_ident(contract);
+    let view_doc = format!(
+        " Reads the state variables of `{}` without decoding the whole storage.",
+        contract.raw.name
+    );
+
+    let mut view_fns = TokenStream::new();
+
+    for (contract, var) in vars {
+        let sc = sc.in_contract(contract);
+        let field_name = var.raw.name.0.clone().with_span(Span::call_site());
+        let fn_name = format_ident!("{}", to_snake_case(&var.raw.name.to_string()));
+
+        // Each level of mapping takes a key, and the value is read at the end of the chain.
+        let mut ty = &var.raw.ty;
+        let mut keys = Vec::new();
+        while let Type::Mapping(mapping) = ty {
+            keys.push(get_mapping_key_sol_storage_type(&sc, &mapping.key)?);
+            ty = &mapping.value;
+        }
+
+        // An overriden mapping type gives the type of its values, one level per key.
+        let value_ty = match &var.attrs.typ {
+            Some(overriden_type) => keys.iter().fold(quote! { #overriden_type }, |ty, _| {
+                quote! { <#ty as #sdecode_solidity::helpers::SolMappingEntries>::Value }
+            }),
+            None => get_default_rust_type(&sc, ty)?,
+        };
+
+        let (key_args, key_generics): (Vec<_>, Vec<_>) = if keys.len() == 1 {
+            (vec![format_ident!("key")], vec![format_ident!("K")])
+        } else {
+            (0..keys.len())
+                .map(|i| (format_ident!("key{i}"), format_ident!("K{i}")))
+                .unzip()
+        };
+
+        view_fns.extend(quote! {
+            pub fn #fn_name<B, #(#key_generics),*>(
+                backend: &mut B,
+                #(#key_args: #key_generics),*
+            ) -> ::core::result::Result<
+                #value_ty,
+                #sdecode_core::StorageError<B::Error, #sdecode_solidity::SolLayoutError>,
+            >
+            where
+                B: #sdecode_core::SlotBackend,
+                #(#key_generics: #sdecode_solidity::SolMappingKeyValue<#keys>,)*
+            {
+                <#storage_structure_path as #sdecode_solidity::SolStorageRoot>::storage_paths()
+                    .#field_name
+                    #(.key(#key_args).map_err(#sdecode_core::StorageError::Layout)?)*
+                    .load(backend)
+            }
+        });
+    }
+
+    Ok(quote! {
+        #[doc = #view_doc]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct #view_ident;
+
+        #[automatically_derived]
+        #[allow(non_snake_case, clippy::style)]
+        impl #view_ident {
+            #view_fns
+        }
+    })
+}
+
+/// Slot derivations of the layout at `base_slot`, with the schemes given by the `derivation`
+/// attributes of the mappings, as attached to their paths.
+fn expand_derivations(
+    sc: &Scope<'_>,
+    contract: &PPContract<'_>,
+    vars: &Vec<(&PPContract<'_>, &PPVariableDef<'_>)>,
+) -> syn::Result<TokenStream> {
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let language = expand_language(contract)?;
+
+    let mut attach_derivations = TokenStream::new();
+    for (_, var) in vars {
+        let Some(derivation) = &var.attrs.derivation else {
+            continue;
+        };
+        if !matches!(var.raw.ty, Type::Mapping(_)) {
+            return Err(syn::Error::new_spanned(
+                derivation,
+                "`derivation` only applies to mappings",
+            ));
+        }
+
+        let field_name = var.raw.name.0.clone().with_span(Span::call_site());
+        attach_derivations.extend(quote! {
+            derivations.extend(
+                paths
+                    .#field_name
+                    .derivation()
+                    .map(|derivation| (paths.#field_name.slot(), derivation)),
+            );
+        });
+    }
+
+    let derivations =
+        quote! { #sdecode_core::SlotDerivations::new(#sdecode_core::MappingKeySide:: #language) };
+    if attach_derivations.is_empty() {
+        return Ok(derivations);
+    }
+
+    Ok(quote! {
+        {
+            let paths = <Self as #sdecode_solidity::SolStorageRoot>::storage_paths_at(base_slot);
+            let mut derivations = #derivations;
+            #attach_derivations
+            derivations
+        }
+    })
+}
+
+pub(super) fn expand_language(contract: &PPContract<'_>) -> syn::Result<TokenStream> {
+    let Some(language) = &contract.attrs.language else {
+        return Ok(quote! { SOLIDITY });
+    };
+    match language.value().to_lowercase().as_ref() {
+        "solidity" => Ok(quote! { SOLIDITY }),
+        "vyper" => Ok(quote! { VYPER }),
+        _ => Err(syn::Error::new_spanned(language, "unknown language")),
+    }
+}
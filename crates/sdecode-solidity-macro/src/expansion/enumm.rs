@@ -3,14 +3,16 @@ use quote::quote;
 use syn::LitStr;
 use syn_solidity::Spanned;
 
-use crate::{attribute::attrs_tokens, pp::PPEnum, scope::Scope};
+use sdecode_solidity_layout::{PPEnum, Scope, attrs_tokens};
 
 #[derive(Debug, Clone, Default)]
 pub struct EnumExpansion {
     pub enum_def: TokenStream,
     pub try_from_u8_impl: TokenStream,
+    pub into_u8_impl: TokenStream,
     pub sol_storage_type_impl: TokenStream,
     pub sol_storage_value_impl: TokenStream,
+    pub sol_storage_encode_impl: TokenStream,
 }
 
 impl EnumExpansion {
@@ -18,8 +20,10 @@ impl EnumExpansion {
         Ok(Self {
             enum_def: expand_enum_def(sc, enumm)?,
             try_from_u8_impl: expand_try_from_u8_impl(sc, enumm)?,
+            into_u8_impl: expand_into_u8_impl(sc, enumm)?,
             sol_storage_type_impl: expand_sol_storage_type_impl(sc, enumm)?,
             sol_storage_value_impl: expand_sol_storage_value_impl(sc, enumm)?,
+            sol_storage_encode_impl: expand_sol_storage_encode_impl(sc, enumm)?,
         })
     }
 
@@ -27,8 +31,10 @@ impl EnumExpansion {
         let mut res = TokenStream::new();
         res.extend(self.enum_def);
         res.extend(self.try_from_u8_impl);
+        res.extend(self.into_u8_impl);
         res.extend(self.sol_storage_type_impl);
         res.extend(self.sol_storage_value_impl);
+        res.extend(self.sol_storage_encode_impl);
         res
     }
 }
@@ -110,6 +116,48 @@ fn expand_try_from_u8_impl(_sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Result<T
     Ok(try_from_u8_impl)
 }
 
+fn expand_into_u8_impl(_sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Result<TokenStream> {
+    if enumm.attrs.remote.is_some() {
+        return Ok(TokenStream::default());
+    }
+
+    let enum_ident = enumm.rust_path();
+
+    let mut into_u8_match = TokenStream::new();
+
+    for (i, variant) in enumm.raw.variants.iter().enumerate() {
+        let Ok(i) = u8::try_from(i) else {
+            return Err(syn::Error::new(variant.span(), "too many variants"));
+        };
+
+        let variant_name = &variant.ident;
+
+        let index_lit = Literal::u8_unsuffixed(i);
+        into_u8_match.extend(quote! {
+            #enum_ident::#variant_name => #index_lit,
+        });
+    }
+
+    let into_u8_impl = quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl ::core::convert::From<&#enum_ident> for u8 {
+            fn from(value: &#enum_ident) -> Self {
+                match value {
+                    #into_u8_match
+                }
+            }
+        }
+    };
+
+    Ok(into_u8_impl)
+}
+
 fn expand_sol_storage_type_impl(sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Result<TokenStream> {
     let enum_path = enumm.rust_path();
     let enum_ident = enumm.rust_ident();
@@ -117,6 +165,12 @@ fn expand_sol_storage_type_impl(sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Resu
 
     let sdecode_solidity = sc.file.sdecode_solidity();
 
+    let mut variants = TokenStream::new();
+    for variant in &enumm.raw.variants {
+        let variant_lit = LitStr::new(&variant.ident.to_string(), variant.ident.span());
+        variants.extend(quote! { ::std::string::String::from(#variant_lit), });
+    }
+
     let sol_storage_type_impl = quote! {
         #[automatically_derived]
         #[allow(
@@ -127,6 +181,17 @@ fn expand_sol_storage_type_impl(sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Resu
         )]
         impl #sdecode_solidity ::SolStorageType for #enum_path {
             const SOL_STORAGE_NAME: &str = #enum_ident_lit;
+
+            fn sol_storage_dyn_type() -> #sdecode_solidity::SolStorageDynType {
+                #sdecode_solidity::SolStorageDynType::Enum {
+                    name: ::std::string::String::from(#enum_ident_lit),
+                    variants: ::std::vec![#variants],
+                }
+            }
+
+            fn sol_storage_footprint() -> #sdecode_solidity::SolStorageFootprint {
+                #sdecode_solidity::SolStorageFootprint::Packed(1)
+            }
         }
     };
 
@@ -158,3 +223,27 @@ fn expand_sol_storage_value_impl(sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Res
 
     Ok(sol_storage_value_impl)
 }
+
+fn expand_sol_storage_encode_impl(sc: &Scope<'_>, enumm: &PPEnum<'_>) -> syn::Result<TokenStream> {
+    let enum_ident = enumm.rust_ident();
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let data_types = sc.file.sdecode_solidity_data_types();
+
+    let sol_storage_encode_impl = quote! {
+        #[automatically_derived]
+        impl #sdecode_solidity ::SolStorageEncode<Self> for #enum_ident {
+            fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> ::core::result::Result<(), #sdecode_solidity::SolLayoutError>
+            where
+                Writer: #sdecode_core::StorageWriter,
+            {
+                <
+                    u8 as #sdecode_solidity::SolStorageEncode<#data_types::Uint<8>>
+                >::encode_storage(&u8::from(self), storage_writer)
+            }
+        }
+    };
+
+    Ok(sol_storage_encode_impl)
+}
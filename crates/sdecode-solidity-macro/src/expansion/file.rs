@@ -1,9 +1,6 @@
 use proc_macro2::TokenStream;
 
-use crate::{
-    pp::{PPFile, UserDefinedItem},
-    scope::Scope,
-};
+use sdecode_solidity_layout::{PPFile, Scope, UserDefinedItem};
 
 use super::{ContractExpansion, EnumExpansion, StructureExpansion, UdtExpansion};
 
@@ -0,0 +1,223 @@
+use alloy_primitives::{B256, U256, hex};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::{Expr, Ident, LitStr};
+
+use sdecode_solidity_layout::{Scope, SlotAllocator, SolStorageFootprint};
+
+/// A state variable or a structure field, to be laid out in storage.
+pub struct LayoutEntry {
+    pub label: String,
+    /// Solidity storage type, as returned by `get_sol_storage_type`.
+    pub sol_ty: TokenStream,
+    pub contract: String,
+    pub footprint: SolStorageFootprint,
+    /// Slot given by the `slot` attribute, if any.
+    pub slot: Option<U256>,
+    /// Scheme given by the `derivation` attribute of a mapping, if any.
+    pub derivation: Option<Expr>,
+}
+
+struct EntryLocation {
+    slot: U256,
+    offset: usize,
+    /// Whether `slot` is absolute, instead of relative to the first slot of the layout.
+    absolute: bool,
+}
+
+fn allocate(entries: &[LayoutEntry], base_slot: U256) -> Vec<EntryLocation> {
+    let mut allocator = SlotAllocator::new(base_slot);
+    let mut absolute = false;
+
+    entries
+        .iter()
+        .map(|entry| {
+            if let Some(slot) = entry.slot {
+                allocator = SlotAllocator::new(slot);
+                absolute = true;
+            }
+            let (slot, offset) = allocator.allocate(entry.footprint);
+            EntryLocation {
+                slot,
+                offset,
+                absolute,
+            }
+        })
+        .collect()
+}
+
+pub fn expand_u256(sc: &Scope<'_>, value: U256) -> TokenStream {
+    let alloy_primitives = sc.file.alloy_primitives();
+    let limbs = value.as_limbs().map(Literal::u64_suffixed);
+    quote! { #alloy_primitives::U256::from_limbs([#(#limbs),*]) }
+}
+
+pub fn expand_b256(sc: &Scope<'_>, value: B256) -> TokenStream {
+    let alloy_primitives = sc.file.alloy_primitives();
+    let value = LitStr::new(&hex::encode_prefixed(value), Span::call_site());
+    quote! { #alloy_primitives::b256!(#value) }
+}
+
+pub fn expand_footprint(sc: &Scope<'_>, footprint: SolStorageFootprint) -> TokenStream {
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    match footprint {
+        SolStorageFootprint::Packed(size) => {
+            quote! { #sdecode_solidity::SolStorageFootprint::Packed(#size) }
+        }
+        SolStorageFootprint::Slots(slots) => {
+            let slots = expand_u256(sc, slots);
+            quote! { #sdecode_solidity::SolStorageFootprint::Slots(#slots) }
+        }
+    }
+}
+
+/// Expands the `SolStorageLayout` impl, with the entries laid out from `base_slot`.
+pub fn expand_sol_storage_layout_impl(
+    sc: &Scope<'_>,
+    path: &TokenStream,
+    name: &str,
+    entries: &[LayoutEntry],
+    base_slot: U256,
+) -> TokenStream {
+    let sdecode_solidity = sc.file.sdecode_solidity();
+
+    let mut layout_entries = TokenStream::new();
+    let mut dyn_variables = TokenStream::new();
+
+    for (entry, location) in entries.iter().zip(allocate(entries, base_slot)) {
+        let LayoutEntry {
+            label,
+            sol_ty,
+            contract,
+            ..
+        } = entry;
+        let offset = location.offset;
+        let slot = expand_u256(sc, location.slot);
+        let size = expand_u256(sc, entry.footprint.size());
+
+        dyn_variables.extend(quote! {
+            #sdecode_solidity::SolStorageDynVariable {
+                name: ::std::string::String::from(#label),
+                contract: ::std::string::String::from(#contract),
+                slot: #slot,
+                offset: #offset,
+                ty: <#sol_ty as #sdecode_solidity::SolStorageType>::sol_storage_dyn_type(),
+            },
+        });
+
+        layout_entries.extend(quote! {
+            #sdecode_solidity::SolStorageLayoutEntry {
+                label: #label,
+                ty: <#sol_ty as #sdecode_solidity::SolStorageType>::SOL_STORAGE_NAME,
+                contract: #contract,
+                slot: #slot,
+                offset: #offset,
+                size: #size,
+            },
+        });
+    }
+
+    quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_solidity::SolStorageLayout for #path {
+            const STORAGE_LAYOUT: &'static [#sdecode_solidity::SolStorageLayoutEntry] = &[
+                #layout_entries
+            ];
+
+            fn sol_storage_dyn_layout() -> #sdecode_solidity::SolStorageDynLayout {
+                #sdecode_solidity::SolStorageDynLayout {
+                    name: ::std::string::String::from(#name),
+                    variables: ::std::vec![#dyn_variables],
+                }
+            }
+        }
+    }
+}
+
+/// Expands the structure holding the paths of the fields, and the `SolStorageFields` impl.
+pub fn expand_sol_storage_fields_impl(
+    sc: &Scope<'_>,
+    path: &TokenStream,
+    fields_ident: &Ident,
+    entries: &[LayoutEntry],
+) -> TokenStream {
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let alloy_primitives = sc.file.alloy_primitives();
+
+    let mut fields_def = TokenStream::new();
+    let mut fields_init = TokenStream::new();
+
+    for (entry, location) in entries.iter().zip(allocate(entries, U256::ZERO)) {
+        let field_name = Ident::new(&entry.label, Span::call_site());
+        let sol_ty = &entry.sol_ty;
+        let offset = location.offset;
+        let slot = expand_u256(sc, location.slot);
+
+        fields_def.extend(quote! {
+            #[allow(missing_docs)]
+            pub #field_name: #sdecode_solidity::SolStoragePath<#sol_ty>,
+        });
+
+        let field_path = if location.absolute {
+            quote! {
+                #sdecode_solidity::SolStoragePath::new(
+                    #alloy_primitives::B256::from(#slot),
+                    #offset,
+                    path.side(),
+                )
+            }
+        } else {
+            quote! { path.child(#slot, #offset) }
+        };
+
+        // The scheme is evaluated once, and shared by all the paths of the mapping.
+        let field_path = if let Some(derivation) = &entry.derivation {
+            quote! {
+                #field_path.with_derivation({
+                    static DERIVATION: ::std::sync::LazyLock<
+                        ::std::boxed::Box<
+                            dyn #sdecode_core::MappingSlotDerivation
+                                + ::core::marker::Send
+                                + ::core::marker::Sync,
+                        >,
+                    > = ::std::sync::LazyLock::new(|| ::std::boxed::Box::new(#derivation));
+                    &**DERIVATION
+                })
+            }
+        } else {
+            field_path
+        };
+        fields_init.extend(quote! { #field_name: #field_path, });
+    }
+
+    quote! {
+        /// Storage paths of the fields.
+        #[allow(non_camel_case_types, non_snake_case, clippy::pub_underscore_fields)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct #fields_ident {
+            #fields_def
+        }
+
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_solidity::SolStorageFields for #path {
+            type Fields = #fields_ident;
+
+            fn fields(path: &#sdecode_solidity::SolStoragePath<Self>) -> Self::Fields {
+                #fields_ident { #fields_init }
+            }
+        }
+    }
+}
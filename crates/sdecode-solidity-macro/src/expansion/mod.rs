@@ -1,5 +1,6 @@
 mod contract;
 pub use contract::ContractExpansion;
+use contract::expand_language;
 
 mod enumm;
 pub use enumm::EnumExpansion;
@@ -7,6 +8,12 @@ pub use enumm::EnumExpansion;
 mod file;
 pub use file::FileExpansion;
 
+mod layout;
+use layout::{
+    LayoutEntry, expand_b256, expand_footprint, expand_sol_storage_fields_impl,
+    expand_sol_storage_layout_impl,
+};
+
 mod structure;
 pub use structure::StructureExpansion;
 
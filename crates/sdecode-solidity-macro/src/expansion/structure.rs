@@ -1,11 +1,18 @@
+use alloy_primitives::U256;
 use proc_macro2::{Span, TokenStream};
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::LitStr;
-use syn_solidity::Spanned;
+use syn_solidity::{Spanned, VariableDeclaration};
 
-use crate::{
-    attribute::StructureFieldAttrs, get_sol_storage_type, pp::PPStruct, scope::Scope,
-    types::get_default_rust_type,
+use sdecode_solidity_layout::{
+    PPStruct, Scope, SolStorageFootprint, StructureFieldAttrs, structure_fields_footprints,
+};
+
+use crate::types::{get_bounded_sol_storage_type, get_default_rust_type};
+
+use super::{
+    LayoutEntry, expand_b256, expand_footprint, expand_language, expand_sol_storage_fields_impl,
+    expand_sol_storage_layout_impl,
 };
 
 #[derive(Debug, Clone, Default)]
@@ -13,6 +20,9 @@ pub struct StructureExpansion {
     pub structure_def: TokenStream,
     pub sol_storage_type_impl: TokenStream,
     pub sol_storage_value_impl: TokenStream,
+    pub sol_storage_encode_impl: TokenStream,
+    pub sol_storage_layout_impl: TokenStream,
+    pub root_impl: TokenStream,
 }
 
 impl StructureExpansion {
@@ -21,6 +31,9 @@ impl StructureExpansion {
             structure_def: expand_structure_def(sc, structure)?,
             sol_storage_type_impl: expand_sol_storage_type_impl(sc, structure)?,
             sol_storage_value_impl: expand_sol_storage_value_impl(sc, structure)?,
+            sol_storage_encode_impl: expand_sol_storage_encode_impl(sc, structure)?,
+            sol_storage_layout_impl: expand_sol_storage_layout_impl_for_structure(sc, structure)?,
+            root_impl: expand_root_impl(sc, structure)?,
         })
     }
 
@@ -29,10 +42,19 @@ impl StructureExpansion {
         res.extend(self.structure_def);
         res.extend(self.sol_storage_type_impl);
         res.extend(self.sol_storage_value_impl);
+        res.extend(self.sol_storage_encode_impl);
+        res.extend(self.sol_storage_layout_impl);
+        res.extend(self.root_impl);
         res
     }
 }
 
+/// Solidity storage type of a structure field, bounded by its `max_len` attribute.
+fn field_sol_storage_type(sc: &Scope<'_>, field: &VariableDeclaration) -> syn::Result<TokenStream> {
+    let (attrs, _) = StructureFieldAttrs::parse(&field.attrs)?;
+    get_bounded_sol_storage_type(sc, &field.ty, attrs.max_len_value()?)
+}
+
 fn expand_structure_def(sc: &Scope<'_>, structure: &PPStruct<'_>) -> syn::Result<TokenStream> {
     if structure.attrs.remote.is_some() {
         return Ok(TokenStream::new());
@@ -87,6 +109,36 @@ fn expand_sol_storage_type_impl(
 
     let sdecode_solidity = sc.file.sdecode_solidity();
 
+    let sdecode_core = sc.file.sdecode_core();
+    let alloy_primitives = sc.file.alloy_primitives();
+
+    let mut dyn_fields = TokenStream::new();
+    let mut load_fields = TokenStream::new();
+    for field in &structure.raw.fields {
+        let Some(field_name) = &field.name else {
+            return Err(syn::Error::new(
+                field.span(),
+                "unnamed fields are not supported",
+            ));
+        };
+        let field_name_lit = LitStr::new(&field_name.to_string(), field_name.span());
+
+        let sol_storage_typ = field_sol_storage_type(sc, field)?;
+
+        dyn_fields.extend(quote! {
+            #sdecode_solidity::SolStorageDynField {
+                name: ::std::string::String::from(#field_name_lit),
+                ty: <#sol_storage_typ as #sdecode_solidity::SolStorageType>::sol_storage_dyn_type(),
+            },
+        });
+        load_fields.extend(quote! {
+            loader.field::<#sol_storage_typ>()?;
+        });
+    }
+
+    let footprint = SolStorageFootprint::structure(structure_fields_footprints(sc, structure)?);
+    let footprint = expand_footprint(sc, footprint);
+
     let sol_storage_type_impl = quote! {
         #[automatically_derived]
         #[allow(
@@ -97,6 +149,32 @@ fn expand_sol_storage_type_impl(
         )]
         impl #sdecode_solidity::SolStorageType for #structure_path {
             const SOL_STORAGE_NAME: &str = #structure_ident_lit;
+
+            fn sol_storage_dyn_type() -> #sdecode_solidity::SolStorageDynType {
+                #sdecode_solidity::helpers::SolStructureHelper::<Self, (), ()>::sol_storage_dyn_type(
+                    #structure_ident_lit,
+                    || ::std::vec![#dyn_fields],
+                )
+            }
+
+            fn sol_storage_footprint() -> #sdecode_solidity::SolStorageFootprint {
+                #footprint
+            }
+
+            fn sol_storage_load<B>(
+                backend: &mut B,
+                slot: #alloy_primitives::B256,
+            ) -> ::core::result::Result<
+                #sdecode_core::StorageStructure,
+                #sdecode_core::StorageError<B::Error, #sdecode_solidity::SolLayoutError>,
+            >
+            where
+                B: #sdecode_core::SlotBackend,
+            {
+                let mut loader = #sdecode_solidity::SolStructureLoader::new(backend, slot);
+                #load_fields
+                ::core::result::Result::Ok(loader.finish())
+            }
         }
     };
 
@@ -112,8 +190,8 @@ fn expand_sol_storage_value_impl(
     let sdecode_solidity = sc.file.sdecode_solidity();
     let sdecode_core = sc.file.sdecode_core();
 
-    let mut destructure_fields = TokenStream::new();
-    let mut sol_storage_types = TokenStream::new();
+    let mut fields_decode = TokenStream::new();
+    let mut struct_creation = TokenStream::new();
 
     for field in &structure.raw.fields {
         let Some(field_name) = &field.name else {
@@ -123,13 +201,23 @@ fn expand_sol_storage_value_impl(
             ));
         };
         let field_name_unspanned = field_name.0.clone().with_span(Span::call_site());
+        let field_name_lit = LitStr::new(&field_name.to_string(), field_name.span());
 
-        destructure_fields.extend(quote! { #field_name_unspanned, });
+        let sol_storage_typ = field_sol_storage_type(sc, field)?;
 
-        let sol_storage_typ = get_sol_storage_type(sc, &field.ty)?;
-        sol_storage_types.extend(quote! { #sol_storage_typ, });
+        // The Rust type of the field is inferred from the structure, which may be remote.
+        fields_decode.extend(quote! {
+            #sdecode_core::StorageReader::enter(storage_reader, || {
+                #sdecode_core::LayoutPathSegment::Field(#field_name_lit.into())
+            });
+            let #field_name_unspanned = <
+                _ as #sdecode_solidity::SolStorageValue<#sol_storage_typ>
+            >::decode_storage(storage_reader)
+                .map_err(|err| err.at_field(#field_name_lit))?;
+            #sdecode_core::StorageReader::leave(storage_reader);
+        });
+        struct_creation.extend(quote! { #field_name_unspanned, });
     }
-    sol_storage_types = quote! { (#sol_storage_types) };
 
     let sol_storage_value_impl = quote! {
         #[automatically_derived]
@@ -144,15 +232,279 @@ fn expand_sol_storage_value_impl(
             where
                 Reader: #sdecode_core::StorageReader,
             {
-                let (#destructure_fields) = <
-                    #sdecode_solidity::helpers::SolStructureHelper<Self, _, #sol_storage_types>
-                    as #sdecode_solidity::SolStorageValue<_>
-                >::decode_storage(storage_reader)?.0;
+                // Structs always start and end on a new slot.
+                let remaining = #sdecode_core::StorageReader::consume_remaining(storage_reader);
+                if remaining.is_not_zero() {
+                    return Err(#sdecode_solidity::SolLayoutError::remaining_bytes(remaining));
+                }
 
-                Ok(Self { #destructure_fields })
+                #fields_decode
+
+                let remaining = #sdecode_core::StorageReader::consume_remaining(storage_reader);
+                if remaining.is_not_zero() {
+                    return Err(#sdecode_solidity::SolLayoutError::remaining_bytes(remaining));
+                }
+
+                Ok(Self { #struct_creation })
             }
         }
     };
 
     Ok(sol_storage_value_impl)
 }
+
+fn expand_sol_storage_encode_impl(
+    sc: &Scope<'_>,
+    structure: &PPStruct<'_>,
+) -> syn::Result<TokenStream> {
+    let structure_path = structure.rust_path();
+
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let sdecode_core = sc.file.sdecode_core();
+
+    let mut fields_refs = TokenStream::new();
+    let mut sol_storage_types = TokenStream::new();
+
+    for field in &structure.raw.fields {
+        let Some(field_name) = &field.name else {
+            return Err(syn::Error::new(
+                field.span(),
+                "unnamed fields are not supported",
+            ));
+        };
+        let field_name_unspanned = field_name.0.clone().with_span(Span::call_site());
+
+        fields_refs.extend(quote! { &self.#field_name_unspanned, });
+
+        let sol_storage_typ = field_sol_storage_type(sc, field)?;
+        sol_storage_types.extend(quote! { #sol_storage_typ, });
+    }
+    sol_storage_types = quote! { (#sol_storage_types) };
+
+    let sol_storage_encode_impl = quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_solidity::SolStorageEncode<Self> for #structure_path {
+            fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), #sdecode_solidity::SolLayoutError>
+            where
+                Writer: #sdecode_core::StorageWriter,
+            {
+                <
+                    #sdecode_solidity::helpers::SolStructureHelper<Self, _, #sol_storage_types>
+                    as #sdecode_solidity::SolStorageEncode<_>
+                >::encode_storage(
+                    &#sdecode_solidity::helpers::SolStructureHelper::new((#fields_refs)),
+                    storage_writer,
+                )
+            }
+        }
+    };
+
+    Ok(sol_storage_encode_impl)
+}
+
+fn expand_sol_storage_layout_impl_for_structure(
+    sc: &Scope<'_>,
+    structure: &PPStruct<'_>,
+) -> syn::Result<TokenStream> {
+    let contract = sc
+        .contract
+        .map(|contract| contract.raw.name.to_string())
+        .unwrap_or_default();
+
+    let footprints = structure_fields_footprints(sc, structure)?;
+
+    let mut entries = Vec::new();
+    for (field, footprint) in structure.raw.fields.iter().zip(footprints) {
+        let Some(field_name) = &field.name else {
+            return Err(syn::Error::new(
+                field.span(),
+                "unnamed fields are not supported",
+            ));
+        };
+
+        entries.push(LayoutEntry {
+            label: field_name.to_string(),
+            sol_ty: field_sol_storage_type(sc, field)?,
+            contract: contract.clone(),
+            footprint,
+            slot: None,
+            derivation: None,
+        });
+    }
+
+    let structure_path = structure.rust_path();
+    let fields_ident = format_ident!("{}Fields", structure.rust_ident());
+
+    let mut res = expand_sol_storage_layout_impl(
+        sc,
+        &structure_path,
+        &structure.raw.name.to_string(),
+        &entries,
+        U256::ZERO,
+    );
+    res.extend(expand_sol_storage_fields_impl(
+        sc,
+        &structure_path,
+        &fields_ident,
+        &entries,
+    ));
+    Ok(res)
+}
+
+/// Expands the decoding and encoding of a structure declared as a namespaced root, from its base
+/// slot.
+fn expand_root_impl(sc: &Scope<'_>, structure: &PPStruct<'_>) -> syn::Result<TokenStream> {
+    if !structure.attrs.is_root() {
+        return Ok(TokenStream::new());
+    }
+
+    let sdecode_core = sc.file.sdecode_core();
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let sdecode_preimages = sc.file.sdecode_preimages();
+    let alloy_primitives = sc.file.alloy_primitives();
+
+    let language = match sc.contract {
+        Some(contract) => expand_language(contract)?,
+        None => quote! { SOLIDITY },
+    };
+
+    let base_slot = if let Some(slot) = structure.attrs.slot_value()? {
+        let slot = expand_b256(sc, slot);
+        quote! {
+            const BASE_SLOT: #alloy_primitives::B256 = #slot;
+        }
+    } else {
+        TokenStream::new()
+    };
+
+    let structure_path = structure.rust_path();
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #sdecode_solidity::SolStorageRoot for #structure_path {
+            const MAPPING_KEY_SIDE: #sdecode_core::MappingKeySide =
+                #sdecode_core::MappingKeySide::#language;
+
+            #base_slot
+        }
+
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_core ::StorageDecode for #structure_path {
+            type LayoutError = #sdecode_solidity::SolLayoutError;
+
+            fn sdecode_at_with_mode_mut<P, E>(
+                base_slot: #alloy_primitives::B256,
+                preimages_provider: &mut P,
+                storage_entries: E,
+                mode: #sdecode_core ::DecodeMode,
+            ) -> ::core::result::Result<
+                #sdecode_core ::StorageReport<Self>,
+                #sdecode_core ::StorageError<P::Error, Self::LayoutError>,
+            >
+            where
+                P: #sdecode_preimages ::PreimagesProviderMut,
+                E: ::core::iter::IntoIterator<Item = (#alloy_primitives::B256, #alloy_primitives::B256)>,
+            {
+                let side = #sdecode_core::MappingKeySide:: #language;
+                let diagnostics = mode
+                    .is_lenient()
+                    .then(|| #sdecode_core ::StorageDiagnostics::new(side));
+
+                let mut layout = #sdecode_core ::Storage::decode_mut(preimages_provider, storage_entries, side)
+                    .map_err(#sdecode_core ::StorageError::Provider)?;
+                let mut storage_reader = layout.reader_at_with(
+                    base_slot,
+                    ::core::clone::Clone::clone(&diagnostics),
+                );
+
+                let value = <
+                    Self as #sdecode_solidity::SolStorageValue<Self>
+                >::decode_storage(&mut storage_reader)
+                    .map_err(#sdecode_core ::StorageError::Layout)?;
+
+                ::core::mem::drop(storage_reader);
+
+                ::core::result::Result::Ok(#sdecode_core ::StorageReport::new(
+                    value,
+                    layout,
+                    diagnostics,
+                ))
+            }
+
+            fn sdecode_with_mode_mut<P, E>(
+                preimages_provider: &mut P,
+                storage_entries: E,
+                mode: #sdecode_core ::DecodeMode,
+            ) -> ::core::result::Result<
+                #sdecode_core ::StorageReport<Self>,
+                #sdecode_core ::StorageError<P::Error, Self::LayoutError>,
+            >
+            where
+                P: #sdecode_preimages ::PreimagesProviderMut,
+                E: ::core::iter::IntoIterator<Item = (#alloy_primitives::B256, #alloy_primitives::B256)>,
+            {
+                Self::sdecode_at_with_mode_mut(
+                    <Self as #sdecode_solidity::SolStorageRoot>::BASE_SLOT,
+                    preimages_provider,
+                    storage_entries,
+                    mode,
+                )
+            }
+        }
+
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_core ::StorageEncode for #structure_path {
+            type EncodeError = #sdecode_solidity::SolLayoutError;
+
+            fn sencode_at_mut(
+                &self,
+                base_slot: #alloy_primitives::B256,
+                preimages: &mut #sdecode_preimages ::MemoryPreimagesProvider,
+                storage_entries: &mut #sdecode_core ::StorageEntries,
+            ) -> Result<(), Self::EncodeError> {
+                let side = #sdecode_core::MappingKeySide:: #language;
+
+                let mut layout = #sdecode_core ::Storage::default();
+                let mut storage_writer = #sdecode_core::StorageStructureWriter::new();
+                <
+                    Self as #sdecode_solidity::SolStorageEncode<Self>
+                >::encode_storage(self, &mut storage_writer)?;
+
+                layout.insert_structure(base_slot, storage_writer.into_structure());
+                layout.encode_into(side, preimages, storage_entries);
+                Ok(())
+            }
+
+            fn sencode_mut(
+                &self,
+                preimages: &mut #sdecode_preimages ::MemoryPreimagesProvider,
+                storage_entries: &mut #sdecode_core ::StorageEntries,
+            ) -> Result<(), Self::EncodeError> {
+                #sdecode_core ::StorageEncode::sencode_at_mut(
+                    self,
+                    <Self as #sdecode_solidity::SolStorageRoot>::BASE_SLOT,
+                    preimages,
+                    storage_entries,
+                )
+            }
+        }
+    })
+}
@@ -2,10 +2,10 @@ use proc_macro2::TokenStream;
 use quote::quote;
 use syn::LitStr;
 
+use sdecode_solidity_layout::{PPUdt, Scope};
+
 use crate::{
     get_sol_storage_type,
-    pp::PPUdt,
-    scope::Scope,
     types::{get_default_rust_type, is_mapping_key_type},
 };
 
@@ -14,6 +14,7 @@ pub struct UdtExpansion {
     pub structure_def: TokenStream,
     pub sol_storage_type_impl: TokenStream,
     pub sol_storage_value_impl: TokenStream,
+    pub sol_storage_encode_impl: TokenStream,
     pub sol_mapping_key_type_impl: TokenStream,
 }
 
@@ -23,6 +24,7 @@ impl UdtExpansion {
             structure_def: expand_structure_def(sc, udt)?,
             sol_storage_type_impl: expand_sol_storage_type_impl(sc, udt)?,
             sol_storage_value_impl: expand_sol_storage_value_impl(sc, udt)?,
+            sol_storage_encode_impl: expand_sol_storage_encode_impl(sc, udt)?,
             sol_mapping_key_type_impl: expand_sol_mapping_key_type_impl(sc, udt)?,
         })
     }
@@ -32,6 +34,7 @@ impl UdtExpansion {
         res.extend(self.structure_def);
         res.extend(self.sol_storage_type_impl);
         res.extend(self.sol_storage_value_impl);
+        res.extend(self.sol_storage_encode_impl);
         res.extend(self.sol_mapping_key_type_impl);
         res
     }
@@ -64,6 +67,8 @@ fn expand_sol_storage_type_impl(sc: &Scope<'_>, udt: &PPUdt<'_>) -> syn::Result<
     let udt_ident = udt.rust_ident();
     let udt_ident_lit = LitStr::new(&udt_ident.to_string(), udt_ident.span());
 
+    let sol_typ = get_sol_storage_type(sc, &udt.raw.ty)?;
+
     let sdecode_solidity = sc.file.sdecode_solidity();
 
     let sol_storage_type_impl = quote! {
@@ -76,6 +81,14 @@ fn expand_sol_storage_type_impl(sc: &Scope<'_>, udt: &PPUdt<'_>) -> syn::Result<
         )]
         impl #sdecode_solidity::SolStorageType for #udt_path {
             const SOL_STORAGE_NAME: &str = #udt_ident_lit;
+
+            fn sol_storage_dyn_type() -> #sdecode_solidity::SolStorageDynType {
+                <#sol_typ as #sdecode_solidity::SolStorageType>::sol_storage_dyn_type()
+            }
+
+            fn sol_storage_footprint() -> #sdecode_solidity::SolStorageFootprint {
+                <#sol_typ as #sdecode_solidity::SolStorageType>::sol_storage_footprint()
+            }
         }
     };
 
@@ -115,6 +128,40 @@ fn expand_sol_storage_value_impl(sc: &Scope<'_>, udt: &PPUdt<'_>) -> syn::Result
     Ok(sol_storage_value_impl)
 }
 
+fn expand_sol_storage_encode_impl(sc: &Scope<'_>, udt: &PPUdt<'_>) -> syn::Result<TokenStream> {
+    let udt_path = udt.rust_path();
+    let sol_typ = get_sol_storage_type(sc, &udt.raw.ty)?;
+
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let sdecode_core = sc.file.sdecode_core();
+
+    let sol_storage_encode_impl = quote! {
+        #[automatically_derived]
+        #[allow(
+            non_camel_case_types,
+            non_snake_case,
+            clippy::pub_underscore_fields,
+            clippy::style
+        )]
+        impl #sdecode_solidity::SolStorageEncode<Self> for #udt_path {
+            fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), #sdecode_solidity::SolLayoutError>
+            where
+                Writer: #sdecode_core::StorageWriter,
+            {
+                <
+                    #sdecode_solidity::helpers::SolStructureHelper<Self, _, #sol_typ>
+                    as #sdecode_solidity::SolStorageEncode<_>
+                >::encode_storage(
+                    &#sdecode_solidity::helpers::SolStructureHelper::new(&self.0),
+                    storage_writer,
+                )
+            }
+        }
+    };
+
+    Ok(sol_storage_encode_impl)
+}
+
 fn expand_sol_mapping_key_type_impl(sc: &Scope<'_>, udt: &PPUdt<'_>) -> syn::Result<TokenStream> {
     let udt_path = udt.rust_path();
 
@@ -1,26 +1,13 @@
 #![cfg_attr(not(test), warn(unused_crate_dependencies))]
 
 use proc_macro2::TokenStream;
+use sdecode_solidity_layout::{PPFile, Scope};
 use syn::parse_macro_input;
 use syn_solidity::File;
 
-mod array_size;
-
-mod attribute;
-
 mod expansion;
 use expansion::FileExpansion;
 
-mod linearize;
-
-mod pp;
-use pp::PPFile;
-
-mod scope;
-use scope::Scope;
-
-mod utils;
-
 mod types;
 use types::get_sol_storage_type;
 
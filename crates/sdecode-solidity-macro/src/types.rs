@@ -1,8 +1,8 @@
 use std::num::NonZero;
 
-use crate::{array_size::ArraySizeEvaluator, pp::UserDefinedItem, scope::Scope};
 use proc_macro2::{Literal, TokenStream};
 use quote::{quote, quote_spanned};
+use sdecode_solidity_layout::{ArraySizeEvaluator, Scope, UserDefinedItem, max_len_error};
 use syn::Ident;
 use syn_solidity::{Spanned, Type};
 
@@ -49,7 +49,7 @@ pub fn get_sol_storage_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStrea
         }
         Type::Function(function) => quote_spanned! {function.span()=> #data_types :: Function },
         Type::Mapping(mapping) => {
-            let key_ty = get_sol_storage_type(sc, &mapping.key)?;
+            let key_ty = get_mapping_key_sol_storage_type(sc, &mapping.key)?;
             let value_ty = get_sol_storage_type(sc, &mapping.value)?;
             quote_spanned! {mapping.span()=> #data_types :: Mapping<#key_ty, #value_ty> }
         }
@@ -112,6 +112,56 @@ pub fn get_sol_storage_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStrea
     Ok(result)
 }
 
+/// Solidity storage type of a state variable or a structure field bounded by a `max_len`
+/// attribute: its dynamic array, `bytes` or `string`, possibly as the value of (nested) mappings,
+/// is a Vyper `DynArray`, `Bytes` or `String`.
+pub fn get_bounded_sol_storage_type(
+    sc: &Scope<'_>,
+    ty: &Type,
+    max_len: Option<usize>,
+) -> syn::Result<TokenStream> {
+    let Some(max_len) = max_len else {
+        return get_sol_storage_type(sc, ty);
+    };
+
+    let data_types = sc.file.sdecode_solidity_data_types();
+    let max_len_lit = Literal::usize_unsuffixed(max_len);
+
+    let result = match ty {
+        Type::Array(array) if array.size.is_none() => {
+            let elem_ty = get_sol_storage_type(sc, &array.ty)?;
+            quote_spanned! {array.span()=> #data_types :: VyDynArray<#elem_ty, #max_len_lit> }
+        }
+        Type::Bytes(span) => quote_spanned! {*span=> #data_types :: VyBytes<#max_len_lit> },
+        Type::String(span) => quote_spanned! {*span=> #data_types :: VyString<#max_len_lit> },
+        Type::Mapping(mapping) => {
+            let key_ty = get_mapping_key_sol_storage_type(sc, &mapping.key)?;
+            let value_ty = get_bounded_sol_storage_type(sc, &mapping.value, Some(max_len))?;
+            quote_spanned! {mapping.span()=> #data_types :: Mapping<#key_ty, #value_ty> }
+        }
+        _ => return Err(max_len_error(ty.span())),
+    };
+
+    Ok(result)
+}
+
+/// Solidity storage type of a mapping key. Vyper hashes its `Bytes` and `String` keys.
+pub fn get_mapping_key_sol_storage_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStream> {
+    let data_types = sc.file.sdecode_solidity_data_types();
+
+    let result = match ty {
+        Type::Bytes(span) if sc.is_vyper() => {
+            quote_spanned! {*span=> #data_types :: VyHashedBytes }
+        }
+        Type::String(span) if sc.is_vyper() => {
+            quote_spanned! {*span=> #data_types :: VyHashedString }
+        }
+        _ => return get_sol_storage_type(sc, ty),
+    };
+
+    Ok(result)
+}
+
 pub fn get_default_rust_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStream> {
     let alloy_primitives = sc.file.alloy_primitives();
 
@@ -173,7 +223,7 @@ pub fn get_default_rust_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStre
             quote_spanned! {function.span()=> #alloy_primitives :: Function }
         }
         Type::Mapping(mapping) => {
-            let key_ty = get_default_rust_type(sc, &mapping.key)?;
+            let key_ty = get_default_mapping_key_rust_type(sc, &mapping.key)?;
             if matches!(*mapping.value, Type::Bool(_)) {
                 quote_spanned! {mapping.span()=> ::std::collections::BTreeSet<#key_ty> }
             } else {
@@ -240,6 +290,18 @@ pub fn get_default_rust_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStre
     Ok(result)
 }
 
+/// Default Rust type of a mapping key. The hashed keys of Vyper are only known when their
+/// preimage is.
+fn get_default_mapping_key_rust_type(sc: &Scope<'_>, ty: &Type) -> syn::Result<TokenStream> {
+    if !sc.is_vyper() || !matches!(ty, Type::Bytes(_) | Type::String(_)) {
+        return get_default_rust_type(sc, ty);
+    }
+
+    let sdecode_solidity = sc.file.sdecode_solidity();
+    let key_ty = get_default_rust_type(sc, ty)?;
+    Ok(quote_spanned! {ty.span()=> #sdecode_solidity :: VyHashedKey<#key_ty> })
+}
+
 const fn is_primitive_int(size: u16) -> bool {
     matches!(size, 8 | 16 | 32 | 64 | 128)
 }
@@ -0,0 +1,484 @@
+use alloy_primitives::{Address, Bytes, FixedBytes, Function, I256, U256, aliases::*};
+use indexmap::IndexMap;
+use sdecode_core::{StorageReader, SubB256};
+
+use crate::{
+    SolLayoutError, SolLayoutErrorKind, SolMappingKeyType, SolMappingKeyValue, SolStorageDynType,
+    SolStorageValue, SolWordType, VyHashedKey, data_types,
+    values::{
+        SolStorageSeed, decode_dynamic_array, decode_fixed_array, decode_mapping, decode_structure,
+        decode_vyper_bytes, decode_vyper_dynamic_array,
+    },
+};
+
+use super::SolStorageDynValue;
+
+/// Dispatches a runtime bit count to the matching Rust and Solidity `uintN` types.
+macro_rules! match_uint {
+    ($bits:expr, $t:ident, $sol_t:ident => $body:expr, _ => $default:expr $(,)?) => {
+        match_uint!(@arms ($bits) ($t $sol_t) ($body) ($default)
+            8 => u8,
+            16 => u16,
+            24 => U24,
+            32 => u32,
+            40 => U40,
+            48 => U48,
+            56 => U56,
+            64 => u64,
+            72 => U72,
+            80 => U80,
+            88 => U88,
+            96 => U96,
+            104 => U104,
+            112 => U112,
+            120 => U120,
+            128 => u128,
+            136 => U136,
+            144 => U144,
+            152 => U152,
+            160 => U160,
+            168 => U168,
+            176 => U176,
+            184 => U184,
+            192 => U192,
+            200 => U200,
+            208 => U208,
+            216 => U216,
+            224 => U224,
+            232 => U232,
+            240 => U240,
+            248 => U248,
+            256 => U256,
+        )
+    };
+    (@arms ($bits:expr) ($t:ident $sol_t:ident) ($body:expr) ($default:expr) $($n:literal => $ty:ty,)*) => {
+        match $bits {
+            $($n => {
+                type $t = $ty;
+                type $sol_t = data_types::Uint<$n>;
+                $body
+            })*
+            _ => $default,
+        }
+    };
+}
+
+/// Dispatches a runtime bit count to the matching Rust and Solidity `intN` types.
+macro_rules! match_int {
+    ($bits:expr, $t:ident, $sol_t:ident => $body:expr, _ => $default:expr $(,)?) => {
+        match_int!(@arms ($bits) ($t $sol_t) ($body) ($default)
+            8 => i8,
+            16 => i16,
+            24 => I24,
+            32 => i32,
+            40 => I40,
+            48 => I48,
+            56 => I56,
+            64 => i64,
+            72 => I72,
+            80 => I80,
+            88 => I88,
+            96 => I96,
+            104 => I104,
+            112 => I112,
+            120 => I120,
+            128 => i128,
+            136 => I136,
+            144 => I144,
+            152 => I152,
+            160 => I160,
+            168 => I168,
+            176 => I176,
+            184 => I184,
+            192 => I192,
+            200 => I200,
+            208 => I208,
+            216 => I216,
+            224 => I224,
+            232 => I232,
+            240 => I240,
+            248 => I248,
+            256 => I256,
+        )
+    };
+    (@arms ($bits:expr) ($t:ident $sol_t:ident) ($body:expr) ($default:expr) $($n:literal => $ty:ty,)*) => {
+        match $bits {
+            $($n => {
+                type $t = $ty;
+                type $sol_t = data_types::Int<$n>;
+                $body
+            })*
+            _ => $default,
+        }
+    };
+}
+
+/// Dispatches a runtime byte count to the matching Rust and Solidity `bytesN` types.
+macro_rules! match_fixed_bytes {
+    ($size:expr, $t:ident, $sol_t:ident => $body:expr, _ => $default:expr $(,)?) => {
+        match_fixed_bytes!(@arms ($size) ($t $sol_t) ($body) ($default)
+            1 => FixedBytes<1>,
+            2 => FixedBytes<2>,
+            3 => FixedBytes<3>,
+            4 => FixedBytes<4>,
+            5 => FixedBytes<5>,
+            6 => FixedBytes<6>,
+            7 => FixedBytes<7>,
+            8 => FixedBytes<8>,
+            9 => FixedBytes<9>,
+            10 => FixedBytes<10>,
+            11 => FixedBytes<11>,
+            12 => FixedBytes<12>,
+            13 => FixedBytes<13>,
+            14 => FixedBytes<14>,
+            15 => FixedBytes<15>,
+            16 => FixedBytes<16>,
+            17 => FixedBytes<17>,
+            18 => FixedBytes<18>,
+            19 => FixedBytes<19>,
+            20 => FixedBytes<20>,
+            21 => FixedBytes<21>,
+            22 => FixedBytes<22>,
+            23 => FixedBytes<23>,
+            24 => FixedBytes<24>,
+            25 => FixedBytes<25>,
+            26 => FixedBytes<26>,
+            27 => FixedBytes<27>,
+            28 => FixedBytes<28>,
+            29 => FixedBytes<29>,
+            30 => FixedBytes<30>,
+            31 => FixedBytes<31>,
+            32 => FixedBytes<32>,
+        )
+    };
+    (@arms ($size:expr) ($t:ident $sol_t:ident) ($body:expr) ($default:expr) $($n:literal => $ty:ty,)*) => {
+        match $size {
+            $($n => {
+                type $t = $ty;
+                type $sol_t = data_types::FixedBytes<$n>;
+                $body
+            })*
+            _ => $default,
+        }
+    };
+}
+
+/// Decoding of values whose type is only known at runtime.
+pub trait SolStorageDynDecode {
+    /// Decodes a value of this type, following the same rules as [`SolStorageValue`].
+    fn decode_storage<Reader>(
+        &self,
+        storage_reader: &mut Reader,
+    ) -> Result<SolStorageDynValue, SolLayoutError>
+    where
+        Reader: StorageReader;
+
+    /// Decodes a raw mapping key of this type, following the same rules as
+    /// [`SolMappingKeyValue`]. The hashed keys whose original value is unknown to
+    /// `storage_reader` are decoded as [`SolStorageDynValue::Raw`].
+    fn decode_mapping_key<Reader>(
+        &self,
+        key: Bytes,
+        storage_reader: &Reader,
+    ) -> Result<SolStorageDynValue, SolLayoutError>
+    where
+        Reader: StorageReader;
+}
+
+impl SolStorageDynDecode for SolStorageDynType {
+    fn decode_storage<Reader>(
+        &self,
+        storage_reader: &mut Reader,
+    ) -> Result<SolStorageDynValue, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        SolStorageDynSeed::new(self).decode_storage_seed(storage_reader)
+    }
+
+    fn decode_mapping_key<Reader>(
+        &self,
+        key: Bytes,
+        storage_reader: &Reader,
+    ) -> Result<SolStorageDynValue, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        match self {
+            Self::Bool => decode_key::<bool, data_types::Bool>(key, storage_reader)
+                .map(SolStorageDynValue::Bool),
+            Self::Uint { bits } => match_uint!(*bits, T, SolT =>
+                decode_key::<T, SolT>(key, storage_reader).map(uint_value),
+                _ => Err(unsupported(self)),
+            ),
+            Self::Int { bits } => match_int!(*bits, T, SolT =>
+                decode_key::<T, SolT>(key, storage_reader).map(int_value),
+                _ => Err(unsupported(self)),
+            ),
+            Self::Address => decode_key::<Address, data_types::Address>(key, storage_reader)
+                .map(SolStorageDynValue::Address),
+            Self::FixedBytes { size } => match_fixed_bytes!(*size, T, SolT =>
+                decode_key::<T, SolT>(key, storage_reader).map(fixed_bytes_value),
+                _ => Err(unsupported(self)),
+            ),
+            Self::Function => decode_key::<Function, data_types::Function>(key, storage_reader)
+                .map(fixed_bytes_value),
+            Self::Bytes => decode_key::<Bytes, data_types::Bytes>(key, storage_reader)
+                .map(SolStorageDynValue::Bytes),
+            Self::String => decode_key::<String, data_types::String>(key, storage_reader)
+                .map(SolStorageDynValue::String),
+            Self::Enum { name, variants } => {
+                let index = decode_key::<u8, data_types::Uint<8>>(key, storage_reader)?;
+                enum_value(name, variants, index)
+            }
+            Self::VyHashedBytes => {
+                decode_key::<VyHashedKey<Bytes>, data_types::VyHashedBytes>(key, storage_reader)
+                    .map(|key| match key {
+                        VyHashedKey::Known(bytes) => SolStorageDynValue::Bytes(bytes),
+                        VyHashedKey::Hashed(hash) => SolStorageDynValue::Raw(hash),
+                    })
+            }
+            Self::VyHashedString => {
+                decode_key::<VyHashedKey<String>, data_types::VyHashedString>(key, storage_reader)
+                    .map(|key| match key {
+                        VyHashedKey::Known(string) => SolStorageDynValue::String(string),
+                        VyHashedKey::Hashed(hash) => SolStorageDynValue::Raw(hash),
+                    })
+            }
+            Self::FixedArray { .. }
+            | Self::Array { .. }
+            | Self::Mapping { .. }
+            | Self::Struct { .. }
+            | Self::StructRef { .. }
+            | Self::VyDynArray { .. }
+            | Self::VyBytes { .. }
+            | Self::VyString { .. } => Err(unsupported(self)),
+        }
+    }
+}
+
+/// [`SolStorageDynType`] along with the structures enclosing it, to resolve
+/// [`SolStorageDynType::StructRef`].
+#[derive(Clone, Copy)]
+struct SolStorageDynSeed<'a> {
+    ty: &'a SolStorageDynType,
+    enclosing: Option<&'a Enclosing<'a>>,
+}
+
+/// Link of the chain of values enclosing a [`SolStorageDynSeed`], from the innermost.
+struct Enclosing<'a> {
+    /// A structure, or `None` for a mapping or a dynamic array, whose values are stored apart.
+    structure: Option<&'a SolStorageDynType>,
+    parent: Option<&'a Enclosing<'a>>,
+}
+
+impl<'a> SolStorageDynSeed<'a> {
+    const fn new(ty: &'a SolStorageDynType) -> Self {
+        Self {
+            ty,
+            enclosing: None,
+        }
+    }
+
+    /// Seed of a value nested in this one.
+    const fn nested(&self, ty: &'a SolStorageDynType) -> Self {
+        Self {
+            ty,
+            enclosing: self.enclosing,
+        }
+    }
+
+    /// Enclosing structure named `name`. It must be stored apart from the reference, otherwise the
+    /// structure would contain itself.
+    fn resolve(&self, name: &str) -> Option<&'a SolStorageDynType> {
+        let mut is_apart = false;
+        let mut enclosing = self.enclosing;
+        while let Some(link) = enclosing {
+            match link.structure {
+                Some(structure @ SolStorageDynType::Struct { name: n, .. }) if n == name => {
+                    return is_apart.then_some(structure);
+                }
+                Some(_) => {}
+                None => is_apart = true,
+            }
+            enclosing = link.parent;
+        }
+        None
+    }
+}
+
+impl SolStorageSeed for SolStorageDynSeed<'_> {
+    type Value = SolStorageDynValue;
+
+    fn decode_storage_seed<Reader>(
+        &self,
+        storage_reader: &mut Reader,
+    ) -> Result<SolStorageDynValue, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        // Mapping values and dynamic array elements are stored apart.
+        let apart = Enclosing {
+            structure: None,
+            parent: self.enclosing,
+        };
+        let stored_apart = |ty| SolStorageDynSeed {
+            ty,
+            enclosing: Some(&apart),
+        };
+
+        match self.ty {
+            SolStorageDynType::Bool => {
+                <bool as SolStorageValue<data_types::Bool>>::decode_storage(storage_reader)
+                    .map(SolStorageDynValue::Bool)
+            }
+            SolStorageDynType::Uint { bits } => match_uint!(*bits, T, SolT =>
+                <T as SolStorageValue<SolT>>::decode_storage(storage_reader).map(uint_value),
+                _ => Err(unsupported(self.ty)),
+            ),
+            SolStorageDynType::Int { bits } => match_int!(*bits, T, SolT =>
+                <T as SolStorageValue<SolT>>::decode_storage(storage_reader).map(int_value),
+                _ => Err(unsupported(self.ty)),
+            ),
+            SolStorageDynType::Address => {
+                <Address as SolStorageValue<data_types::Address>>::decode_storage(storage_reader)
+                    .map(SolStorageDynValue::Address)
+            }
+            SolStorageDynType::FixedBytes { size } => match_fixed_bytes!(*size, T, SolT =>
+                <T as SolStorageValue<SolT>>::decode_storage(storage_reader).map(fixed_bytes_value),
+                _ => Err(unsupported(self.ty)),
+            ),
+            SolStorageDynType::Function => {
+                <Function as SolStorageValue<data_types::Function>>::decode_storage(storage_reader)
+                    .map(fixed_bytes_value)
+            }
+            SolStorageDynType::Bytes => {
+                <Bytes as SolStorageValue<data_types::Bytes>>::decode_storage(storage_reader)
+                    .map(SolStorageDynValue::Bytes)
+            }
+            SolStorageDynType::String => {
+                <String as SolStorageValue<data_types::String>>::decode_storage(storage_reader)
+                    .map(SolStorageDynValue::String)
+            }
+            SolStorageDynType::Enum { name, variants } => {
+                let index =
+                    <u8 as SolStorageValue<data_types::Uint<8>>>::decode_storage(storage_reader)?;
+                enum_value(name, variants, index)
+            }
+            SolStorageDynType::FixedArray { element, len } => {
+                decode_fixed_array(storage_reader, *len, &self.nested(element))
+                    .map(SolStorageDynValue::Array)
+            }
+            SolStorageDynType::Array { element } => {
+                decode_dynamic_array(storage_reader, &stored_apart(element))
+                    .map(SolStorageDynValue::Array)
+            }
+            SolStorageDynType::Mapping { key, value } => decode_mapping(
+                storage_reader,
+                "mapping",
+                |raw, storage_reader| key.decode_mapping_key(raw, storage_reader),
+                &stored_apart(value),
+            )
+            .map(SolStorageDynValue::Mapping),
+            SolStorageDynType::Struct { name, fields } => {
+                let structure = Enclosing {
+                    structure: Some(self.ty),
+                    parent: self.enclosing,
+                };
+                let seeds = fields
+                    .iter()
+                    .map(|field| SolStorageDynSeed {
+                        ty: &field.ty,
+                        enclosing: Some(&structure),
+                    })
+                    .collect::<Vec<_>>();
+                let values: Vec<_> = decode_structure(
+                    storage_reader,
+                    fields.iter().map(|field| field.name.as_str()).zip(&seeds),
+                )?;
+                let fields = fields
+                    .iter()
+                    .map(|field| field.name.clone())
+                    .zip(values)
+                    .collect::<IndexMap<_, _>>();
+                Ok(SolStorageDynValue::Struct {
+                    name: name.clone(),
+                    fields,
+                })
+            }
+            SolStorageDynType::StructRef { name, .. } => match self.resolve(name) {
+                Some(structure) => self.nested(structure).decode_storage_seed(storage_reader),
+                None => Err(unsupported(self.ty)),
+            },
+            SolStorageDynType::VyDynArray { element, max_len } => decode_vyper_dynamic_array(
+                storage_reader,
+                *max_len,
+                element.footprint(),
+                &self.nested(element),
+            )
+            .map(SolStorageDynValue::Array),
+            SolStorageDynType::VyBytes { max_len } => decode_vyper_bytes(storage_reader, *max_len)
+                .map(|bytes| SolStorageDynValue::Bytes(bytes.into())),
+            SolStorageDynType::VyString { max_len } => decode_vyper_bytes(storage_reader, *max_len)
+                .map(|bytes| SolStorageDynValue::String(String::from_utf8_lossy(&bytes).into())),
+            SolStorageDynType::VyHashedBytes | SolStorageDynType::VyHashedString => {
+                Err(unsupported(self.ty))
+            }
+        }
+    }
+}
+
+fn decode_key<K, SolK>(key: Bytes, storage_reader: &impl StorageReader) -> Result<K, SolLayoutError>
+where
+    K: SolMappingKeyValue<SolK>,
+    SolK: SolMappingKeyType,
+{
+    K::decode_sol_mapping_key(key, storage_reader).map_err(|raw| {
+        SolLayoutErrorKind::InvalidMappingKey {
+            sol_type: SolK::SOL_STORAGE_NAME,
+            raw,
+        }
+        .into()
+    })
+}
+
+fn unsupported(ty: &SolStorageDynType) -> SolLayoutError {
+    SolLayoutErrorKind::UnsupportedType { ty: ty.clone() }.into()
+}
+
+fn uint_value<T: SolWordType>(value: T) -> SolStorageDynValue {
+    SolStorageDynValue::Uint(U256::from_be_bytes(value.into_word().0))
+}
+
+fn int_value<T: SolWordType>(value: T) -> SolStorageDynValue {
+    SolStorageDynValue::Int(I256::from_be_bytes(value.into_word().0))
+}
+
+fn fixed_bytes_value<T: SolWordType>(value: T) -> SolStorageDynValue {
+    SolStorageDynValue::FixedBytes(Bytes::copy_from_slice(value.into_packed_word().as_slice()))
+}
+
+fn enum_value(
+    name: &str,
+    variants: &[String],
+    index: u8,
+) -> Result<SolStorageDynValue, SolLayoutError> {
+    let variant = if variants.is_empty() {
+        None
+    } else {
+        let Some(variant) = variants.get(usize::from(index)) else {
+            return Err(SolLayoutErrorKind::InvalidEnumDiscriminant {
+                sol_type: name.to_string().into(),
+                discriminant: index,
+            }
+            .into());
+        };
+        Some(variant.clone())
+    };
+
+    Ok(SolStorageDynValue::Enum {
+        name: name.to_string(),
+        index,
+        variant,
+    })
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+
+use alloy_primitives::Bytes;
+use sdecode_core::{LayoutPath, LayoutPathSegment, StorageChange, StorageChangeKind};
+
+use crate::SolLayoutError;
+
+use super::SolStorageDynValue;
+
+/// Change of a decoded Solidity value, located by its path in the layout.
+pub type SolStorageChange = StorageChange<SolStorageDynValue>;
+
+/// Structural difference between two decoded storages.
+///
+/// Implemented by the storage structures generated by `sol_storage!`.
+pub trait SolStorageDiff {
+    /// Changes from `self` to `other`: added and removed mapping entries and array elements, and
+    /// changed values, in the order of the layout. Mapping entries holding a zero value are
+    /// absent, as in storage.
+    fn sdiff(&self, other: &Self) -> Result<Vec<SolStorageChange>, SolLayoutError>;
+}
+
+impl SolStorageDiff for SolStorageDynValue {
+    fn sdiff(&self, other: &Self) -> Result<Vec<SolStorageChange>, SolLayoutError> {
+        let mut changes = Vec::new();
+        self.diff_into(other, &mut LayoutPath::default(), &mut changes);
+        Ok(changes)
+    }
+}
+
+impl SolStorageDynValue {
+    /// Appends the changes from `self` to `other` to `changes`, located under `path`.
+    pub fn diff_into(
+        &self,
+        other: &Self,
+        path: &mut LayoutPath,
+        changes: &mut Vec<SolStorageChange>,
+    ) {
+        match (self, other) {
+            (Self::Array(before), Self::Array(after)) => {
+                for index in 0..before.len().max(after.len()) {
+                    path.0.push(LayoutPathSegment::Index(index as u64));
+                    diff_entries(before.get(index), after.get(index), path, changes);
+                    path.0.pop();
+                }
+            }
+            (Self::Mapping(before), Self::Mapping(after)) => {
+                let mut entries = BTreeMap::<Bytes, (Option<_>, Option<_>)>::new();
+                for (key, value) in before {
+                    entries.entry(raw_key(key)).or_default().0 = Some(value);
+                }
+                for (key, value) in after {
+                    entries.entry(raw_key(key)).or_default().1 = Some(value);
+                }
+
+                for (key, (before, after)) in entries {
+                    path.0.push(LayoutPathSegment::Key(key));
+                    diff_entries(before, after, path, changes);
+                    path.0.pop();
+                }
+            }
+            (
+                Self::Struct {
+                    name: before_name,
+                    fields: before,
+                },
+                Self::Struct {
+                    name: after_name,
+                    fields: after,
+                },
+            ) if before_name == after_name => {
+                let names = before
+                    .keys()
+                    .chain(after.keys().filter(|name| !before.contains_key(*name)));
+                for name in names {
+                    path.0.push(LayoutPathSegment::Field(name.clone().into()));
+                    diff_entries(before.get(name), after.get(name), path, changes);
+                    path.0.pop();
+                }
+            }
+            (before, after) if before == after => {}
+            (before, after) => changes.push(StorageChange {
+                path: path.clone(),
+                kind: StorageChangeKind::Changed {
+                    before: before.clone(),
+                    after: after.clone(),
+                },
+            }),
+        }
+    }
+}
+
+/// Diffs two mapping entries, array elements or struct fields, any of which may be absent.
+fn diff_entries(
+    before: Option<&SolStorageDynValue>,
+    after: Option<&SolStorageDynValue>,
+    path: &mut LayoutPath,
+    changes: &mut Vec<SolStorageChange>,
+) {
+    match (before, after) {
+        (Some(before), Some(after)) => before.diff_into(after, path, changes),
+        (before, after) => {
+            if let Some(kind) = StorageChangeKind::between(before.cloned(), after.cloned()) {
+                changes.push(StorageChange {
+                    path: path.clone(),
+                    kind,
+                });
+            }
+        }
+    }
+}
+
+fn raw_key(key: &SolStorageDynValue) -> Bytes {
+    key.to_mapping_key().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, B256, U256, address};
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    use self::{forum::Post, governor::Proposal};
+
+    crate::sol_storage! {
+        #[derive(Clone)]
+        contract Governor {
+            #[derive(Clone)]
+            struct Proposal {
+                address proposer;
+                uint64 deadline;
+                bool executed;
+            }
+
+            uint256 quorum;
+            mapping(uint256 => Proposal) proposals;
+            address[] guardians;
+            string name;
+        }
+
+        #[derive(Clone)]
+        contract Forum {
+            #[derive(Clone)]
+            struct Post {
+                uint64 id;
+                mapping(uint256 => Post) replies;
+            }
+
+            Post root;
+        }
+    }
+
+    #[test]
+    fn test_storage_diff() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+
+        let before = GovernorStorage {
+            quorum: U256::from(100),
+            proposals: BTreeMap::from([(
+                U256::from(1),
+                Proposal {
+                    proposer: alice,
+                    deadline: 10,
+                    executed: false,
+                },
+            )]),
+            guardians: vec![alice, bob],
+            name: "governor".to_string(),
+        };
+
+        let mut after = before.clone();
+        after.quorum = U256::from(200);
+        after.proposals.get_mut(&U256::from(1)).unwrap().executed = true;
+        after.proposals.insert(
+            U256::from(2),
+            Proposal {
+                proposer: bob,
+                deadline: 20,
+                executed: false,
+            },
+        );
+        after.guardians.pop();
+        // Entries holding a zero value are absent from storage.
+        after.proposals.insert(
+            U256::from(3),
+            Proposal {
+                proposer: Address::ZERO,
+                deadline: 0,
+                executed: false,
+            },
+        );
+
+        assert_eq!(before.sdiff(&before).unwrap(), []);
+
+        let changes = before
+            .sdiff(&after)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                "`quorum`: 100 -> 200".to_string(),
+                format!(
+                    "`proposals[{}].executed`: false -> true",
+                    B256::from(U256::from(1))
+                ),
+                format!(
+                    "`proposals[{}]`: added Proposal {{ proposer: {bob}, deadline: 20, executed: false }}",
+                    B256::from(U256::from(2))
+                ),
+                format!("`guardians[1]`: removed {bob}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursive_storage_diff() {
+        let post = |id, replies: Vec<(u64, Post)>| Post {
+            id,
+            replies: replies
+                .into_iter()
+                .map(|(key, reply)| (U256::from(key), reply))
+                .collect(),
+        };
+
+        let before = ForumStorage {
+            root: post(1, vec![(1, post(2, vec![]))]),
+        };
+        let after = ForumStorage {
+            root: post(1, vec![(1, post(3, vec![(5, post(4, vec![]))]))]),
+        };
+
+        let key = |key: u64| B256::from(U256::from(key));
+        let changes = before
+            .sdiff(&after)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                format!("`root.replies[{}].id`: 2 -> 3", key(1)),
+                format!(
+                    "`root.replies[{}].replies[{}]`: added Post {{ id: 4, replies: {{}} }}",
+                    key(1),
+                    key(5)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dyn_value_diff() {
+        let before = SolStorageDynValue::Struct {
+            name: "S".to_string(),
+            fields: IndexMap::from([
+                ("a".to_string(), SolStorageDynValue::Uint(U256::from(1))),
+                (
+                    "b".to_string(),
+                    SolStorageDynValue::Mapping(vec![(
+                        SolStorageDynValue::String("key".to_string()),
+                        SolStorageDynValue::Address(Address::ZERO),
+                    )]),
+                ),
+            ]),
+        };
+        let after = SolStorageDynValue::Struct {
+            name: "S".to_string(),
+            fields: IndexMap::from([
+                ("a".to_string(), SolStorageDynValue::Uint(U256::from(1))),
+                ("b".to_string(), SolStorageDynValue::Mapping(Vec::new())),
+            ]),
+        };
+
+        assert_eq!(
+            before.sdiff(&after).unwrap(),
+            [StorageChange {
+                path: LayoutPath(vec![
+                    LayoutPathSegment::Field("b".into()),
+                    LayoutPathSegment::Key(Bytes::from_static(b"key")),
+                ]),
+                kind: StorageChangeKind::Removed(SolStorageDynValue::Address(Address::ZERO)),
+            }]
+        );
+    }
+}
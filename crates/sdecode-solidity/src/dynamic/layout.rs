@@ -0,0 +1,478 @@
+use alloy_primitives::{B256, U256};
+use indexmap::IndexMap;
+use sdecode_core::{
+    DecodeMode, LayoutPathSegment, MAX_STORAGE_OFFSET, MappingKeySide, Storage, StorageDiagnostic,
+    StorageDiagnostics, StorageError, StorageReader, StorageReport,
+};
+use sdecode_preimages::{PreimagesProvider, PreimagesProviderMut, caches::StoragePreimagesCache};
+
+use crate::{SolLayoutError, SolStorageDynLayout};
+
+use super::{SolStorageDynDecode, SolStorageDynValue};
+
+/// Decoding of contract storages whose layout is only known at runtime.
+pub trait SolStorageDynLayoutDecode {
+    /// Decodes the state variables into a [`SolStorageDynValue::Struct`]. With `diagnostics`, the
+    /// decoding is done in [`DecodeMode::Lenient`](sdecode_core::DecodeMode::Lenient).
+    fn decode_with(
+        &self,
+        storage: &mut Storage,
+        diagnostics: Option<StorageDiagnostics>,
+    ) -> Result<SolStorageDynValue, SolLayoutError>;
+
+    /// Decodes the state variables into a [`SolStorageDynValue::Struct`].
+    fn decode(&self, storage: &mut Storage) -> Result<SolStorageDynValue, SolLayoutError> {
+        self.decode_with(storage, None)
+    }
+
+    /// Runtime counterpart of [`StorageDecode::sdecode`](sdecode_core::StorageDecode::sdecode).
+    fn sdecode<P, E>(
+        &self,
+        preimages_provider: P,
+        storage_entries: E,
+    ) -> Result<SolStorageDynValue, StorageError<P::Error, SolLayoutError>>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        self.sdecode_mut(
+            &mut StoragePreimagesCache::new(preimages_provider, U256::from(MAX_STORAGE_OFFSET)),
+            storage_entries,
+        )
+    }
+
+    /// Runtime counterpart of
+    /// [`StorageDecode::sdecode_mut`](sdecode_core::StorageDecode::sdecode_mut).
+    fn sdecode_mut<P, E>(
+        &self,
+        preimages_provider: &mut P,
+        storage_entries: E,
+    ) -> Result<SolStorageDynValue, StorageError<P::Error, SolLayoutError>>
+    where
+        P: PreimagesProviderMut,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        let mut storage = Storage::decode_mut(
+            preimages_provider,
+            storage_entries,
+            MappingKeySide::SOLIDITY,
+        )
+        .map_err(StorageError::Provider)?;
+
+        self.decode(&mut storage).map_err(StorageError::Layout)
+    }
+
+    /// Runtime counterpart of
+    /// [`StorageDecode::sdecode_lenient`](sdecode_core::StorageDecode::sdecode_lenient).
+    fn sdecode_lenient<P, E>(
+        &self,
+        preimages_provider: P,
+        storage_entries: E,
+    ) -> Result<(SolStorageDynValue, Vec<StorageDiagnostic>), StorageError<P::Error, SolLayoutError>>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        self.sdecode_report(preimages_provider, storage_entries, DecodeMode::Lenient)
+            .map(|report| (report.value, report.diagnostics))
+    }
+
+    /// Runtime counterpart of
+    /// [`StorageDecode::sdecode_report`](sdecode_core::StorageDecode::sdecode_report).
+    fn sdecode_report<P, E>(
+        &self,
+        preimages_provider: P,
+        storage_entries: E,
+        mode: DecodeMode,
+    ) -> Result<StorageReport<SolStorageDynValue>, StorageError<P::Error, SolLayoutError>>
+    where
+        P: PreimagesProvider,
+        E: IntoIterator<Item = (B256, B256)>,
+    {
+        let side = MappingKeySide::SOLIDITY;
+        let mut storage = Storage::decode(preimages_provider, storage_entries, side)
+            .map_err(StorageError::Provider)?;
+
+        let diagnostics = mode.is_lenient().then(|| StorageDiagnostics::new(side));
+        let value = self
+            .decode_with(&mut storage, diagnostics.clone())
+            .map_err(StorageError::Layout)?;
+        Ok(StorageReport::new(value, storage, diagnostics))
+    }
+}
+
+impl SolStorageDynLayoutDecode for SolStorageDynLayout {
+    fn decode_with(
+        &self,
+        storage: &mut Storage,
+        diagnostics: Option<StorageDiagnostics>,
+    ) -> Result<SolStorageDynValue, SolLayoutError> {
+        let mut fields = IndexMap::new();
+
+        let mut variables = self.variables.iter().peekable();
+        while let Some(first) = variables.next() {
+            // Variables sharing a slot are packed, so they are read with the same reader.
+            let mut storage_reader =
+                storage.reader_at_with(B256::from(first.slot), diagnostics.clone());
+
+            storage_reader.enter(|| LayoutPathSegment::Field(first.name.clone().into()));
+            let value = first
+                .ty
+                .decode_storage(&mut storage_reader)
+                .map_err(|err| err.at_field(first.name.clone()))?;
+            storage_reader.leave();
+            fields.insert(first.name.clone(), value);
+
+            while let Some(variable) = variables.next_if(|variable| variable.slot == first.slot) {
+                storage_reader.enter(|| LayoutPathSegment::Field(variable.name.clone().into()));
+                let value = variable
+                    .ty
+                    .decode_storage(&mut storage_reader)
+                    .map_err(|err| err.at_field(variable.name.clone()))?;
+                storage_reader.leave();
+                fields.insert(variable.name.clone(), value);
+            }
+
+            let remaining = storage_reader.consume_remaining();
+            if remaining.is_not_zero() {
+                return Err(SolLayoutError::remaining_bytes(remaining));
+            }
+        }
+
+        Ok(SolStorageDynValue::Struct {
+            name: self.name.clone(),
+            fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy_primitives::{Address, Bytes, I256, U256, fixed_bytes};
+    use sdecode_core::StorageEncode;
+
+    use super::*;
+    use crate::{SolStorageLayout, SolcLayoutError, SolcStorageLayout, parse_storage_layouts};
+
+    crate::sol_storage! {
+        contract Token {
+            struct Account {
+                uint128 balance;
+                uint64 nonce;
+                bool frozen;
+            }
+
+            enum Kind {
+                A,
+                B,
+            }
+
+            string name;
+            address owner;
+            uint8 decimals;
+            Kind kind;
+            mapping(address => Account) accounts;
+            uint256[] supplies;
+            int16[2] deltas;
+            bytes4 tag;
+        }
+
+        contract Tree {
+            struct Node {
+                uint64 id;
+                mapping(uint256 => Node) children;
+            }
+
+            Node root;
+        }
+    }
+
+    const SOLC_LAYOUT: &str = r#"{
+        "storage": [
+            { "astId": 20, "contract": "Token.sol:Token", "label": "name", "offset": 0, "slot": "0", "type": "t_string_storage" },
+            { "astId": 22, "contract": "Token.sol:Token", "label": "owner", "offset": 0, "slot": "1", "type": "t_address" },
+            { "astId": 24, "contract": "Token.sol:Token", "label": "decimals", "offset": 20, "slot": "1", "type": "t_uint8" },
+            { "astId": 27, "contract": "Token.sol:Token", "label": "kind", "offset": 21, "slot": "1", "type": "t_enum(Kind)12" },
+            { "astId": 32, "contract": "Token.sol:Token", "label": "accounts", "offset": 0, "slot": "2", "type": "t_mapping(t_address,t_struct(Account)9_storage)" },
+            { "astId": 35, "contract": "Token.sol:Token", "label": "supplies", "offset": 0, "slot": "3", "type": "t_array(t_uint256)dyn_storage" },
+            { "astId": 39, "contract": "Token.sol:Token", "label": "deltas", "offset": 0, "slot": "4", "type": "t_array(t_int16)2_storage" },
+            { "astId": 41, "contract": "Token.sol:Token", "label": "tag", "offset": 0, "slot": "5", "type": "t_bytes4" }
+        ],
+        "types": {
+            "t_address": { "encoding": "inplace", "label": "address", "numberOfBytes": "20" },
+            "t_array(t_int16)2_storage": { "base": "t_int16", "encoding": "inplace", "label": "int16[2]", "numberOfBytes": "32" },
+            "t_array(t_uint256)dyn_storage": { "base": "t_uint256", "encoding": "dynamic_array", "label": "uint256[]", "numberOfBytes": "32" },
+            "t_bool": { "encoding": "inplace", "label": "bool", "numberOfBytes": "1" },
+            "t_bytes4": { "encoding": "inplace", "label": "bytes4", "numberOfBytes": "4" },
+            "t_enum(Kind)12": { "encoding": "inplace", "label": "enum Token.Kind", "numberOfBytes": "1" },
+            "t_int16": { "encoding": "inplace", "label": "int16", "numberOfBytes": "2" },
+            "t_mapping(t_address,t_struct(Account)9_storage)": { "encoding": "mapping", "key": "t_address", "label": "mapping(address => struct Token.Account)", "numberOfBytes": "32", "value": "t_struct(Account)9_storage" },
+            "t_string_storage": { "encoding": "bytes", "label": "string", "numberOfBytes": "32" },
+            "t_struct(Account)9_storage": {
+                "encoding": "inplace",
+                "label": "struct Token.Account",
+                "members": [
+                    { "astId": 4, "contract": "Token.sol:Token", "label": "balance", "offset": 0, "slot": "0", "type": "t_uint128" },
+                    { "astId": 6, "contract": "Token.sol:Token", "label": "nonce", "offset": 16, "slot": "0", "type": "t_uint64" },
+                    { "astId": 8, "contract": "Token.sol:Token", "label": "frozen", "offset": 24, "slot": "0", "type": "t_bool" }
+                ],
+                "numberOfBytes": "32"
+            },
+            "t_uint128": { "encoding": "inplace", "label": "uint128", "numberOfBytes": "16" },
+            "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" },
+            "t_uint64": { "encoding": "inplace", "label": "uint64", "numberOfBytes": "8" },
+            "t_uint8": { "encoding": "inplace", "label": "uint8", "numberOfBytes": "1" }
+        }
+    }"#;
+
+    #[test]
+    fn test_solc_layout_decode() {
+        let holder = Address::repeat_byte(0x11);
+
+        let token = TokenStorage {
+            name: "a token name that does not fit in a single slot".to_string(),
+            owner: Address::repeat_byte(0x22),
+            decimals: 6,
+            kind: token::Kind::B,
+            accounts: BTreeMap::from([(
+                holder,
+                token::Account {
+                    balance: 500,
+                    nonce: 3,
+                    frozen: true,
+                },
+            )]),
+            supplies: vec![U256::from(10), U256::from(20)],
+            deltas: [-1, 7],
+            tag: fixed_bytes!("0xdeadbeef"),
+        };
+        let (storage_entries, preimages) = token.sencode().unwrap();
+
+        let solc_layout: SolcStorageLayout = serde_json::from_str(SOLC_LAYOUT).unwrap();
+        let layout = solc_layout.to_dyn_layout("Token").unwrap();
+        let decoded = layout.sdecode(preimages, storage_entries).unwrap();
+
+        assert_eq!(
+            decoded.field("name"),
+            Some(&SolStorageDynValue::String(token.name.clone()))
+        );
+        assert_eq!(
+            decoded.field("owner"),
+            Some(&SolStorageDynValue::Address(token.owner))
+        );
+        assert_eq!(
+            decoded.field("decimals"),
+            Some(&SolStorageDynValue::Uint(U256::from(6)))
+        );
+        assert_eq!(
+            decoded.field("kind"),
+            Some(&SolStorageDynValue::Enum {
+                name: "Kind".to_string(),
+                index: 1,
+                variant: None,
+            })
+        );
+
+        let account = decoded
+            .field("accounts")
+            .unwrap()
+            .get(&SolStorageDynValue::Address(holder))
+            .unwrap();
+        assert_eq!(
+            account.field("balance"),
+            Some(&SolStorageDynValue::Uint(U256::from(500)))
+        );
+        assert_eq!(
+            account.field("frozen"),
+            Some(&SolStorageDynValue::Bool(true))
+        );
+
+        assert_eq!(
+            decoded.field("deltas"),
+            Some(&SolStorageDynValue::Array(vec![
+                SolStorageDynValue::Int(I256::MINUS_ONE),
+                SolStorageDynValue::Int(I256::try_from(7).unwrap()),
+            ]))
+        );
+        assert_eq!(
+            decoded.field("tag"),
+            Some(&SolStorageDynValue::FixedBytes(Bytes::from_static(&[
+                0xde, 0xad, 0xbe, 0xef
+            ])))
+        );
+
+        let json = serde_json::to_value(decoded.field("supplies").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "array",
+                "value": [
+                    { "type": "uint", "value": "0xa" },
+                    { "type": "uint", "value": "0x14" },
+                ],
+            })
+        );
+    }
+
+    const TREE_SOLC_LAYOUT: &str = r#"{
+        "storage": [
+            { "astId": 12, "contract": "Tree.sol:Tree", "label": "root", "offset": 0, "slot": "0", "type": "t_struct(Node)9_storage" }
+        ],
+        "types": {
+            "t_mapping(t_uint256,t_struct(Node)9_storage)": { "encoding": "mapping", "key": "t_uint256", "label": "mapping(uint256 => struct Tree.Node)", "numberOfBytes": "32", "value": "t_struct(Node)9_storage" },
+            "t_struct(Node)9_storage": {
+                "encoding": "inplace",
+                "label": "struct Tree.Node",
+                "members": [
+                    { "astId": 3, "contract": "Tree.sol:Tree", "label": "id", "offset": 0, "slot": "0", "type": "t_uint64" },
+                    { "astId": 8, "contract": "Tree.sol:Tree", "label": "children", "offset": 0, "slot": "1", "type": "t_mapping(t_uint256,t_struct(Node)9_storage)" }
+                ],
+                "numberOfBytes": "64"
+            },
+            "t_uint256": { "encoding": "inplace", "label": "uint256", "numberOfBytes": "32" },
+            "t_uint64": { "encoding": "inplace", "label": "uint64", "numberOfBytes": "8" }
+        }
+    }"#;
+
+    #[test]
+    fn test_solc_recursive_layout_decode() {
+        let tree = TreeStorage {
+            root: tree::Node {
+                id: 1,
+                children: BTreeMap::from([(
+                    U256::from(7),
+                    tree::Node {
+                        id: 2,
+                        children: BTreeMap::new(),
+                    },
+                )]),
+            },
+        };
+        let (storage_entries, preimages) = tree.sencode().unwrap();
+
+        let solc_layout: SolcStorageLayout = serde_json::from_str(TREE_SOLC_LAYOUT).unwrap();
+        let layout = solc_layout.to_dyn_layout("Tree").unwrap();
+        assert_eq!(
+            layout.variable("root").unwrap().ty,
+            TreeStorage::sol_storage_dyn_layout()
+                .variable("root")
+                .unwrap()
+                .ty
+        );
+
+        let decoded = layout.sdecode(preimages, storage_entries).unwrap();
+        let child = decoded
+            .field("root")
+            .and_then(|root| root.field("children"))
+            .and_then(|children| children.get(&SolStorageDynValue::Uint(U256::from(7))))
+            .unwrap();
+        assert_eq!(
+            child.field("id"),
+            Some(&SolStorageDynValue::Uint(U256::from(2)))
+        );
+
+        // A structure containing itself in place is rejected.
+        let mut solc_layout = solc_layout;
+        let types = solc_layout.types.as_mut().unwrap();
+        let node = types.get_mut("t_struct(Node)9_storage").unwrap();
+        node.members.as_mut().unwrap()[1].ty = "t_struct(Node)9_storage".to_string();
+        assert_eq!(
+            solc_layout.to_dyn_layout("Tree"),
+            Err(SolcLayoutError::RecursiveType(
+                "t_struct(Node)9_storage".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_source_layout_decode() {
+        let source = r#"
+            contract Token {
+                struct Account {
+                    uint128 balance;
+                    uint64 nonce;
+                    bool frozen;
+                }
+
+                enum Kind {
+                    A,
+                    B,
+                }
+
+                string name;
+                address owner;
+                uint8 decimals;
+                Kind kind;
+                mapping(address => Account) accounts;
+                uint256[] supplies;
+                int16[2] deltas;
+                bytes4 tag;
+            }
+        "#;
+
+        let token = TokenStorage {
+            name: "token".to_string(),
+            owner: Address::repeat_byte(0x22),
+            decimals: 18,
+            kind: token::Kind::A,
+            accounts: BTreeMap::new(),
+            supplies: vec![U256::from(1)],
+            deltas: [3, -4],
+            tag: fixed_bytes!("0x01020304"),
+        };
+        let (storage_entries, preimages) = token.sencode().unwrap();
+
+        let solc_layout: SolcStorageLayout = serde_json::from_str(SOLC_LAYOUT).unwrap();
+        let mut layouts = parse_storage_layouts(source).unwrap();
+        assert_eq!(layouts.len(), 1);
+        let layout = layouts.remove(0);
+
+        let solc_locations = solc_layout
+            .storage
+            .iter()
+            .map(|item| {
+                (
+                    item.label.clone(),
+                    item.slot.parse::<U256>().unwrap(),
+                    item.offset,
+                )
+            })
+            .collect::<Vec<_>>();
+        let locations = layout
+            .variables
+            .iter()
+            .map(|var| (var.name.clone(), var.slot, var.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(locations, solc_locations);
+
+        let macro_locations = TokenStorage::STORAGE_LAYOUT
+            .iter()
+            .map(|entry| (entry.label.to_string(), entry.slot, entry.offset))
+            .collect::<Vec<_>>();
+        assert_eq!(macro_locations, solc_locations);
+
+        let solc_layout = solc_layout.to_dyn_layout("Token").unwrap();
+        assert_eq!(
+            TokenStorage::sol_storage_dyn_layout().upgrade_errors(&solc_layout),
+            []
+        );
+
+        let decoded = layout.sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(
+            decoded.field("kind"),
+            Some(&SolStorageDynValue::Enum {
+                name: "Kind".to_string(),
+                index: 0,
+                variant: Some("A".to_string()),
+            })
+        );
+        assert_eq!(
+            decoded.field("tag"),
+            Some(&SolStorageDynValue::FixedBytes(Bytes::from_static(&[
+                1, 2, 3, 4
+            ])))
+        );
+    }
+}
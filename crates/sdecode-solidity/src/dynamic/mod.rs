@@ -0,0 +1,11 @@
+mod decode;
+pub use decode::SolStorageDynDecode;
+
+mod diff;
+pub use diff::{SolStorageChange, SolStorageDiff};
+
+mod layout;
+pub use layout::SolStorageDynLayoutDecode;
+
+mod value;
+pub use value::SolStorageDynValue;
@@ -0,0 +1,328 @@
+use std::fmt;
+
+use alloy_primitives::{Address, B256, Bytes, I256, U256};
+use indexmap::IndexMap;
+use sdecode_core::{IntoStorageReader, StorageReader, StorageStructureWriter};
+
+use crate::{SolLayoutError, SolStorageEncode, SolStorageType, SolStorageValue};
+
+use super::SolStorageDynDecode;
+
+/// A decoded Solidity storage value whose type is only known at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum SolStorageDynValue {
+    Bool(bool),
+    Uint(U256),
+    Int(I256),
+    Address(Address),
+    /// `bytesN` values, and external function pointers (address followed by the selector).
+    FixedBytes(Bytes),
+    Bytes(Bytes),
+    String(String),
+    /// Elements of a fixed size or dynamic array.
+    Array(Vec<SolStorageDynValue>),
+    /// Entries of a mapping, ordered by raw key.
+    Mapping(Vec<(SolStorageDynValue, SolStorageDynValue)>),
+    Struct {
+        name: String,
+        fields: IndexMap<String, SolStorageDynValue>,
+    },
+    Enum {
+        name: String,
+        index: u8,
+        /// Name of the variant, if known.
+        variant: Option<String>,
+    },
+    /// A word that could not be interpreted.
+    Raw(B256),
+}
+
+impl SolStorageDynValue {
+    /// Dynamic representation of a value of the Solidity type `T`, such as a struct generated by
+    /// `sol_storage!`, as it would be decoded from storage.
+    pub fn from_value<T, V>(value: &V) -> Result<Self, SolLayoutError>
+    where
+        T: SolStorageType,
+        V: SolStorageEncode<T> + ?Sized,
+    {
+        let mut storage_writer = StorageStructureWriter::new();
+        value.encode_storage(&mut storage_writer)?;
+
+        T::sol_storage_dyn_type()
+            .decode_storage(&mut storage_writer.into_structure().into_storage_reader())
+    }
+
+    /// Field of a struct value, if any.
+    pub fn field(&self, name: &str) -> Option<&Self> {
+        match self {
+            Self::Struct { fields, .. } => fields.get(name),
+            _ => None,
+        }
+    }
+
+    /// Value of a mapping entry, if any.
+    pub fn get(&self, key: &Self) -> Option<&Self> {
+        match self {
+            Self::Mapping(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Raw mapping key of this value, as found in the preimage of the slot of an entry. Reference
+    /// types other than `bytes` and `string` cannot be mapping keys.
+    pub fn to_mapping_key(&self) -> Option<Bytes> {
+        let word = match self {
+            Self::Bool(value) => B256::with_last_byte(u8::from(*value)),
+            Self::Uint(value) => B256::from(*value),
+            Self::Int(value) => B256::from(value.into_raw()),
+            Self::Address(address) => address.into_word(),
+            Self::FixedBytes(bytes) => B256::right_padding_from(bytes),
+            Self::Enum { index, .. } => B256::with_last_byte(*index),
+            Self::Raw(word) => *word,
+            Self::Bytes(bytes) => return Some(bytes.clone()),
+            Self::String(string) => return Some(Bytes::copy_from_slice(string.as_bytes())),
+            Self::Array(_) | Self::Mapping(_) | Self::Struct { .. } => return None,
+        };
+        Some(word.into())
+    }
+}
+
+/// Solidity-like notation of the value.
+impl fmt::Display for SolStorageDynValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Uint(value) => write!(f, "{value}"),
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Address(address) => write!(f, "{address}"),
+            Self::FixedBytes(bytes) | Self::Bytes(bytes) => write!(f, "{bytes}"),
+            Self::String(string) => write!(f, "{string:?}"),
+            Self::Array(elements) => {
+                f.write_str("[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Mapping(entries) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{key}: {value}")?;
+                }
+                f.write_str("}")
+            }
+            Self::Struct { name, fields } => {
+                write!(f, "{name} {{")?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    f.write_str(if i > 0 { ", " } else { " " })?;
+                    write!(f, "{field}: {value}")?;
+                }
+                f.write_str(" }")
+            }
+            Self::Enum {
+                name,
+                variant: Some(variant),
+                ..
+            } => write!(f, "{name}.{variant}"),
+            Self::Enum { name, index, .. } => write!(f, "{name}({index})"),
+            Self::Raw(word) => write!(f, "{word}"),
+        }
+    }
+}
+
+impl<T: SolStorageType> SolStorageValue<T> for SolStorageDynValue {
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        T::sol_storage_dyn_type().decode_storage(storage_reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy_primitives::{Address, U256};
+    use sdecode_core::IntoStorageReader;
+
+    use super::*;
+    use crate::{SolStorageDynField, SolStorageDynType};
+
+    crate::sol_storage! {
+        contract Registry {
+            enum Status {
+                Pending,
+                Active,
+            }
+
+            struct Entry {
+                address owner;
+                Status status;
+                uint32[] history;
+                mapping(string => uint256) scores;
+            }
+
+            struct Node {
+                uint64 id;
+                mapping(uint256 => Node) children;
+            }
+        }
+    }
+
+    #[test]
+    fn test_dyn_value_from_generated_struct() {
+        let entry = registry::Entry {
+            owner: Address::repeat_byte(0xaa),
+            status: registry::Status::Active,
+            history: vec![1, 2, 3],
+            scores: BTreeMap::from([("alice".to_string(), U256::from(42))]),
+        };
+
+        let value = SolStorageDynValue::from_value::<registry::Entry, _>(&entry).unwrap();
+
+        assert_eq!(
+            value,
+            SolStorageDynValue::Struct {
+                name: "Entry".to_string(),
+                fields: IndexMap::from([
+                    (
+                        "owner".to_string(),
+                        SolStorageDynValue::Address(entry.owner)
+                    ),
+                    (
+                        "status".to_string(),
+                        SolStorageDynValue::Enum {
+                            name: "Status".to_string(),
+                            index: 1,
+                            variant: Some("Active".to_string()),
+                        }
+                    ),
+                    (
+                        "history".to_string(),
+                        SolStorageDynValue::Array(vec![
+                            SolStorageDynValue::Uint(U256::from(1)),
+                            SolStorageDynValue::Uint(U256::from(2)),
+                            SolStorageDynValue::Uint(U256::from(3)),
+                        ])
+                    ),
+                    (
+                        "scores".to_string(),
+                        SolStorageDynValue::Mapping(vec![(
+                            SolStorageDynValue::String("alice".to_string()),
+                            SolStorageDynValue::Uint(U256::from(42)),
+                        )])
+                    ),
+                ]),
+            }
+        );
+
+        let mut storage_writer = StorageStructureWriter::new();
+        entry.encode_storage(&mut storage_writer).unwrap();
+        let decoded = <SolStorageDynValue as SolStorageValue<registry::Entry>>::decode_storage(
+            &mut storage_writer.into_structure().into_storage_reader(),
+        )
+        .unwrap();
+        assert_eq!(decoded, value);
+
+        assert!(matches!(
+            <registry::Entry as SolStorageType>::sol_storage_dyn_type(),
+            SolStorageDynType::Struct { fields, .. } if fields.len() == 4
+        ));
+    }
+
+    #[test]
+    fn test_dyn_value_from_recursive_struct() {
+        let node = registry::Node {
+            id: 1,
+            children: BTreeMap::from([(
+                U256::from(7),
+                registry::Node {
+                    id: 2,
+                    children: BTreeMap::new(),
+                },
+            )]),
+        };
+
+        let children = SolStorageDynType::Mapping {
+            key: Box::new(SolStorageDynType::Uint { bits: 256 }),
+            value: Box::new(SolStorageDynType::StructRef {
+                name: "Node".to_string(),
+                slots: U256::from(2),
+            }),
+        };
+        assert_eq!(
+            <registry::Node as SolStorageType>::sol_storage_dyn_type(),
+            SolStorageDynType::Struct {
+                name: "Node".to_string(),
+                fields: vec![
+                    SolStorageDynField {
+                        name: "id".to_string(),
+                        ty: SolStorageDynType::Uint { bits: 64 },
+                    },
+                    SolStorageDynField {
+                        name: "children".to_string(),
+                        ty: children,
+                    },
+                ],
+            }
+        );
+
+        let node_value = |id, children| SolStorageDynValue::Struct {
+            name: "Node".to_string(),
+            fields: IndexMap::from([
+                ("id".to_string(), SolStorageDynValue::Uint(U256::from(id))),
+                (
+                    "children".to_string(),
+                    SolStorageDynValue::Mapping(children),
+                ),
+            ]),
+        };
+        assert_eq!(
+            SolStorageDynValue::from_value::<registry::Node, _>(&node).unwrap(),
+            node_value(
+                1,
+                vec![(
+                    SolStorageDynValue::Uint(U256::from(7)),
+                    node_value(2, vec![])
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn test_unresolved_struct_ref() {
+        // A structure containing itself in place would take infinite space.
+        let ty = SolStorageDynType::Struct {
+            name: "Node".to_string(),
+            fields: vec![SolStorageDynField {
+                name: "next".to_string(),
+                ty: SolStorageDynType::StructRef {
+                    name: "Node".to_string(),
+                    slots: U256::from(1),
+                },
+            }],
+        };
+
+        let err = ty
+            .decode_storage(
+                &mut StorageStructureWriter::new()
+                    .into_structure()
+                    .into_storage_reader(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.kind,
+            crate::SolLayoutErrorKind::UnsupportedType {
+                ty: SolStorageDynType::StructRef { .. }
+            }
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+use alloy_primitives::U256;
+
+use crate::SolStorageDynLayout;
+
+/// Location of a state variable or of a structure field, as computed by `sol_storage!`.
+///
+/// This is the equivalent of an entry of the `solc` `storageLayout` output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SolStorageLayoutEntry {
+    pub label: &'static str,
+    /// Solidity type name, as given by
+    /// [`SolStorageType::SOL_STORAGE_NAME`](crate::SolStorageType::SOL_STORAGE_NAME).
+    pub ty: &'static str,
+    /// Contract in which the variable or the structure is declared. Empty for structures declared
+    /// at the top level.
+    pub contract: &'static str,
+    /// Slot of the value, relative to the first slot of the structure for structure fields.
+    pub slot: U256,
+    /// Offset in bytes of the value in its slot, starting from the right.
+    pub offset: usize,
+    /// Number of bytes taken by the value. Values occupying whole slots always take a multiple of
+    /// 32 bytes.
+    pub size: U256,
+}
+
+/// Storage layout of a contract or a structure generated by `sol_storage!`.
+pub trait SolStorageLayout {
+    /// State variables or structure fields, in storage order.
+    const STORAGE_LAYOUT: &'static [SolStorageLayoutEntry];
+
+    /// Runtime description of the layout, with the full types of the variables or fields.
+    fn sol_storage_dyn_layout() -> SolStorageDynLayout;
+
+    /// Entry of the variable or field named `label`, if any.
+    fn storage_layout_entry(label: &str) -> Option<&'static SolStorageLayoutEntry> {
+        Self::STORAGE_LAYOUT
+            .iter()
+            .find(|entry| entry.label == label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SolStorageDynField, SolStorageDynType};
+
+    crate::sol_storage! {
+        contract Ownable {
+            address owner;
+            bool paused;
+        }
+
+        contract Registry is Ownable {
+            struct Node {
+                uint64 id;
+                address parent;
+                mapping(uint256 => Node) children;
+                uint16[20] weights;
+            }
+
+            uint256 constant MAX = 10;
+
+            uint8 version;
+            Node root;
+            bytes32 tag;
+            uint128 low;
+            uint128 high;
+
+            #[sdecode(slot = "0x00000000000000000000000000000000000000000000000000000000000000ff")]
+            uint32[] history;
+        }
+    }
+
+    fn locations<T: SolStorageLayout>()
+    -> Vec<(&'static str, &'static str, &'static str, u64, usize, u64)> {
+        T::STORAGE_LAYOUT
+            .iter()
+            .map(|entry| {
+                (
+                    entry.label,
+                    entry.ty,
+                    entry.contract,
+                    entry.slot.to(),
+                    entry.offset,
+                    entry.size.to(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_storage_layout() {
+        assert_eq!(
+            locations::<RegistryStorage>(),
+            [
+                ("owner", "address", "Ownable", 0, 0, 20),
+                ("paused", "bool", "Ownable", 0, 20, 1),
+                ("version", "uint8", "Registry", 0, 21, 1),
+                ("root", "Node", "Registry", 1, 0, 128),
+                ("tag", "bytes32", "Registry", 5, 0, 32),
+                ("low", "uint128", "Registry", 6, 0, 16),
+                ("high", "uint128", "Registry", 6, 16, 16),
+                ("history", "uint32[]", "Registry", 0xff, 0, 32),
+            ]
+        );
+
+        assert_eq!(
+            locations::<registry::Node>(),
+            [
+                ("id", "uint64", "Registry", 0, 0, 8),
+                ("parent", "address", "Registry", 0, 8, 20),
+                ("children", "mapping(uint256 => Node)", "Registry", 1, 0, 32),
+                ("weights", "uint16[20]", "Registry", 2, 0, 64),
+            ]
+        );
+
+        assert_eq!(
+            RegistryStorage::storage_layout_entry("tag").map(|entry| entry.slot),
+            Some(U256::from(5))
+        );
+    }
+
+    #[test]
+    fn test_storage_dyn_layout() {
+        let layout = RegistryStorage::sol_storage_dyn_layout();
+        assert_eq!(layout.name, "Registry");
+        assert_eq!(
+            layout
+                .variables
+                .iter()
+                .map(|variable| (variable.name.as_str(), variable.slot.to(), variable.offset))
+                .collect::<Vec<(_, u64, _)>>(),
+            [
+                ("owner", 0, 0),
+                ("paused", 0, 20),
+                ("version", 0, 21),
+                ("root", 1, 0),
+                ("tag", 5, 0),
+                ("low", 6, 0),
+                ("high", 6, 16),
+                ("history", 0xff, 0),
+            ]
+        );
+
+        // The structure refers back to itself through the mapping.
+        let field = |name: &str, ty| SolStorageDynField {
+            name: name.to_string(),
+            ty,
+        };
+        assert_eq!(
+            layout.variable("root").unwrap().ty,
+            SolStorageDynType::Struct {
+                name: "Node".to_string(),
+                fields: vec![
+                    field("id", SolStorageDynType::Uint { bits: 64 }),
+                    field("parent", SolStorageDynType::Address),
+                    field(
+                        "children",
+                        SolStorageDynType::Mapping {
+                            key: Box::new(SolStorageDynType::Uint { bits: 256 }),
+                            value: Box::new(SolStorageDynType::StructRef {
+                                name: "Node".to_string(),
+                                slots: U256::from(4),
+                            }),
+                        }
+                    ),
+                    field(
+                        "weights",
+                        SolStorageDynType::FixedArray {
+                            element: Box::new(SolStorageDynType::Uint { bits: 16 }),
+                            len: 20,
+                        }
+                    ),
+                ],
+            }
+        );
+    }
+}
@@ -8,14 +8,41 @@ pub mod data_types {
 
     pub use types::{
         Address, Array, Bool, ByteCount, Bytes, FixedArray, FixedBytes, Function, Int, IntBitCount,
-        Mapping, String, SupportedFixedBytes, SupportedInt, Uint,
+        Mapping, String, SupportedFixedBytes, SupportedInt, Uint, VyBytes, VyDynArray,
+        VyHashedBytes, VyHashedString, VyString,
     };
 }
 
+mod dynamic;
+pub use dynamic::{
+    SolStorageChange, SolStorageDiff, SolStorageDynDecode, SolStorageDynLayoutDecode,
+    SolStorageDynValue,
+};
+pub use sdecode_solidity_layout::{
+    SlotAllocator, SolStorageDynField, SolStorageDynLayout, SolStorageDynType,
+    SolStorageDynVariable, SolStorageFootprint, SolStorageUpgradeError, SolcLayoutError,
+    SolcStorageEncoding, SolcStorageItem, SolcStorageLayout, SolcStorageTypeDef, erc7201_slot,
+    parse_storage_layouts,
+};
+
+mod layout;
+pub use layout::{SolStorageLayout, SolStorageLayoutEntry};
+
+mod path;
+pub use path::{SolStorageFields, SolStoragePath, SolStorageRoot};
+
 mod values;
-pub use values::{SolLayoutError, SolMappingKeyValue, SolStorageValue, SolWordType, helpers};
+pub use values::{
+    SolLayoutError, SolLayoutErrorKind, SolLayoutPath, SolLayoutPathSegment, SolMappingKeyValue,
+    SolStorageEncode, SolStorageValue, SolWordType, VyHashedKey, helpers,
+};
 
 mod unknown;
+pub use unknown::{SolStorageInference, infer_storage_layout};
+
+mod view;
+#[doc(hidden)]
+pub use view::SolStructureLoader;
 
 mod utils;
 
@@ -43,3 +70,355 @@ pub mod __private {
     #[doc(hidden)]
     pub use sdecode_solidity_macro;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy_primitives::{Address, B256, Bytes, U256, address, b256, keccak256};
+    use sdecode_core::{StorageDecode, StorageEncode, StorageEntries};
+
+    use self::{
+        pool::Route,
+        vault::{Position, Status},
+    };
+    use crate::{SolStorageRoot, VyHashedKey};
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        contract Vault {
+            #[derive(Debug, PartialEq, Eq)]
+            enum Status {
+                Active,
+                Paused,
+            }
+
+            #[derive(Debug, PartialEq, Eq)]
+            struct Position {
+                uint128 amount;
+                uint64 since;
+                Status status;
+                string label;
+            }
+
+            address owner;
+            bool initialized;
+            uint8 decimals;
+            string name;
+            bytes data;
+            uint256[] totals;
+            uint32[3] weights;
+            mapping(address => mapping(uint256 => Position)) positions;
+            Position last;
+            uint16 version;
+        }
+    }
+
+    crate::sol_storage! {
+        contract Ownable {
+            address owner;
+        }
+
+        #[derive(Debug, PartialEq, Eq)]
+        #[sdecode(layout_at = keccak256("vault.storage"))]
+        contract Relocated is Ownable {
+            uint64 nonce;
+            string name;
+        }
+    }
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        #[sdecode(namespace = "openzeppelin.storage.ERC20")]
+        struct ERC20Storage {
+            mapping(address => uint256) _balances;
+            mapping(address => mapping(address => uint256)) _allowances;
+            uint256 _totalSupply;
+            string _name;
+            string _symbol;
+        }
+    }
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        #[sdecode(language = "vyper")]
+        contract Pool {
+            #[derive(Debug, PartialEq, Eq)]
+            struct Route {
+                #[sdecode(max_len = 4)]
+                address[] hops;
+                uint256 fee;
+            }
+
+            #[sdecode(max_len = 3)]
+            address[] coins;
+            #[sdecode(max_len = 64)]
+            string name;
+            #[sdecode(max_len = 40)]
+            mapping(address => bytes) data;
+            Route route;
+            uint256 total;
+        }
+    }
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        #[sdecode(language = "vyper")]
+        contract Registry {
+            // ids: HashMap[String[64], uint256]
+            mapping(string => uint256) ids;
+        }
+    }
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        contract SoladyToken {
+            uint256 totalSupply;
+            // keccak256(owner . _BALANCE_SLOT_SEED)
+            #[sdecode(derivation = sdecode_core::PackedSlotDerivation::new()
+                .key(20)
+                .seed(alloy_primitives::bytes!("0x000000000000000087a211a2")))]
+            mapping(address => uint256) balances;
+        }
+    }
+
+    #[test]
+    fn test_storage_encode_round_trip() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+
+        let vault = VaultStorage {
+            owner: Address::repeat_byte(0x42),
+            initialized: true,
+            decimals: 18,
+            name: "vault".to_string(),
+            data: Bytes::from(vec![0xab; 100]),
+            totals: vec![U256::from(1), U256::ZERO, U256::from(3)],
+            weights: [7, 8, 9],
+            positions: BTreeMap::from([
+                (
+                    alice,
+                    BTreeMap::from([(
+                        U256::from(1),
+                        Position {
+                            amount: 1000,
+                            since: 12,
+                            status: Status::Paused,
+                            label: "a label long enough to be stored out of its slot".to_string(),
+                        },
+                    )]),
+                ),
+                (
+                    bob,
+                    BTreeMap::from([(
+                        U256::from(2),
+                        Position {
+                            amount: 5,
+                            since: 0,
+                            status: Status::Active,
+                            label: "short".to_string(),
+                        },
+                    )]),
+                ),
+            ]),
+            last: Position {
+                amount: 3,
+                since: 4,
+                status: Status::Paused,
+                label: String::new(),
+            },
+            version: 2,
+        };
+
+        let (storage_entries, preimages) = vault.sencode().unwrap();
+        let decoded = VaultStorage::sdecode(preimages, storage_entries).unwrap();
+
+        assert_eq!(decoded, vault);
+    }
+
+    crate::sol_storage! {
+        #[derive(Debug, PartialEq, Eq)]
+        contract Packed {
+            #[derive(Debug, PartialEq, Eq)]
+            struct Pair {
+                uint8 a;
+                uint8 b;
+            }
+
+            Pair pair;
+            uint8 afterPair;
+            uint8[2] small;
+            uint8 afterArray;
+        }
+    }
+
+    #[test]
+    fn test_structure_alignment() {
+        let packed = PackedStorage {
+            pair: packed::Pair { a: 1, b: 2 },
+            afterPair: 3,
+            small: [4, 5],
+            afterArray: 6,
+        };
+
+        // Values following a struct or a fixed size array start a new slot.
+        let storage_entries = StorageEntries::from([
+            (B256::ZERO, B256::from(U256::from(0x0201))),
+            (B256::with_last_byte(1), B256::with_last_byte(3)),
+            (B256::with_last_byte(2), B256::from(U256::from(0x0504))),
+            (B256::with_last_byte(3), B256::with_last_byte(6)),
+        ]);
+
+        let (encoded, preimages) = packed.sencode().unwrap();
+        assert_eq!(encoded, storage_entries);
+        let decoded = PackedStorage::sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(decoded, packed);
+    }
+
+    #[test]
+    fn test_namespaced_root() {
+        let base_slot = b256!("0x52c63247e1f47db19d5ce0460030c497f067ca4cebf71ba98eeadabe20bace00");
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+
+        let erc20 = ERC20Storage {
+            _balances: BTreeMap::from([(alice, U256::from(100))]),
+            _allowances: BTreeMap::new(),
+            _totalSupply: U256::from(100),
+            _name: "Token".to_string(),
+            _symbol: "TKN".to_string(),
+        };
+
+        assert_eq!(ERC20Storage::BASE_SLOT, base_slot);
+        let total_supply_slot = U256::from_be_bytes(base_slot.0) + U256::from(2);
+        assert_eq!(
+            ERC20Storage::storage_paths()._totalSupply.slot(),
+            B256::from(total_supply_slot)
+        );
+
+        let (storage_entries, preimages) = erc20.sencode().unwrap();
+        assert_eq!(
+            storage_entries.get(&B256::from(total_supply_slot)),
+            Some(&B256::from(U256::from(100)))
+        );
+        let decoded = ERC20Storage::sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(decoded, erc20);
+
+        let (storage_entries, preimages) = erc20.sencode_at(B256::ZERO).unwrap();
+        let decoded = ERC20Storage::sdecode_at(B256::ZERO, preimages, storage_entries).unwrap();
+        assert_eq!(decoded, erc20);
+    }
+
+    #[test]
+    fn test_layout_at() {
+        let base_slot = keccak256("vault.storage");
+        let relocated = RelocatedStorage {
+            owner: Address::repeat_byte(0x42),
+            nonce: 7,
+            name: "relocated".to_string(),
+        };
+
+        assert_eq!(RelocatedStorage::BASE_SLOT, base_slot);
+        assert_eq!(RelocatedStorage::storage_paths().owner.slot(), base_slot);
+
+        let (storage_entries, preimages) = relocated.sencode().unwrap();
+        assert!(storage_entries.contains_key(&base_slot));
+        assert!(!storage_entries.contains_key(&B256::ZERO));
+        let decoded = RelocatedStorage::sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(decoded, relocated);
+    }
+
+    #[test]
+    fn test_vyper_layout() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+
+        let pool = PoolStorage {
+            coins: vec![Address::repeat_byte(1), Address::repeat_byte(2)],
+            name: "Curve.fi Factory Crypto Pool: tricrypto".to_string(),
+            data: BTreeMap::from([(alice, Bytes::from(vec![0xab; 33]))]),
+            route: Route {
+                hops: vec![alice],
+                fee: U256::from(3),
+            },
+            total: U256::from(1000),
+        };
+
+        let paths = PoolStorage::storage_paths();
+        assert_eq!(paths.coins.index(1).slot(), B256::with_last_byte(2));
+        assert_eq!(paths.name.slot(), B256::with_last_byte(4));
+        assert_eq!(paths.route.slot(), B256::with_last_byte(8));
+        assert_eq!(paths.total.slot(), B256::with_last_byte(14));
+
+        let (mut storage_entries, preimages) = pool.sencode().unwrap();
+        assert_eq!(
+            storage_entries.get(&B256::ZERO),
+            Some(&B256::with_last_byte(2))
+        );
+        assert_eq!(
+            storage_entries.get(&B256::with_last_byte(14)),
+            Some(&B256::from(U256::from(1000)))
+        );
+
+        // A popped element is left in the unused capacity.
+        storage_entries.insert(B256::with_last_byte(3), Address::repeat_byte(3).into_word());
+        let decoded = PoolStorage::sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(decoded, pool);
+    }
+
+    #[test]
+    fn test_slot_derivation() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let token = SoladyTokenStorage {
+            totalSupply: U256::from(100),
+            balances: BTreeMap::from([(alice, U256::from(100))]),
+        };
+
+        let (storage_entries, preimages) = token.sencode().unwrap();
+        let balance_slot = keccak256(
+            [
+                alice.as_slice(),
+                alloy_primitives::bytes!("0x000000000000000087a211a2").as_ref(),
+            ]
+            .concat(),
+        );
+        assert_eq!(
+            storage_entries.get(&balance_slot),
+            Some(&B256::from(U256::from(100)))
+        );
+
+        // Paths and views derive the slots with the same scheme.
+        let paths = SoladyTokenStorage::storage_paths();
+        assert_eq!(paths.balances.key(alice).unwrap().slot(), balance_slot);
+        assert_eq!(
+            SoladyTokenView::balances(&mut storage_entries.clone(), alice).unwrap(),
+            U256::from(100)
+        );
+
+        let decoded = SoladyTokenStorage::sdecode(preimages, storage_entries).unwrap();
+        assert_eq!(decoded, token);
+    }
+
+    #[test]
+    fn test_vyper_hashed_keys() {
+        let registry = RegistryStorage {
+            ids: BTreeMap::from([
+                (VyHashedKey::Known("tricrypto".to_string()), U256::from(1)),
+                (VyHashedKey::Hashed(keccak256("stableswap")), U256::from(2)),
+            ]),
+        };
+
+        let paths = RegistryStorage::storage_paths();
+        assert_eq!(
+            paths.ids.key("tricrypto".to_string()).unwrap().slot(),
+            keccak256([B256::ZERO.as_slice(), keccak256("tricrypto").as_slice()].concat())
+        );
+
+        // Only the hashes of the keys are stored.
+        let (storage_entries, mut preimages) = registry.sencode().unwrap();
+        let decoded = RegistryStorage::sdecode(&preimages, storage_entries.clone()).unwrap();
+        assert!(decoded.ids.keys().all(|key| key.known().is_none()));
+
+        preimages.insert(Bytes::from_static(b"tricrypto"));
+        let decoded = RegistryStorage::sdecode(&preimages, storage_entries).unwrap();
+        assert_eq!(decoded, registry);
+    }
+}
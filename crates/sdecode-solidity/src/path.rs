@@ -0,0 +1,373 @@
+use std::{fmt, hash::Hash, marker::PhantomData};
+
+use alloy_primitives::{B256, U256, keccak256};
+use sdecode_core::{MappingEntryLocation, MappingKeySide, MappingSlotDerivation};
+
+use crate::{
+    SolLayoutError, SolLayoutErrorKind, SolMappingKeyType, SolMappingKeyValue, SolStorageFootprint,
+    SolStorageType, data_types, utils::b256_to_u256,
+};
+
+/// Location in storage of a value of the Solidity type `SolT`.
+///
+/// Paths are obtained from [`SolStorageRoot::storage_paths`], then refined with
+/// [`key`](Self::key), [`index`](Self::index) and [`fields`](Self::fields), without reading the
+/// storage.
+pub struct SolStoragePath<SolT> {
+    slot: B256,
+    offset: usize,
+    side: MappingKeySide,
+    derivation: Option<&'static (dyn MappingSlotDerivation + Send + Sync)>,
+    _marker: PhantomData<fn() -> SolT>,
+}
+
+impl<SolT> SolStoragePath<SolT> {
+    pub const fn new(slot: B256, offset: usize, side: MappingKeySide) -> Self {
+        Self {
+            slot,
+            offset,
+            side,
+            derivation: None,
+            _marker: PhantomData,
+        }
+    }
+
+    pub const fn slot(&self) -> B256 {
+        self.slot
+    }
+
+    /// Offset in bytes of the value in its slot, starting from the right.
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub const fn location(&self) -> (B256, usize) {
+        (self.slot, self.offset)
+    }
+
+    /// Side of the mapping keys in the preimages of the mapping slots.
+    pub const fn side(&self) -> MappingKeySide {
+        self.side
+    }
+
+    /// Path of a value located `slot` slots after this one, at `offset`.
+    pub fn child<T>(&self, slot: U256, offset: usize) -> SolStoragePath<T> {
+        let slot = b256_to_u256(self.slot).wrapping_add(slot);
+        SolStoragePath::new(slot.into(), offset, self.side)
+    }
+}
+
+impl<K, V> SolStoragePath<data_types::Mapping<K, V>>
+where
+    K: SolMappingKeyType,
+{
+    /// Derives the slots of the entries with `derivation`. The keys it cannot derive have no
+    /// path.
+    pub const fn with_derivation(
+        mut self,
+        derivation: &'static (dyn MappingSlotDerivation + Send + Sync),
+    ) -> Self {
+        self.derivation = Some(derivation);
+        self
+    }
+
+    /// Scheme deriving the slots of the entries, if not the rule of the language.
+    pub const fn derivation(&self) -> Option<&'static (dyn MappingSlotDerivation + Send + Sync)> {
+        self.derivation
+    }
+
+    /// Path of the value associated to `key`.
+    ///
+    /// Fails with [`UnderivableMappingKey`](SolLayoutErrorKind::UnderivableMappingKey) if the
+    /// attached derivation cannot derive the slot of the entry.
+    pub fn key<Key>(&self, key: Key) -> Result<SolStoragePath<V>, SolLayoutError>
+    where
+        Key: SolMappingKeyValue<K>,
+    {
+        let location = MappingEntryLocation {
+            entry_key: key.into_sol_mapping_key(),
+            mapping_slot: self.slot,
+        };
+        let preimage = match self.derivation {
+            Some(derivation) => derivation.join_key(&location.entry_key).ok_or_else(|| {
+                SolLayoutErrorKind::UnderivableMappingKey {
+                    key: location.entry_key,
+                }
+            })?,
+            None => location.into_preimage(self.side),
+        };
+
+        Ok(SolStoragePath::new(keccak256(preimage), 0, self.side))
+    }
+}
+
+impl<T> SolStoragePath<data_types::Array<T>>
+where
+    T: SolStorageType,
+{
+    /// Path of the element at `index`. The length of the array is not checked.
+    pub fn index(&self, index: usize) -> SolStoragePath<T> {
+        let data = SolStoragePath::<()>::new(keccak256(self.slot), 0, self.side);
+        element_path(&data, index)
+    }
+}
+
+impl<T, const N: usize> SolStoragePath<data_types::FixedArray<T, N>>
+where
+    T: SolStorageType,
+{
+    /// Path of the element at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn index(&self, index: usize) -> SolStoragePath<T> {
+        assert!(
+            index < N,
+            "index out of bounds: the len is {N} but the index is {index}"
+        );
+        element_path(self, index)
+    }
+}
+
+impl<T, const N: usize> SolStoragePath<data_types::VyDynArray<T, N>>
+where
+    T: SolStorageType,
+{
+    /// Path of the element at `index`. The elements follow the length, each starting on a new
+    /// slot. The length of the array is not checked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is beyond the capacity of the array.
+    pub fn index(&self, index: usize) -> SolStoragePath<T> {
+        assert!(
+            index < N,
+            "index out of bounds: the capacity is {N} but the index is {index}"
+        );
+        let slots = T::sol_storage_footprint().slots();
+        self.child(
+            slots
+                .wrapping_mul(U256::from(index))
+                .wrapping_add(U256::from(1)),
+            0,
+        )
+    }
+}
+
+/// Path of the element at `index` of an array whose elements start at `start`.
+fn element_path<A, T>(start: &SolStoragePath<A>, index: usize) -> SolStoragePath<T>
+where
+    T: SolStorageType,
+{
+    match T::sol_storage_footprint() {
+        SolStorageFootprint::Packed(size) => {
+            let per_slot = 32 / size;
+            start.child(U256::from(index / per_slot), (index % per_slot) * size)
+        }
+        SolStorageFootprint::Slots(slots) => start.child(slots.wrapping_mul(U256::from(index)), 0),
+    }
+}
+
+/// Structures and contracts generated by `sol_storage!`, whose fields have their own paths.
+pub trait SolStorageFields: Sized {
+    /// Paths of the fields, generated by `sol_storage!`.
+    type Fields;
+
+    /// Paths of the fields of the value located at `path`.
+    fn fields(path: &SolStoragePath<Self>) -> Self::Fields;
+}
+
+impl<T> SolStoragePath<T>
+where
+    T: SolStorageFields,
+{
+    /// Paths of the fields of the structure or contract.
+    pub fn fields(&self) -> T::Fields {
+        T::fields(self)
+    }
+}
+
+/// Contracts generated by `sol_storage!`, and structures declared as namespaced roots.
+pub trait SolStorageRoot: SolStorageFields {
+    /// Side of the mapping keys in the preimages, depending on the language of the contract.
+    const MAPPING_KEY_SIDE: MappingKeySide;
+
+    /// Slot at which the layout starts, zero unless the root is namespaced.
+    const BASE_SLOT: B256 = B256::ZERO;
+
+    /// Paths of the state variables.
+    fn storage_paths() -> Self::Fields {
+        Self::storage_paths_at(Self::BASE_SLOT)
+    }
+
+    /// Paths of the state variables, with the layout starting at `base_slot`.
+    fn storage_paths_at(base_slot: B256) -> Self::Fields {
+        SolStoragePath::<Self>::new(base_slot, 0, Self::MAPPING_KEY_SIDE).fields()
+    }
+}
+
+impl<SolT> fmt::Debug for SolStoragePath<SolT> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SolStoragePath")
+            .field("slot", &self.slot)
+            .field("offset", &self.offset)
+            .field("side", &self.side)
+            .field("derivation", &self.derivation)
+            .finish()
+    }
+}
+
+impl<SolT> Clone for SolStoragePath<SolT> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<SolT> Copy for SolStoragePath<SolT> {}
+
+impl<SolT> SolStoragePath<SolT> {
+    /// Address of the derivation, which identifies it.
+    fn derivation_addr(&self) -> Option<*const ()> {
+        self.derivation
+            .map(|derivation| std::ptr::from_ref(derivation).cast::<()>())
+    }
+}
+
+impl<SolT> PartialEq for SolStoragePath<SolT> {
+    fn eq(&self, other: &Self) -> bool {
+        self.location() == other.location()
+            && self.side == other.side
+            && self.derivation_addr() == other.derivation_addr()
+    }
+}
+
+impl<SolT> Eq for SolStoragePath<SolT> {}
+
+impl<SolT> Hash for SolStoragePath<SolT> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.slot.hash(state);
+        self.offset.hash(state);
+        self.side.hash(state);
+        self.derivation_addr().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, address};
+
+    use super::*;
+
+    crate::sol_storage! {
+        contract Token {
+            struct Item {
+                uint128 x;
+                uint128 y;
+                address owner;
+            }
+
+            mapping(address => uint256) balanceOf;
+            mapping(address => mapping(address => uint256)) allowance;
+            Item[] items;
+            uint64[] history;
+            uint16[20] weights;
+            Item item;
+        }
+
+        contract Packed {
+            // keccak256(account . seed), with accounts on 20 bytes.
+            #[sdecode(derivation = sdecode_core::PackedSlotDerivation::new()
+                .key(20)
+                .seed(alloy_primitives::bytes!("0x87a211a2")))]
+            mapping(uint256 => uint256) balances;
+        }
+    }
+
+    fn slot(n: u64) -> B256 {
+        U256::from(n).into()
+    }
+
+    fn hash_key(key: Address, slot: B256) -> B256 {
+        keccak256([key.into_word().as_slice(), slot.as_slice()].concat())
+    }
+
+    fn add(slot: B256, n: u64) -> B256 {
+        (b256_to_u256(slot) + U256::from(n)).into()
+    }
+
+    #[test]
+    fn test_storage_paths() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+
+        let paths = TokenStorage::storage_paths();
+
+        assert_eq!(paths.balanceOf.location(), (slot(0), 0));
+        assert_eq!(
+            paths.balanceOf.key(alice).unwrap().location(),
+            (hash_key(alice, slot(0)), 0)
+        );
+        assert_eq!(
+            paths
+                .allowance
+                .key(alice)
+                .unwrap()
+                .key(bob)
+                .unwrap()
+                .location(),
+            (hash_key(bob, hash_key(alice, slot(1))), 0)
+        );
+
+        let items = keccak256(slot(2));
+        assert_eq!(
+            paths.items.index(3).fields().owner.location(),
+            (add(items, 7), 0)
+        );
+        assert_eq!(
+            paths.items.index(3).fields().y.location(),
+            (add(items, 6), 16)
+        );
+
+        let history = keccak256(slot(3));
+        assert_eq!(paths.history.index(5).location(), (add(history, 1), 8));
+
+        assert_eq!(paths.weights.index(17).location(), (slot(5), 2));
+        assert_eq!(paths.item.fields().owner.location(), (slot(7), 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_storage_paths_out_of_bounds() {
+        TokenStorage::storage_paths().weights.index(20);
+    }
+
+    #[test]
+    fn test_storage_paths_underivable_key() {
+        let paths = PackedStorage::storage_paths();
+
+        let account = U256::from(0xa11ce);
+        assert_eq!(
+            paths.balances.key(account).unwrap().location(),
+            (
+                keccak256(
+                    [
+                        &account.to_be_bytes::<32>()[12..],
+                        &[0x87, 0xa2, 0x11, 0xa2]
+                    ]
+                    .concat()
+                ),
+                0
+            )
+        );
+
+        // The key does not fit on the 20 bytes of the scheme.
+        let err = paths.balances.key(U256::MAX).unwrap_err();
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::UnderivableMappingKey {
+                key: U256::MAX.to_be_bytes::<32>().into(),
+            }
+        );
+    }
+}
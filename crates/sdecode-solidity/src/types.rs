@@ -6,35 +6,139 @@ pub use alloy_sol_types::sol_data::{
     String, SupportedFixedBytes, SupportedInt, Uint,
 };
 
+use alloy_primitives::{B256, U256};
+use sdecode_core::{SlotBackend, StorageError, StorageNode, StorageStructure};
+
+use crate::{
+    SolLayoutError, SolStorageDynType, SolStorageFootprint,
+    view::{
+        load_bytes, load_dynamic_array, load_elements, load_footprint, load_vyper_bytes,
+        load_vyper_dynamic_array,
+    },
+};
+
 pub trait SolStorageType {
     const SOL_STORAGE_NAME: &'static str;
+
+    /// Runtime description of the type.
+    fn sol_storage_dyn_type() -> SolStorageDynType;
+
+    /// Space taken by a value of the type in storage.
+    fn sol_storage_footprint() -> SolStorageFootprint;
+
+    /// Reads from `backend` the slots occupied by a value of the type located at `slot`. The
+    /// entries of the mappings are not read.
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_footprint(backend, slot, Self::sol_storage_footprint())
+    }
 }
 pub trait SolMappingKeyType: SolStorageType {}
 
 pub struct Mapping<K, V>(PhantomData<(K, V)>);
 
+/// Vyper `DynArray[T, N]`.
+pub struct VyDynArray<T, const N: usize>(PhantomData<T>);
+
+/// Vyper `Bytes[N]`.
+pub struct VyBytes<const N: usize>;
+
+/// Vyper `String[N]`.
+pub struct VyString<const N: usize>;
+
+/// Vyper `Bytes` as a mapping key, hashed with keccak256 before locating the entry.
+pub struct VyHashedBytes;
+
+/// Vyper `String` as a mapping key, hashed with keccak256 before locating the entry.
+pub struct VyHashedString;
+
 impl SolStorageType for Bool {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Bool
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(1)
+    }
 }
 impl SolMappingKeyType for Bool {}
 
 impl SolStorageType for Address {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Address
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(20)
+    }
 }
 impl SolMappingKeyType for Address {}
 
 impl SolStorageType for Function {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Function
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(24)
+    }
 }
 impl SolMappingKeyType for Function {}
 
 impl SolStorageType for Bytes {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Bytes
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_bytes(backend, slot)
+    }
 }
 impl SolMappingKeyType for Bytes {}
 
 impl SolStorageType for String {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::String
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_bytes(backend, slot)
+    }
 }
 impl SolMappingKeyType for String {}
 
@@ -43,6 +147,14 @@ where
     ByteCount<N>: SupportedFixedBytes,
 {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::FixedBytes { size: N }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(N)
+    }
 }
 impl<const N: usize> SolMappingKeyType for FixedBytes<N> where ByteCount<N>: SupportedFixedBytes {}
 
@@ -51,6 +163,14 @@ where
     IntBitCount<N>: SupportedInt,
 {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Uint { bits: N }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(N / 8)
+    }
 }
 impl<const N: usize> SolMappingKeyType for Uint<N> where IntBitCount<N>: SupportedInt {}
 
@@ -59,6 +179,14 @@ where
     IntBitCount<N>: SupportedInt,
 {
     const SOL_STORAGE_NAME: &'static str = <Self as SolType>::SOL_NAME;
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Int { bits: N }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Packed(N / 8)
+    }
 }
 impl<const N: usize> SolMappingKeyType for Int<N> where IntBitCount<N>: SupportedInt {}
 
@@ -69,6 +197,27 @@ impl<T: SolStorageType, const N: usize> SolStorageType for FixedArray<T, N> {
         .write_usize(N)
         .write_byte(b']')
         .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::FixedArray {
+            element: Box::new(T::sol_storage_dyn_type()),
+            len: N,
+        }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::fixed_array(T::sol_storage_footprint(), N)
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_elements::<T, _>(backend, slot, N)
+    }
 }
 
 impl<T: SolStorageType> SolStorageType for Array<T> {
@@ -76,6 +225,26 @@ impl<T: SolStorageType> SolStorageType for Array<T> {
         .write_str(T::SOL_STORAGE_NAME)
         .write_str("[]")
         .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Array {
+            element: Box::new(T::sol_storage_dyn_type()),
+        }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_dynamic_array::<T, _>(backend, slot)
+    }
 }
 
 impl<K: SolMappingKeyType, V: SolStorageType> SolStorageType for Mapping<K, V> {
@@ -86,7 +255,138 @@ impl<K: SolMappingKeyType, V: SolStorageType> SolStorageType for Mapping<K, V> {
         .write_str(V::SOL_STORAGE_NAME)
         .write_byte(b')')
         .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::Mapping {
+            key: Box::new(K::sol_storage_dyn_type()),
+            value: Box::new(V::sol_storage_dyn_type()),
+        }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
+
+    /// The entries are not read, as their keys cannot be known from the storage alone.
+    fn sol_storage_load<B>(
+        _backend: &mut B,
+        _slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        Ok(StorageStructure::single_node(StorageNode::empty()))
+    }
+}
+
+impl<T: SolStorageType, const N: usize> SolStorageType for VyDynArray<T, N> {
+    const SOL_STORAGE_NAME: &'static str = NameBuffer::new()
+        .write_str("DynArray[")
+        .write_str(T::SOL_STORAGE_NAME)
+        .write_str(", ")
+        .write_usize(N)
+        .write_byte(b']')
+        .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::VyDynArray {
+            element: Box::new(T::sol_storage_dyn_type()),
+            max_len: N,
+        }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::vyper_dynamic_array(T::sol_storage_footprint(), N)
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_vyper_dynamic_array::<T, _>(backend, slot, N)
+    }
+}
+
+impl<const N: usize> SolStorageType for VyBytes<N> {
+    const SOL_STORAGE_NAME: &'static str = NameBuffer::new()
+        .write_str("Bytes[")
+        .write_usize(N)
+        .write_byte(b']')
+        .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::VyBytes { max_len: N }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::vyper_bytes(N)
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_vyper_bytes(backend, slot, N)
+    }
+}
+
+impl<const N: usize> SolStorageType for VyString<N> {
+    const SOL_STORAGE_NAME: &'static str = NameBuffer::new()
+        .write_str("String[")
+        .write_usize(N)
+        .write_byte(b']')
+        .as_str();
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::VyString { max_len: N }
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::vyper_bytes(N)
+    }
+
+    fn sol_storage_load<B>(
+        backend: &mut B,
+        slot: B256,
+    ) -> Result<StorageStructure, StorageError<B::Error, SolLayoutError>>
+    where
+        B: SlotBackend,
+    {
+        load_vyper_bytes(backend, slot, N)
+    }
+}
+
+impl SolStorageType for VyHashedBytes {
+    const SOL_STORAGE_NAME: &'static str = "Bytes";
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::VyHashedBytes
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
+}
+impl SolMappingKeyType for VyHashedBytes {}
+
+impl SolStorageType for VyHashedString {
+    const SOL_STORAGE_NAME: &'static str = "String";
+
+    fn sol_storage_dyn_type() -> SolStorageDynType {
+        SolStorageDynType::VyHashedString
+    }
+
+    fn sol_storage_footprint() -> SolStorageFootprint {
+        SolStorageFootprint::Slots(U256::from(1))
+    }
 }
+impl SolMappingKeyType for VyHashedString {}
 
 const NAME_CAP: usize = 256;
 
@@ -174,4 +474,20 @@ mod tests {
             "mapping(bool => uint136)"
         );
     }
+
+    #[test]
+    fn test_vyper_names() {
+        assert_eq!(
+            <VyDynArray<Address, 8> as SolStorageType>::SOL_STORAGE_NAME,
+            "DynArray[address, 8]"
+        );
+        assert_eq!(
+            <VyString<64> as SolStorageType>::SOL_STORAGE_NAME,
+            "String[64]"
+        );
+        assert_eq!(
+            <VyBytes<100> as SolStorageType>::sol_storage_footprint(),
+            SolStorageFootprint::Slots(U256::from(5))
+        );
+    }
 }
@@ -1 +1,568 @@
+//! Layout inference for contracts whose source is not available.
 
+use alloy_primitives::{B256, Bytes, U256};
+use sdecode_core::{
+    MappingKeySide, Storage, StorageDiagnostic, StorageDiagnostics, StorageNode, StorageStructure,
+};
+
+use crate::{
+    SolLayoutError, SolStorageDynField, SolStorageDynLayout, SolStorageDynLayoutDecode,
+    SolStorageDynType, SolStorageDynValue, SolStorageDynVariable,
+};
+
+/// Name of the contract of the inferred layouts.
+const CONTRACT_NAME: &str = "Unknown";
+
+/// Layout guessed from a storage alone, along with the values it decodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolStorageInference {
+    pub layout: SolStorageDynLayout,
+    /// State variables decoded with the inferred layout.
+    pub value: SolStorageDynValue,
+    /// Violations of the inferred layout, skipped while decoding.
+    pub diagnostics: Vec<StorageDiagnostic>,
+}
+
+impl SolStorageInference {
+    /// Infers the layout of `storage`, and decodes it in
+    /// [`DecodeMode::Lenient`](sdecode_core::DecodeMode::Lenient).
+    pub fn infer(storage: &Storage) -> Result<Self, SolLayoutError> {
+        let layout = infer_storage_layout(storage);
+
+        let diagnostics = StorageDiagnostics::new(MappingKeySide::SOLIDITY);
+        let value = layout.decode_with(&mut storage.clone(), Some(diagnostics.clone()))?;
+
+        Ok(Self {
+            layout,
+            value,
+            diagnostics: diagnostics.take(),
+        })
+    }
+}
+
+/// Guesses the state variables of a Solidity contract from its decoded storage.
+///
+/// Each anchor becomes a variable named after its slot. Mappings are recognized by their hashed
+/// entries, dynamic arrays, `bytes` and `string` by the data stored under the empty key, and
+/// words are split into an address followed by flags when they look like one. Anything else is
+/// an `uint256`.
+pub fn infer_storage_layout(storage: &Storage) -> SolStorageDynLayout {
+    let mut inferrer = Inferrer::default();
+    let mut variables = Vec::new();
+
+    for (slot, node) in &storage.anchors {
+        if node.is_zero() {
+            continue;
+        }
+
+        let slot = U256::from_be_bytes(slot.0);
+        let fields = inferrer.slot_fields(&[node]);
+        let is_packed = fields.len() > 1;
+
+        let mut offset = 0;
+        for ty in fields {
+            let name = if is_packed {
+                format!("slot_0x{slot:x}_{offset}")
+            } else {
+                format!("slot_0x{slot:x}")
+            };
+            let size = packed_size(&ty);
+            variables.push(SolStorageDynVariable {
+                name,
+                contract: CONTRACT_NAME.to_string(),
+                slot,
+                offset,
+                ty,
+            });
+            offset += size;
+        }
+    }
+
+    SolStorageDynLayout {
+        name: CONTRACT_NAME.to_string(),
+        variables,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inferrer {
+    /// Number of structs inferred so far, used to name them.
+    structs: usize,
+}
+
+impl Inferrer {
+    /// Types of the values starting at a slot, from samples of its nodes. Several value types are
+    /// packed from the lowest bytes of the slot.
+    fn slot_fields(&mut self, nodes: &[&StorageNode]) -> Vec<SolStorageDynType> {
+        let keys: Vec<&Bytes> = nodes.iter().flat_map(|node| node.children.keys()).collect();
+
+        if keys.is_empty() {
+            if let Some(ty) = bytes_type(nodes) {
+                return vec![ty];
+            }
+            return word_fields(nodes);
+        }
+
+        if keys.iter().all(|key| key.is_empty()) {
+            let ty = bytes_type(nodes).unwrap_or_else(|| self.array_type(nodes));
+            return vec![ty];
+        }
+
+        if keys.iter().all(|key| !key.is_empty()) && nodes.iter().all(|node| node.value().is_zero())
+        {
+            let values: Vec<&StorageStructure> = nodes
+                .iter()
+                .flat_map(|node| node.children.values())
+                .collect();
+            return vec![SolStorageDynType::Mapping {
+                key: Box::new(key_type(&keys)),
+                value: Box::new(self.structure_type(&values)),
+            }];
+        }
+
+        // The hashed values cannot be explained by a single type.
+        word_fields(nodes)
+    }
+
+    /// Type of the values spanning the given structures, such as mapping values.
+    fn structure_type(&mut self, structures: &[&StorageStructure]) -> SolStorageDynType {
+        let slots = structures
+            .iter()
+            .map(|structure| structure.len())
+            .max()
+            .unwrap_or_default()
+            .max(1);
+
+        let groups: Vec<_> = (0..slots)
+            .map(|i| {
+                let nodes: Vec<&StorageNode> = structures
+                    .iter()
+                    .filter_map(|structure| structure.get(i))
+                    .collect();
+                self.slot_fields(&nodes)
+            })
+            .collect();
+
+        match <[_; 1]>::try_from(groups) {
+            Ok([mut fields]) if fields.len() == 1 => fields.remove(0),
+            Ok([fields]) => self.struct_type(vec![fields]),
+            Err(groups) => self.struct_type(groups),
+        }
+    }
+
+    /// Struct with the fields of each of its slots.
+    fn struct_type(&mut self, mut groups: Vec<Vec<SolStorageDynType>>) -> SolStorageDynType {
+        // A value type at the start of a slot would be packed in the previous slot if it fits,
+        // so the last field of the previous slot is widened to fill it.
+        for i in 1..groups.len() {
+            let used: usize = groups[i - 1].iter().map(packed_size).sum();
+            let Some(first) = groups[i].first() else {
+                continue;
+            };
+            if !is_value_type(first) || packed_size(first) > 32 - used {
+                continue;
+            }
+            if let Some(last) = groups[i - 1].last_mut() {
+                let bits = (packed_size(last) + 32 - used) * 8;
+                *last = SolStorageDynType::Uint { bits };
+            }
+        }
+
+        let mut fields = Vec::new();
+        for (i, group) in groups.into_iter().enumerate() {
+            let is_packed = group.len() > 1;
+            let mut offset = 0;
+            for ty in group {
+                let name = if is_packed {
+                    format!("slot{i}_{offset}")
+                } else {
+                    format!("slot{i}")
+                };
+                offset += packed_size(&ty);
+                fields.push(SolStorageDynField { name, ty });
+            }
+        }
+
+        let name = format!("Struct{}", self.structs);
+        self.structs += 1;
+        SolStorageDynType::Struct { name, fields }
+    }
+
+    /// Dynamic array whose length is the value of the nodes and whose elements are stored under
+    /// the empty key.
+    fn array_type(&mut self, nodes: &[&StorageNode]) -> SolStorageDynType {
+        let arrays: Vec<(u64, &StorageStructure)> = nodes
+            .iter()
+            .filter_map(|node| {
+                let len = u64::try_from(U256::from_be_bytes(node.value().0)).ok()?;
+                let data = node.children.get(&Bytes::new())?;
+                (len > 0).then_some((len, data))
+            })
+            .collect();
+
+        let slots_per_element = arrays
+            .iter()
+            .map(|(len, data)| (data.len() as u64).div_ceil(*len))
+            .max()
+            .unwrap_or(1);
+
+        let element = if slots_per_element > 1 {
+            let elements: Vec<StorageStructure> = arrays
+                .iter()
+                .flat_map(|(_, data)| data.chunks(slots_per_element as usize))
+                .map(|chunk| StorageStructure(chunk.to_vec()))
+                .collect();
+            self.structure_type(&elements.iter().collect::<Vec<_>>())
+        } else {
+            match packed_element_size(&arrays) {
+                Some(size) => SolStorageDynType::Uint { bits: size * 8 },
+                None => {
+                    let elements: Vec<StorageStructure> = arrays
+                        .iter()
+                        .flat_map(|(_, data)| data.iter())
+                        .map(|node| StorageStructure::single_node(node.clone()))
+                        .collect();
+                    self.structure_type(&elements.iter().collect::<Vec<_>>())
+                }
+            }
+        };
+
+        SolStorageDynType::Array {
+            element: Box::new(element),
+        }
+    }
+}
+
+/// Size of the elements of arrays packing several of them in each slot, if they look so: the
+/// smallest size fitting the data of all the arrays, with two non-zero elements in a same slot.
+fn packed_element_size(arrays: &[(u64, &StorageStructure)]) -> Option<usize> {
+    [1, 2, 4, 8, 16].into_iter().find(|&size| {
+        let per_slot = (32 / size) as u64;
+
+        let fits = arrays.iter().all(|(len, data)| {
+            let Some(last) = data.last() else {
+                return true;
+            };
+            let slots = data.len() as u64;
+            if slots > len.div_ceil(per_slot) {
+                return false;
+            }
+            // Trailing zero slots are not stored, so the last one may be full.
+            let count = (len - (slots - 1) * per_slot).min(per_slot) as usize;
+            last.value()[..32 - count * size].iter().all(|b| *b == 0)
+        });
+
+        let shares_slots = arrays.iter().flat_map(|(_, data)| data.iter()).any(|node| {
+            node.value()
+                .chunks(size)
+                .filter(|element| element.iter().any(|b| *b != 0))
+                .count()
+                >= 2
+        });
+
+        fits && shares_slots
+    })
+}
+
+/// `string` or `bytes`, if all the nodes are valid encodings of one of them. Since short values
+/// look like integers, `bytes` are only recognized when at least one of them is long.
+fn bytes_type(nodes: &[&StorageNode]) -> Option<SolStorageDynType> {
+    let mut is_string = true;
+    let mut is_long = false;
+    let mut is_empty = true;
+
+    for node in nodes {
+        if node.is_zero() {
+            continue;
+        }
+        let data = bytes_data(node)?;
+        is_empty = false;
+        is_long |= data.len() >= 32;
+        is_string &= std::str::from_utf8(&data)
+            .is_ok_and(|string| !string.chars().any(|c| c.is_control() && c != '\n'));
+    }
+
+    if is_empty {
+        None
+    } else if is_string {
+        Some(SolStorageDynType::String)
+    } else if is_long {
+        Some(SolStorageDynType::Bytes)
+    } else {
+        None
+    }
+}
+
+/// Data of a node holding `bytes` or a `string`, if it is a valid encoding of one.
+fn bytes_data(node: &StorageNode) -> Option<Vec<u8>> {
+    let word = node.value();
+    let last_byte = word[31];
+
+    if last_byte % 2 == 0 {
+        let len = usize::from(last_byte / 2);
+        let is_valid =
+            node.children.is_empty() && len <= 31 && word[len..31].iter().all(|b| *b == 0);
+        return is_valid.then(|| word[..len].to_vec());
+    }
+
+    let len =
+        usize::try_from((U256::from_be_bytes(word.0) - U256::from(1)) / U256::from(2)).ok()?;
+    if len < 32 || node.children.len() != 1 {
+        return None;
+    }
+    // The chunks end with the last non-zero one, which must be the last chunk of the data. This
+    // also bounds the buffer by the words found in storage, whatever the length claims.
+    let chunks = node.children.get(&Bytes::new())?;
+    if chunks.len() != len.div_ceil(32) {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(chunks.len() * 32);
+    for chunk in chunks.iter() {
+        if !chunk.children.is_empty() {
+            return None;
+        }
+        data.extend_from_slice(chunk.value().as_slice());
+    }
+    if data[len..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    data.truncate(len);
+    Some(data)
+}
+
+/// Value types packed in the words of the nodes: an address followed by flags or a small integer
+/// when all the words look so, or a full `uint256`.
+fn word_fields(nodes: &[&StorageNode]) -> Vec<SolStorageDynType> {
+    let words: Vec<B256> = nodes
+        .iter()
+        .map(|node| node.value())
+        .filter(|word| !word.is_zero())
+        .collect();
+
+    if words.is_empty() || !words.iter().all(is_packed_address) {
+        return vec![SolStorageDynType::Uint { bits: 256 }];
+    }
+
+    // Number of bytes used above the address.
+    let extra = words
+        .iter()
+        .map(|word| {
+            word[..12]
+                .iter()
+                .position(|b| *b != 0)
+                .map_or(0, |i| 12 - i)
+        })
+        .max()
+        .unwrap_or_default();
+
+    let mut fields = vec![SolStorageDynType::Address];
+    if words
+        .iter()
+        .all(|word| word[12 - extra..12].iter().all(|b| *b <= 1))
+    {
+        fields.extend(std::iter::repeat_n(SolStorageDynType::Bool, extra));
+    } else {
+        fields.push(SolStorageDynType::Uint { bits: extra * 8 });
+    }
+    fields
+}
+
+/// Whether the lowest bytes of the word look like an address, and the highest one is unused.
+fn is_packed_address(word: &B256) -> bool {
+    word[0] == 0 && is_address(&word[12..])
+}
+
+/// Whether the bytes look like an address rather than a small integer. Vanity addresses may
+/// start with several zero bytes, while integers rarely reach 2^96.
+fn is_address(bytes: &[u8]) -> bool {
+    bytes[..8].iter().any(|b| *b != 0)
+}
+
+/// Type of the keys of a mapping.
+fn key_type(keys: &[&Bytes]) -> SolStorageDynType {
+    if keys.iter().all(|key| key.len() == 32) {
+        if keys
+            .iter()
+            .all(|key| key[..12].iter().all(|b| *b == 0) && is_address(&key[12..]))
+        {
+            SolStorageDynType::Address
+        } else if keys.iter().all(|key| key[..24].iter().all(|b| *b == 0)) {
+            SolStorageDynType::Uint { bits: 256 }
+        } else {
+            SolStorageDynType::FixedBytes { size: 32 }
+        }
+    } else if keys.iter().all(|key| std::str::from_utf8(key).is_ok()) {
+        SolStorageDynType::String
+    } else {
+        SolStorageDynType::Bytes
+    }
+}
+
+fn is_value_type(ty: &SolStorageDynType) -> bool {
+    packed_size(ty) < 32 || matches!(ty, SolStorageDynType::Uint { .. })
+}
+
+/// Size in bytes of a value type. Other types take whole slots.
+fn packed_size(ty: &SolStorageDynType) -> usize {
+    match ty {
+        SolStorageDynType::Bool | SolStorageDynType::Enum { .. } => 1,
+        SolStorageDynType::Uint { bits } | SolStorageDynType::Int { bits } => bits / 8,
+        SolStorageDynType::Address => 20,
+        SolStorageDynType::FixedBytes { size } => *size,
+        SolStorageDynType::Function => 24,
+        _ => 32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy_primitives::address;
+    use sdecode_core::StorageEncode;
+
+    use super::*;
+
+    use self::token::Position;
+
+    crate::sol_storage! {
+        contract Token {
+            struct Position {
+                uint256 amount;
+                address owner;
+            }
+
+            address owner;
+            bool paused;
+            bool initialized;
+            uint256 totalSupply;
+            string symbol;
+            string description;
+            mapping(address => uint256) balances;
+            address[] holders;
+            mapping(uint256 => Position) positions;
+            uint16[] weights;
+        }
+    }
+
+    #[test]
+    fn test_infer_storage_layout() {
+        let alice = address!("0xa11ce00000000000000000000000000000000001");
+        // A vanity address, starting with zero bytes.
+        let bob = address!("0x00000000000000adc04c56bf30ac9d3c0aaf14dc");
+
+        let token = TokenStorage {
+            owner: alice,
+            paused: false,
+            initialized: true,
+            totalSupply: U256::from(10).pow(U256::from(24)),
+            symbol: "TKN".to_string(),
+            description: "a description long enough to be stored out of its slot".to_string(),
+            balances: BTreeMap::from([(alice, U256::from(100)), (bob, U256::from(200))]),
+            holders: vec![alice, bob],
+            positions: BTreeMap::from([
+                (
+                    U256::from(1),
+                    Position {
+                        amount: U256::from(5),
+                        owner: alice,
+                    },
+                ),
+                (
+                    U256::from(2),
+                    Position {
+                        amount: U256::from(6),
+                        owner: bob,
+                    },
+                ),
+            ]),
+            weights: vec![1, 2, 3, 4, 5],
+        };
+        let (storage_entries, preimages) = token.sencode().unwrap();
+        let storage =
+            Storage::decode(&preimages, storage_entries, MappingKeySide::SOLIDITY).unwrap();
+
+        let inference = SolStorageInference::infer(&storage).unwrap();
+        assert_eq!(inference.diagnostics, []);
+
+        let variables = inference
+            .layout
+            .variables
+            .iter()
+            .map(|variable| (variable.name.as_str(), variable.ty.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variables,
+            [
+                ("slot_0x0_0", "address".to_string()),
+                ("slot_0x0_20", "bool".to_string()),
+                ("slot_0x0_21", "bool".to_string()),
+                ("slot_0x1", "uint256".to_string()),
+                ("slot_0x2", "string".to_string()),
+                ("slot_0x3", "string".to_string()),
+                ("slot_0x4", "mapping(address => uint256)".to_string()),
+                ("slot_0x5", "address[]".to_string()),
+                ("slot_0x6", "mapping(uint256 => Struct0)".to_string()),
+                ("slot_0x7", "uint16[]".to_string()),
+            ]
+        );
+
+        let value = &inference.value;
+        assert_eq!(
+            value.field("slot_0x0_0"),
+            Some(&SolStorageDynValue::Address(alice))
+        );
+        assert_eq!(
+            value.field("slot_0x0_21"),
+            Some(&SolStorageDynValue::Bool(true))
+        );
+        assert_eq!(
+            value.field("slot_0x3"),
+            Some(&SolStorageDynValue::String(token.description.clone()))
+        );
+        assert_eq!(
+            value
+                .field("slot_0x4")
+                .unwrap()
+                .get(&SolStorageDynValue::Address(bob)),
+            Some(&SolStorageDynValue::Uint(U256::from(200)))
+        );
+        assert_eq!(
+            value
+                .field("slot_0x6")
+                .unwrap()
+                .get(&SolStorageDynValue::Uint(U256::from(2)))
+                .unwrap()
+                .field("slot1"),
+            Some(&SolStorageDynValue::Address(bob))
+        );
+        assert_eq!(
+            value.field("slot_0x7"),
+            Some(&SolStorageDynValue::Array(
+                (1..=5u64)
+                    .map(|weight| SolStorageDynValue::Uint(U256::from(weight)))
+                    .collect()
+            ))
+        );
+
+        assert!(
+            infer_storage_layout(&Storage::default())
+                .variables
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_bytes_data() {
+        let chunk = StorageStructure(vec![StorageNode::word(B256::repeat_byte(0x61))]);
+        let long = |len: u64| {
+            StorageNode::word(B256::from(U256::from(len * 2 + 1)))
+                .with_child(Bytes::new(), chunk.clone())
+        };
+
+        assert_eq!(bytes_data(&long(32)), Some(vec![0x61; 32]));
+        assert_eq!(bytes_data(&long(33)), None);
+        assert_eq!(bytes_data(&long(1 << 40)), None);
+    }
+}
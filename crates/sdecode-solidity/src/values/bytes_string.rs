@@ -3,14 +3,16 @@ use crate::{
     utils::{b256_to_u256, slice_is_zero},
 };
 
-use super::{SolLayoutError, SolStorageValue};
+use super::{SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageValue};
 use alloy_primitives::{
-    B256, Bytes,
+    B256, Bytes, U256,
     bytes::{self, BufMut, BytesMut},
     uint,
 };
 use overf::checked;
-use sdecode_core::{IntoStorageReader, StorageReader, StorageReaderNext};
+use sdecode_core::{
+    StorageNodeChildren, StorageReader, StorageReaderNext, StorageStructure, StorageWriter,
+};
 
 impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
     /// # [`bytes` and `string`](https://docs.soliditylang.org/en/latest/internals/layout_in_storage.html#bytes-and-string)
@@ -41,7 +43,7 @@ impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
         let next = storage_reader.next_or_default::<B256>();
 
         if next.is_remaining_not_zero() {
-            return Err(SolLayoutError::remaining_bytes(remaining));
+            return Err(SolLayoutError::remaining_bytes(next.remaining));
         }
 
         let StorageReaderNext {
@@ -51,36 +53,42 @@ impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
         let last_byte = word[31];
         let is_short = last_byte % 2 == 0;
         if is_short {
-            if !children.is_empty() {
-                return Err(SolLayoutError::Err);
-            }
+            storage_reader.reject_children(children)?;
             let size = (last_byte / 2) as usize;
+            if size > 31 {
+                return Err(SolLayoutErrorKind::LengthOverflow {
+                    length: U256::from(size),
+                }
+                .into());
+            }
             let remaining = &word[size..31];
             if slice_is_zero(remaining) {
                 Ok(Self::copy_from_slice(&word[0..size]))
             } else {
-                Err(SolLayoutError::remaining_bytes(Bytes::copy_from_slice(
-                    remaining,
-                )))
+                Err(SolLayoutErrorKind::BytesTail {
+                    tail: Bytes::copy_from_slice(remaining),
+                }
+                .into())
             }
         } else {
             let size = checked! { (b256_to_u256(word) - uint!(1_U256)) / uint!(2_U256) };
-            let mut size = u64::try_from(size).unwrap();
+            let Ok(mut size) = u64::try_from(size) else {
+                return Err(SolLayoutErrorKind::LengthOverflow { length: size }.into());
+            };
 
-            let child = children.remove(&Bytes::new()).unwrap_or_default();
+            let key = Bytes::new();
+            let child = children.remove(&key).unwrap_or_default();
 
-            if !children.is_empty() {
-                return Err(SolLayoutError::Err);
-            }
+            storage_reader.reject_children(children)?;
 
-            let mut child_storage_reader = child.into_storage_reader();
+            let mut child_storage_reader = storage_reader.child_reader(&key, child);
 
             let mut buf = BytesMut::new();
             while let Some(new_size) = size.checked_sub(32) {
                 let next = child_storage_reader.next_or_default::<B256>();
 
-                if !next.is_remaining_zero() {
-                    return Err(SolLayoutError::Err);
+                if next.is_remaining_not_zero() {
+                    return Err(SolLayoutError::remaining_bytes(next.remaining));
                 }
 
                 let StorageReaderNext {
@@ -89,9 +97,7 @@ impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
                     ..
                 } = next;
 
-                if !children.is_empty() {
-                    return Err(SolLayoutError::Err);
-                }
+                child_storage_reader.reject_children(children)?;
                 buf.put_slice(chunk.as_ref());
                 size = new_size;
             }
@@ -99,8 +105,8 @@ impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
             if size > 0 {
                 let next = child_storage_reader.next_or_default::<B256>();
 
-                if !next.is_remaining_zero() {
-                    return Err(SolLayoutError::Err);
+                if next.is_remaining_not_zero() {
+                    return Err(SolLayoutError::remaining_bytes(next.remaining));
                 }
 
                 let StorageReaderNext {
@@ -109,15 +115,16 @@ impl SolStorageValue<data_types::Bytes> for bytes::Bytes {
                     ..
                 } = next;
 
-                if !children.is_empty() {
-                    return Err(SolLayoutError::Err);
-                }
+                child_storage_reader.reject_children(children)?;
                 buf.put_slice(&chunk[0..(size as usize)]);
 
                 let remaining = &chunk[(size as usize)..];
 
                 if !slice_is_zero(remaining) {
-                    return Err(SolLayoutError::Err);
+                    return Err(SolLayoutErrorKind::BytesTail {
+                        tail: Bytes::copy_from_slice(remaining),
+                    }
+                    .into());
                 }
             }
 
@@ -131,7 +138,8 @@ impl SolStorageValue<data_types::Bytes> for Bytes {
     where
         Reader: StorageReader,
     {
-        let b = bytes::Bytes::decode_storage(storage_reader)?;
+        let b =
+            <bytes::Bytes as SolStorageValue<data_types::Bytes>>::decode_storage(storage_reader)?;
         Ok(b.into())
     }
 }
@@ -141,7 +149,8 @@ impl SolStorageValue<data_types::Bytes> for Vec<u8> {
     where
         Reader: StorageReader,
     {
-        let b = bytes::Bytes::decode_storage(storage_reader)?;
+        let b =
+            <bytes::Bytes as SolStorageValue<data_types::Bytes>>::decode_storage(storage_reader)?;
         Ok(b.into())
     }
 }
@@ -151,7 +160,110 @@ impl SolStorageValue<data_types::String> for String {
     where
         Reader: StorageReader,
     {
-        let b = Bytes::decode_storage(storage_reader)?;
+        let b = <Bytes as SolStorageValue<data_types::Bytes>>::decode_storage(storage_reader)?;
         Ok(Self::from_utf8_lossy(&b).into_owned())
     }
 }
+
+/// Encodes `bytes` and `string` values following the short/long forms described in
+/// [`SolStorageValue::decode_storage`].
+fn encode_bytes<Writer>(data: &[u8], storage_writer: &mut Writer)
+where
+    Writer: StorageWriter,
+{
+    storage_writer.skip_remaining();
+
+    if data.len() < 32 {
+        let mut word = B256::ZERO;
+        word[..data.len()].copy_from_slice(data);
+        word[31] = (data.len() * 2) as u8;
+        storage_writer.write(word, Default::default());
+    } else {
+        let word = B256::from(checked! { U256::from(data.len()) * uint!(2_U256) + uint!(1_U256) });
+
+        let structure = data
+            .chunks(32)
+            .map(B256::right_padding_from)
+            .collect::<StorageStructure>();
+
+        let mut children = StorageNodeChildren::new();
+        children.insert(Bytes::new(), structure);
+
+        storage_writer.write(word, children);
+    }
+}
+
+impl SolStorageEncode<data_types::Bytes> for bytes::Bytes {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_bytes(self, storage_writer);
+        Ok(())
+    }
+}
+
+impl SolStorageEncode<data_types::Bytes> for Bytes {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_bytes(self, storage_writer);
+        Ok(())
+    }
+}
+
+impl SolStorageEncode<data_types::Bytes> for Vec<u8> {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_bytes(self, storage_writer);
+        Ok(())
+    }
+}
+
+impl SolStorageEncode<data_types::String> for String {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_bytes(self.as_bytes(), storage_writer);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sdecode_core::{IntoStorageReader, StorageStructureWriter};
+
+    use super::*;
+
+    fn test_encode_decode(value: &str) {
+        let mut writer = StorageStructureWriter::new();
+        SolStorageEncode::<data_types::String>::encode_storage(&value.to_string(), &mut writer)
+            .unwrap();
+        let structure = writer.into_structure();
+
+        let decoded = <String as SolStorageValue<data_types::String>>::decode_storage(
+            &mut structure.into_storage_reader(),
+        )
+        .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_sol_string_short() {
+        test_encode_decode("");
+        test_encode_decode("CryptoPunks");
+        test_encode_decode("0123456789012345678901234567890");
+    }
+
+    #[test]
+    fn test_sol_string_long() {
+        test_encode_decode("01234567890123456789012345678901");
+        test_encode_decode(
+            "The quick brown fox jumps over the lazy dog, then takes a nap under the old oak tree.",
+        );
+    }
+}
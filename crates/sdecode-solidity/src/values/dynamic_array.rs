@@ -1,11 +1,17 @@
 use std::marker::PhantomData;
 
-use alloy_primitives::{B256, Bytes};
-use sdecode_core::{IntoStorageReader, StorageReader, StorageReaderNext};
+use alloy_primitives::{B256, Bytes, U256};
+use sdecode_core::{
+    LayoutPathSegment, StorageNodeChildren, StorageReader, StorageReaderNext,
+    StorageStructureWriter, StorageWriter,
+};
 
 use crate::{SolStorageType, data_types, utils::b256_to_u256};
 
-use super::{SolLayoutError, SolStorageValue};
+use super::{
+    SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageSeed, SolStorageTypedSeed,
+    SolStorageValue,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SolDynamicArrayHelper<A, T>(pub A, PhantomData<T>);
@@ -16,6 +22,51 @@ impl<A, T> SolDynamicArrayHelper<A, T> {
     }
 }
 
+/// Decodes a dynamic array: the main slot holds the length and the elements are stored in the
+/// data area at `keccak256(slot)`.
+pub(crate) fn decode_dynamic_array<A, S, Reader>(
+    storage_reader: &mut Reader,
+    element_seed: &S,
+) -> Result<A, SolLayoutError>
+where
+    A: FromIterator<S::Value>,
+    S: SolStorageSeed,
+    Reader: StorageReader,
+{
+    let StorageReaderNext {
+        word,
+        mut children,
+        remaining,
+    } = storage_reader.next_or_default::<B256>();
+
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    let Ok(size) = u64::try_from(b256_to_u256(word)) else {
+        return Err(SolLayoutErrorKind::LengthOverflow {
+            length: b256_to_u256(word),
+        }
+        .into());
+    };
+
+    let key = Bytes::new();
+    let child = children.remove(&key).unwrap_or_default();
+    storage_reader.reject_children(children)?;
+
+    let mut child_storage_reader = storage_reader.child_reader(&key, child);
+    (0..size)
+        .map(|index| {
+            child_storage_reader.enter(|| LayoutPathSegment::Index(index));
+            let element = element_seed
+                .decode_storage_seed(&mut child_storage_reader)
+                .map_err(|err| err.at_index(index));
+            child_storage_reader.leave();
+            element
+        })
+        .collect()
+}
+
 impl<A, T, SolT> SolStorageValue<data_types::Array<SolT>> for SolDynamicArrayHelper<A, T>
 where
     A: FromIterator<T>,
@@ -26,30 +77,7 @@ where
     where
         Reader: StorageReader,
     {
-        let StorageReaderNext {
-            word,
-            mut children,
-            remaining,
-        } = storage_reader.next_or_default::<B256>();
-
-        if remaining.is_not_zero() {
-            return Err(SolLayoutError::remaining_bytes(remaining));
-        }
-
-        let Ok(size) = u64::try_from(b256_to_u256(word)) else {
-            return Err(SolLayoutError::Err);
-        };
-
-        let child = children.remove(&Bytes::new()).unwrap_or_default();
-        if !children.is_empty() {
-            return Err(SolLayoutError::Err);
-        }
-
-        let mut child_storage_reader = child.into_storage_reader();
-        (0..size)
-            .map(|_| T::decode_storage(&mut child_storage_reader))
-            .collect::<Result<A, _>>()
-            .map(Self::new)
+        decode_dynamic_array(storage_reader, &SolStorageTypedSeed::<T, SolT>::new()).map(Self::new)
     }
 }
 
@@ -65,3 +93,57 @@ where
         SolDynamicArrayHelper::decode_storage(storage_reader).map(|x| x.0)
     }
 }
+
+/// Encodes a dynamic array: the main slot holds the length and the elements are stored in the
+/// data area at `keccak256(slot)`.
+fn encode_dynamic_array<'a, T, SolT, Writer>(
+    elements: impl IntoIterator<Item = &'a T>,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    T: SolStorageEncode<SolT> + 'a,
+    SolT: SolStorageType,
+    Writer: StorageWriter,
+{
+    let mut child_storage_writer = StorageStructureWriter::new();
+    let mut len = 0u64;
+    for element in elements {
+        element
+            .encode_storage(&mut child_storage_writer)
+            .map_err(|err| err.at_index(len))?;
+        len += 1;
+    }
+
+    let mut children = StorageNodeChildren::new();
+    children.insert(Bytes::new(), child_storage_writer.into_structure());
+
+    storage_writer.write(B256::from(U256::from(len)), children);
+    Ok(())
+}
+
+impl<A, T, SolT> SolStorageEncode<data_types::Array<SolT>> for SolDynamicArrayHelper<A, T>
+where
+    for<'a> &'a A: IntoIterator<Item = &'a T>,
+    T: SolStorageEncode<SolT>,
+    SolT: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_dynamic_array::<T, SolT, _>(&self.0, storage_writer)
+    }
+}
+
+impl<T, SolT> SolStorageEncode<data_types::Array<SolT>> for Vec<T>
+where
+    T: SolStorageEncode<SolT>,
+    SolT: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_dynamic_array::<T, SolT, _>(self, storage_writer)
+    }
+}
@@ -1,11 +1,11 @@
 use std::marker::PhantomData;
 
 use quick_impl::quick_impl;
-use sdecode_core::StorageReader;
+use sdecode_core::{StorageReader, StorageWriter};
 
 use crate::{SolStorageType, data_types};
 
-use super::{SolLayoutError, SolStorageValue};
+use super::{SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageValue};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[quick_impl]
@@ -30,7 +30,24 @@ where
         Reader: StorageReader,
     {
         let discr = <u8 as SolStorageValue<data_types::Uint<8>>>::decode_storage(storage_reader)?;
-        let res = E::try_from(discr).map_err(|_| SolLayoutError::Err)?;
+        let res = E::try_from(discr).map_err(|_| SolLayoutErrorKind::InvalidEnumDiscriminant {
+            sol_type: SolE::SOL_STORAGE_NAME.into(),
+            discriminant: discr,
+        })?;
         Ok(Self::new(res))
     }
 }
+
+impl<E, SolE> SolStorageEncode<SolE> for SolEnumHelper<E, SolE>
+where
+    for<'a> &'a E: Into<u8>,
+    SolE: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        let discr: u8 = (&self.0).into();
+        <u8 as SolStorageEncode<data_types::Uint<8>>>::encode_storage(&discr, storage_writer)
+    }
+}
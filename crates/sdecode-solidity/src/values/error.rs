@@ -0,0 +1,279 @@
+use std::{borrow::Cow, fmt};
+
+use alloy_primitives::{B256, Bytes, U256};
+use sdecode_core::{LayoutPath, LayoutPathSegment, StorageViolation};
+
+use crate::SolStorageDynType;
+
+/// Error raised when the storage does not match the expected layout.
+///
+/// The error holds the path, from the state variable, of the value that failed to decode, e.g.
+/// `allowance[0x..ab][0x..cd].amount`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolLayoutError {
+    pub kind: SolLayoutErrorKind,
+    pub path: LayoutPath,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SolLayoutErrorKind {
+    /// Triggered when a storage entry has non-zero bytes on an unused part.
+    ///
+    /// For example, an address uses only 20 bytes in storage. So in the following contract
+    ///
+    /// ```solidity
+    /// contract Contract {
+    ///     address addr;
+    ///     uint256 value;
+    /// }
+    /// ```
+    ///
+    /// The 12 fist bytes of the first slot must remain zero.
+    #[error("non zero bytes remaining on unused part of a word: {remaining}")]
+    RemainingBytes { remaining: Bytes },
+
+    #[error("invalid mapping key, expected {sol_type} got {raw}")]
+    InvalidMappingKey { sol_type: &'static str, raw: Bytes },
+
+    /// The scheme attached to a mapping cannot derive the slot of the entry at `key`.
+    #[error("the slot of the entry at {key} cannot be derived")]
+    UnderivableMappingKey { key: Bytes },
+
+    /// When decoding a mapping or a dynamic array, the slot of the variable must be empty.
+    #[error("expected empty slot, got {value}")]
+    NonEmptySlot { sol_type: &'static str, value: B256 },
+
+    /// The bytes of a word are not a valid value of its type, e.g. a `bool` set to `2`.
+    #[error("invalid {sol_type} value: {word}")]
+    InvalidWord { sol_type: &'static str, word: Bytes },
+
+    #[error("invalid discriminant {discriminant} for enum {sol_type}")]
+    InvalidEnumDiscriminant {
+        sol_type: Cow<'static, str>,
+        discriminant: u8,
+    },
+
+    /// The length of a dynamic array, `bytes` or `string` does not fit in memory.
+    #[error("length {length} is too large")]
+    LengthOverflow { length: U256 },
+
+    /// The number of elements of a fixed-size array does not match its type.
+    #[error("expected {expected} elements, got {length}")]
+    InvalidLength { expected: usize, length: usize },
+
+    /// Values stored at a hashed location were found under a value that cannot have any, such as
+    /// a value type or a short `bytes`.
+    #[error("unexpected hashed values under the keys {keys:?}")]
+    UnexpectedChildren { keys: Vec<Bytes> },
+
+    /// The data of a `bytes` or `string` has non-zero bytes after its end.
+    #[error("non zero bytes after the end of the data: {tail}")]
+    BytesTail { tail: Bytes },
+
+    /// The type cannot be decoded, e.g. an `uint` with an invalid bit count, a mapping key of a
+    /// reference type, or a [`StructRef`](SolStorageDynType::StructRef) that no mapping or
+    /// dynamic array separates from its structure.
+    #[error("unsupported type {ty}")]
+    UnsupportedType { ty: SolStorageDynType },
+}
+
+impl SolLayoutError {
+    pub fn remaining_bytes(remaining: impl Into<Bytes>) -> Self {
+        SolLayoutErrorKind::RemainingBytes {
+            remaining: remaining.into(),
+        }
+        .into()
+    }
+
+    pub fn unexpected_children<'a>(keys: impl IntoIterator<Item = &'a Bytes>) -> Self {
+        SolLayoutErrorKind::UnexpectedChildren {
+            keys: keys.into_iter().cloned().collect(),
+        }
+        .into()
+    }
+
+    /// Prefixes the path of the error with a structure field or a state variable.
+    pub fn at_field(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.path.0.insert(0, LayoutPathSegment::Field(name.into()));
+        self
+    }
+
+    /// Prefixes the path of the error with an array index.
+    pub fn at_index(mut self, index: u64) -> Self {
+        self.path.0.insert(0, LayoutPathSegment::Index(index));
+        self
+    }
+
+    /// Prefixes the path of the error with a raw mapping key.
+    pub fn at_key(mut self, key: Bytes) -> Self {
+        self.path.0.insert(0, LayoutPathSegment::Key(key));
+        self
+    }
+}
+
+impl From<SolLayoutErrorKind> for SolLayoutError {
+    fn from(kind: SolLayoutErrorKind) -> Self {
+        Self {
+            kind,
+            path: LayoutPath::default(),
+        }
+    }
+}
+
+impl From<StorageViolation> for SolLayoutErrorKind {
+    fn from(violation: StorageViolation) -> Self {
+        match violation {
+            StorageViolation::RemainingBytes { remaining } => Self::RemainingBytes { remaining },
+            StorageViolation::UnexpectedChildren { keys } => Self::UnexpectedChildren { keys },
+        }
+    }
+}
+
+impl From<StorageViolation> for SolLayoutError {
+    fn from(violation: StorageViolation) -> Self {
+        SolLayoutErrorKind::from(violation).into()
+    }
+}
+
+impl fmt::Display for SolLayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            self.kind.fmt(f)
+        } else {
+            write!(f, "at `{}`: {}", self.path, self.kind)
+        }
+    }
+}
+
+impl std::error::Error for SolLayoutError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use alloy_primitives::{Address, address};
+    use sdecode_core::{StorageDecode, StorageEncode, StorageError};
+
+    use super::*;
+    use crate::{SolStorageDynLayoutDecode, SolStorageLayout, SolStorageRoot};
+
+    use self::ledger::{Allowance, Status};
+
+    crate::sol_storage! {
+        #[derive(Debug)]
+        contract Ledger {
+            #[derive(Debug)]
+            enum Status {
+                Active,
+                Revoked,
+            }
+
+            #[derive(Debug)]
+            struct Allowance {
+                uint128 amount;
+                Status status;
+            }
+
+            mapping(address => mapping(address => Allowance)) allowance;
+            string[] names;
+        }
+    }
+
+    fn layout_error<T: std::fmt::Debug>(
+        result: Result<T, StorageError<std::convert::Infallible, SolLayoutError>>,
+    ) -> SolLayoutError {
+        result.unwrap_err().into_layout().unwrap()
+    }
+
+    #[test]
+    fn test_layout_error_path() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+
+        let ledger = LedgerStorage {
+            allowance: BTreeMap::from([(
+                alice,
+                BTreeMap::from([(
+                    bob,
+                    Allowance {
+                        amount: 10,
+                        status: Status::Active,
+                    },
+                )]),
+            )]),
+            names: vec!["a".to_string(), "b".to_string()],
+        };
+        let (mut entries, preimages) = ledger.sencode().unwrap();
+
+        // An enum set to an unknown variant.
+        let paths = LedgerStorage::storage_paths();
+        let (slot, offset) = paths
+            .allowance
+            .key(alice)
+            .unwrap()
+            .key(bob)
+            .unwrap()
+            .fields()
+            .status
+            .location();
+        entries.get_mut(&slot).unwrap()[31 - offset] = 2;
+
+        let err = layout_error(LedgerStorage::sdecode(&preimages, entries.clone()));
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::InvalidEnumDiscriminant {
+                sol_type: "Status".into(),
+                discriminant: 2,
+            }
+        );
+        assert_eq!(
+            err.path.to_string(),
+            format!(
+                "allowance[{}][{}].status",
+                alice.into_word(),
+                bob.into_word()
+            )
+        );
+        assert!(err.to_string().starts_with("at `allowance["));
+
+        // The same path is reported when the layout is only known at runtime.
+        let dyn_err = layout_error(
+            LedgerStorage::sol_storage_dyn_layout().sdecode(&preimages, entries.clone()),
+        );
+        assert_eq!(dyn_err, err);
+
+        // Garbage after the data of a short string.
+        entries.get_mut(&slot).unwrap()[31 - offset] = 1;
+        let (slot, _) = paths.names.location();
+        let data = U256::from_be_bytes(alloy_primitives::keccak256(slot).0);
+        let name = B256::from(data + U256::from(1));
+        entries.get_mut(&name).unwrap()[10] = 0xff;
+
+        let err = layout_error(LedgerStorage::sdecode(&preimages, entries));
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::BytesTail {
+                tail: Bytes::from([&[0; 9][..], &[0xff], &[0; 20]].concat()),
+            }
+        );
+        assert_eq!(err.path.to_string(), "names[1]");
+    }
+
+    #[test]
+    fn test_layout_error_display() {
+        let err = SolLayoutError::from(SolLayoutErrorKind::LengthOverflow { length: U256::MAX })
+            .at_field("amount")
+            .at_index(3)
+            .at_key(Address::ZERO.into_word().into())
+            .at_field("positions");
+
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "at `positions[{}][3].amount`: length {} is too large",
+                B256::ZERO,
+                U256::MAX
+            )
+        );
+    }
+}
@@ -1,10 +1,13 @@
 use std::marker::PhantomData;
 
-use sdecode_core::StorageReader;
+use sdecode_core::{LayoutPathSegment, StorageReader, StorageWriter};
 
 use crate::{SolStorageType, data_types};
 
-use super::{SolLayoutError, SolStorageValue};
+use super::{
+    SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageSeed, SolStorageTypedSeed,
+    SolStorageValue,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SolFixedArrayHelper<const N: usize, A, T>(pub A, PhantomData<T>);
@@ -30,11 +33,61 @@ where
             return Err(SolLayoutError::remaining_bytes(remaining));
         }
 
-        let res = array_init::try_array_init(|_| T::decode_storage(storage_reader))?;
+        let res = array_init::try_array_init(|index| {
+            storage_reader.enter(|| LayoutPathSegment::Index(index as u64));
+            let element =
+                T::decode_storage(storage_reader).map_err(|err| err.at_index(index as u64));
+            storage_reader.leave();
+            element
+        })?;
+
+        let remaining = storage_reader.consume_remaining();
+
+        if remaining.is_not_zero() {
+            return Err(SolLayoutError::remaining_bytes(remaining));
+        }
+
         Ok(res)
     }
 }
 
+/// Decodes a fixed size array. Like structs, fixed size arrays always start and end on a new slot.
+pub(crate) fn decode_fixed_array<A, S, Reader>(
+    storage_reader: &mut Reader,
+    len: usize,
+    element_seed: &S,
+) -> Result<A, SolLayoutError>
+where
+    A: FromIterator<S::Value>,
+    S: SolStorageSeed,
+    Reader: StorageReader,
+{
+    let remaining = storage_reader.consume_remaining();
+
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    let res = (0..len)
+        .map(|index| {
+            storage_reader.enter(|| LayoutPathSegment::Index(index as u64));
+            let element = element_seed
+                .decode_storage_seed(storage_reader)
+                .map_err(|err| err.at_index(index as u64));
+            storage_reader.leave();
+            element
+        })
+        .collect::<Result<A, _>>()?;
+
+    let remaining = storage_reader.consume_remaining();
+
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    Ok(res)
+}
+
 impl<const N: usize, A, T, SolT> SolStorageValue<data_types::FixedArray<SolT, N>>
     for SolFixedArrayHelper<N, A, T>
 where
@@ -46,16 +99,7 @@ where
     where
         Reader: StorageReader,
     {
-        let remaining = storage_reader.consume_remaining();
-
-        if remaining.is_not_zero() {
-            return Err(SolLayoutError::remaining_bytes(remaining));
-        }
-
-        (0..N)
-            .map(|_| T::decode_storage(storage_reader))
-            .collect::<Result<A, _>>()
-            .map(Self::new)
+        decode_fixed_array(storage_reader, N, &SolStorageTypedSeed::<T, SolT>::new()).map(Self::new)
     }
 }
 
@@ -72,10 +116,84 @@ where
     }
 }
 
+/// Encodes the `N` elements of a fixed-size array. Arrays always start and end on a new slot.
+fn encode_fixed_array<'a, const N: usize, T, SolT, Writer>(
+    elements: impl IntoIterator<Item = &'a T>,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    T: SolStorageEncode<SolT> + 'a,
+    SolT: SolStorageType,
+    Writer: StorageWriter,
+{
+    storage_writer.skip_remaining();
+
+    let mut len = 0;
+    for element in elements {
+        if len < N {
+            element
+                .encode_storage(storage_writer)
+                .map_err(|err| err.at_index(len as u64))?;
+        }
+        len += 1;
+    }
+    if len != N {
+        return Err(SolLayoutErrorKind::InvalidLength {
+            expected: N,
+            length: len,
+        }
+        .into());
+    }
+
+    storage_writer.skip_remaining();
+    Ok(())
+}
+
+impl<const N: usize, T, SolT> SolStorageEncode<data_types::FixedArray<SolT, N>> for [T; N]
+where
+    SolT: SolStorageType,
+    T: SolStorageEncode<SolT>,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_fixed_array::<N, T, SolT, _>(self, storage_writer)
+    }
+}
+
+impl<const N: usize, A, T, SolT> SolStorageEncode<data_types::FixedArray<SolT, N>>
+    for SolFixedArrayHelper<N, A, T>
+where
+    for<'a> &'a A: IntoIterator<Item = &'a T>,
+    SolT: SolStorageType,
+    T: SolStorageEncode<SolT>,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_fixed_array::<N, T, SolT, _>(&self.0, storage_writer)
+    }
+}
+
+impl<const N: usize, T, SolT> SolStorageEncode<data_types::FixedArray<SolT, N>> for Vec<T>
+where
+    SolT: SolStorageType,
+    T: SolStorageEncode<SolT>,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_fixed_array::<N, T, SolT, _>(self, storage_writer)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloy_primitives::{FixedBytes, b256, fixed_bytes};
-    use sdecode_core::{IntoStorageReader, StorageNode, StorageStructure};
+    use sdecode_core::{IntoStorageReader, StorageNode, StorageStructure, StorageStructureWriter};
 
     use super::*;
 
@@ -105,4 +223,23 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_encode_fixed_array_length() {
+        let mut storage_writer = StorageStructureWriter::new();
+        let err =
+            SolStorageEncode::<data_types::FixedArray<data_types::Uint<8>, 3>>::encode_storage(
+                &vec![1u8, 2],
+                &mut storage_writer,
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::InvalidLength {
+                expected: 3,
+                length: 2,
+            }
+        );
+    }
 }
@@ -1,5 +1,7 @@
 use alloy_primitives::{Address, Bytes, FixedBytes, Function, aliases::*};
-use sdecode_core::{IntoStorageReader, StorageReader};
+use sdecode_core::{
+    LayoutPathSegment, StorageNodeChildren, StorageReader, StorageStructureWriter, StorageWriter,
+};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     hash::{BuildHasher, Hash},
@@ -8,7 +10,10 @@ use std::{
 
 use crate::{SolMappingKeyType, SolStorageType, data_types};
 
-use super::{SolLayoutError, SolStorageValue, word::SolWordType};
+use super::{
+    SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageSeed, SolStorageTypedSeed,
+    SolStorageValue, word::SolWordType,
+};
 
 /// # [Mappings and Dynamic Arrays](https://docs.soliditylang.org/en/latest/internals/layout_in_storage.html#mappings-and-dynamic-arrays)
 ///
@@ -19,9 +24,21 @@ use super::{SolLayoutError, SolStorageValue, word::SolWordType};
 /// - for value types, ``h`` pads the value to 32 bytes in the same way as when storing the value in
 ///   memory.
 /// - for strings and byte arrays, ``h(k)`` is just the unpadded data.
+///
+/// Vyper differs for its `Bytes` and `String` keys, hashed with keccak256 instead: see
+/// [`VyHashedKey`].
 pub trait SolMappingKeyValue<SolK: SolMappingKeyType>: Sized {
     fn into_sol_mapping_key(self) -> Bytes;
     fn try_from_sol_mapping_key(key: Bytes) -> Result<Self, Bytes>;
+
+    /// Converts the raw key of an entry read from `storage_reader`, which knows the original
+    /// values of the hashed keys.
+    fn decode_sol_mapping_key<Reader>(key: Bytes, _storage_reader: &Reader) -> Result<Self, Bytes>
+    where
+        Reader: StorageReader,
+    {
+        Self::try_from_sol_mapping_key(key)
+    }
 }
 
 macro_rules! impl_sol_mapping_key_value_for_word {
@@ -210,6 +227,47 @@ impl<M, K, V> SolMappingHelper<M, K, V> {
     }
 }
 
+/// Decodes a mapping: the main slot must be empty and each value is read from the location
+/// derived from its key. `decode_key` converts the raw preimage key.
+pub(crate) fn decode_mapping<M, K, S, Reader>(
+    storage_reader: &mut Reader,
+    sol_type: &'static str,
+    mut decode_key: impl FnMut(Bytes, &Reader) -> Result<K, SolLayoutError>,
+    value_seed: &S,
+) -> Result<M, SolLayoutError>
+where
+    M: FromIterator<(K, S::Value)>,
+    S: SolStorageSeed,
+    Reader: StorageReader,
+{
+    let next = storage_reader.next_or_default::<B256>();
+
+    if next.is_remaining_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(next.remaining));
+    }
+
+    if !next.word.is_zero() {
+        return Err(SolLayoutErrorKind::NonEmptySlot {
+            sol_type,
+            value: next.word,
+        }
+        .into());
+    }
+
+    next.children
+        .into_iter()
+        .map(|(key, structure)| -> Result<_, SolLayoutError> {
+            storage_reader.enter(|| LayoutPathSegment::Key(key.clone()));
+            let value = value_seed
+                .decode_storage_seed(&mut storage_reader.child_reader(&key, structure))
+                .map_err(|err| err.at_key(key.clone()))?;
+            storage_reader.leave();
+            let key = decode_key(key, storage_reader)?;
+            Ok((key, value))
+        })
+        .collect()
+}
+
 impl<K, SolK, V, SolV, M> SolStorageValue<data_types::Mapping<SolK, SolV>>
     for SolMappingHelper<M, K, V>
 where
@@ -223,33 +281,21 @@ where
     where
         Reader: StorageReader,
     {
-        let next = storage_reader.next_or_default::<B256>();
-
-        if next.is_remaining_not_zero() {
-            return Err(SolLayoutError::remaining_bytes(next.remaining));
-        }
-
-        if !next.word.is_zero() {
-            return Err(SolLayoutError::NonEmptySlot {
-                sol_type: data_types::Mapping::<SolK, SolV>::SOL_STORAGE_NAME,
-                value: next.word,
-            });
-        }
-
-        next.children
-            .into_iter()
-            .map(|(key, structure)| -> Result<_, SolLayoutError> {
-                let key = K::try_from_sol_mapping_key(key).map_err(|raw| {
-                    SolLayoutError::InvalidMappingKey {
+        decode_mapping(
+            storage_reader,
+            data_types::Mapping::<SolK, SolV>::SOL_STORAGE_NAME,
+            |key, storage_reader| {
+                K::decode_sol_mapping_key(key, storage_reader).map_err(|raw| {
+                    SolLayoutErrorKind::InvalidMappingKey {
                         sol_type: SolK::SOL_STORAGE_NAME,
                         raw,
                     }
-                })?;
-                let value = V::decode_storage(&mut structure.into_storage_reader())?;
-                Ok((key, value))
-            })
-            .collect::<Result<M, _>>()
-            .map(Self::new)
+                    .into()
+                })
+            },
+            &SolStorageTypedSeed::<V, SolV>::new(),
+        )
+        .map(Self::new)
     }
 }
 
@@ -299,6 +345,36 @@ impl<M, K> SolSetHelper<M, K> {
     }
 }
 
+/// Rust containers of the entries of a mapping. Gives the views generated by `sol_storage!` the
+/// type of the values of a mapping declared with the `type` attribute.
+pub trait SolMappingEntries {
+    type Value;
+}
+
+impl<M, K, V> SolMappingEntries for SolMappingHelper<M, K, V> {
+    type Value = V;
+}
+
+impl<K, V> SolMappingEntries for BTreeMap<K, V> {
+    type Value = V;
+}
+
+impl<K, V, S> SolMappingEntries for HashMap<K, V, S> {
+    type Value = V;
+}
+
+impl<M, K> SolMappingEntries for SolSetHelper<M, K> {
+    type Value = bool;
+}
+
+impl<K> SolMappingEntries for BTreeSet<K> {
+    type Value = bool;
+}
+
+impl<K, S> SolMappingEntries for HashSet<K, S> {
+    type Value = bool;
+}
+
 impl<K, SolK, M> SolStorageValue<data_types::Mapping<SolK, data_types::Bool>> for SolSetHelper<M, K>
 where
     K: SolMappingKeyValue<SolK>,
@@ -316,29 +392,31 @@ where
         }
 
         if !next.word.is_zero() {
-            return Err(SolLayoutError::NonEmptySlot {
+            return Err(SolLayoutErrorKind::NonEmptySlot {
                 sol_type: data_types::Mapping::<SolK, data_types::Bool>::SOL_STORAGE_NAME,
                 value: next.word,
-            });
+            }
+            .into());
         }
 
         next.children
             .into_iter()
             .filter_map(|(key, structure)| -> Option<Result<_, SolLayoutError>> {
-                let key = match K::try_from_sol_mapping_key(key) {
-                    Ok(key) => key,
-                    Err(raw) => {
-                        return Some(Err(SolLayoutError::InvalidMappingKey {
-                            sol_type: SolK::SOL_STORAGE_NAME,
-                            raw,
-                        }));
-                    }
-                };
-                match <bool as SolStorageValue<data_types::Bool>>::decode_storage(
-                    &mut structure.into_storage_reader(),
+                storage_reader.enter(|| LayoutPathSegment::Key(key.clone()));
+                let present = match <bool as SolStorageValue<data_types::Bool>>::decode_storage(
+                    &mut storage_reader.child_reader(&key, structure),
                 ) {
-                    Ok(present) => present.then_some(Ok(key)),
-                    Err(err) => Some(Err(err)),
+                    Ok(present) => present,
+                    Err(err) => return Some(Err(err.at_key(key.clone()))),
+                };
+                storage_reader.leave();
+                match K::decode_sol_mapping_key(key, &*storage_reader) {
+                    Ok(key) => present.then_some(Ok(key)),
+                    Err(raw) => Some(Err(SolLayoutErrorKind::InvalidMappingKey {
+                        sol_type: SolK::SOL_STORAGE_NAME,
+                        raw,
+                    }
+                    .into())),
                 }
             })
             .collect::<Result<M, _>>()
@@ -378,3 +456,139 @@ where
         .map(|x| x.0)
     }
 }
+
+/// Encodes a mapping: the main slot stays empty and each value is stored at the location derived
+/// from its key.
+fn encode_mapping<'a, K, SolK, V, SolV, Writer>(
+    entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    K: SolMappingKeyValue<SolK> + Clone + 'a,
+    V: SolStorageEncode<SolV> + 'a,
+    SolK: SolMappingKeyType,
+    SolV: SolStorageType,
+    Writer: StorageWriter,
+{
+    let mut children = StorageNodeChildren::new();
+    for (key, value) in entries {
+        let key = key.clone().into_sol_mapping_key();
+        let mut child_storage_writer = StorageStructureWriter::new();
+        value
+            .encode_storage(&mut child_storage_writer)
+            .map_err(|err| err.at_key(key.clone()))?;
+
+        // Entries holding a zero value are absent from storage.
+        let child = child_storage_writer.into_structure();
+        if !child.is_zero() {
+            children.insert(key, child);
+        }
+    }
+
+    storage_writer.write(B256::ZERO, children);
+    Ok(())
+}
+
+impl<K, SolK, V, SolV, M> SolStorageEncode<data_types::Mapping<SolK, SolV>>
+    for SolMappingHelper<M, K, V>
+where
+    for<'a> &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+    K: SolMappingKeyValue<SolK> + Clone,
+    V: SolStorageEncode<SolV>,
+    SolK: SolMappingKeyType,
+    SolV: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_mapping::<K, SolK, V, SolV, _>(&self.0, storage_writer)
+    }
+}
+
+impl<K, SolK, V, SolV> SolStorageEncode<data_types::Mapping<SolK, SolV>> for BTreeMap<K, V>
+where
+    K: SolMappingKeyValue<SolK> + Clone,
+    V: SolStorageEncode<SolV>,
+    SolK: SolMappingKeyType,
+    SolV: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_mapping::<K, SolK, V, SolV, _>(self, storage_writer)
+    }
+}
+
+impl<K, SolK, V, SolV, S> SolStorageEncode<data_types::Mapping<SolK, SolV>> for HashMap<K, V, S>
+where
+    K: SolMappingKeyValue<SolK> + Clone,
+    V: SolStorageEncode<SolV>,
+    SolK: SolMappingKeyType,
+    SolV: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_mapping::<K, SolK, V, SolV, _>(self, storage_writer)
+    }
+}
+
+/// Encodes a set as a mapping to `true`.
+fn encode_set<'a, K, SolK, Writer>(
+    keys: impl IntoIterator<Item = &'a K>,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    K: SolMappingKeyValue<SolK> + Clone + 'a,
+    SolK: SolMappingKeyType,
+    Writer: StorageWriter,
+{
+    encode_mapping::<K, SolK, bool, data_types::Bool, _>(
+        keys.into_iter().map(|key| (key, &true)),
+        storage_writer,
+    )
+}
+
+impl<K, SolK, M> SolStorageEncode<data_types::Mapping<SolK, data_types::Bool>>
+    for SolSetHelper<M, K>
+where
+    for<'a> &'a M: IntoIterator<Item = &'a K>,
+    K: SolMappingKeyValue<SolK> + Clone,
+    SolK: SolMappingKeyType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_set::<K, SolK, _>(&self.0, storage_writer)
+    }
+}
+
+impl<K, SolK> SolStorageEncode<data_types::Mapping<SolK, data_types::Bool>> for BTreeSet<K>
+where
+    K: SolMappingKeyValue<SolK> + Clone,
+    SolK: SolMappingKeyType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_set::<K, SolK, _>(self, storage_writer)
+    }
+}
+
+impl<K, SolK, S> SolStorageEncode<data_types::Mapping<SolK, data_types::Bool>> for HashSet<K, S>
+where
+    K: SolMappingKeyValue<SolK> + Clone,
+    SolK: SolMappingKeyType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_set::<K, SolK, _>(self, storage_writer)
+    }
+}
@@ -1,20 +1,33 @@
-use alloy_primitives::{B256, Bytes};
-use sdecode_core::StorageReader;
+use std::marker::PhantomData;
+
+use sdecode_core::{StorageReader, StorageWriter};
 
 use crate::SolStorageType;
 
 mod bytes_string;
 
+mod error;
+pub use error::{SolLayoutError, SolLayoutErrorKind};
+pub use sdecode_core::{LayoutPath as SolLayoutPath, LayoutPathSegment as SolLayoutPathSegment};
+
 mod enumm;
 
 mod dynamic_array;
+pub(crate) use dynamic_array::decode_dynamic_array;
 
 mod fixed_array;
+pub(crate) use fixed_array::decode_fixed_array;
 
 mod mapping;
 pub use mapping::SolMappingKeyValue;
+pub(crate) use mapping::decode_mapping;
 
 mod structure;
+pub(crate) use structure::decode_structure;
+
+mod vyper;
+pub use vyper::VyHashedKey;
+pub(crate) use vyper::{decode_vyper_bytes, decode_vyper_dynamic_array};
 
 mod word;
 pub use word::SolWordType;
@@ -25,7 +38,7 @@ pub mod helpers {
     pub use dynamic_array::SolDynamicArrayHelper;
     pub use enumm::SolEnumHelper;
     pub use fixed_array::SolFixedArrayHelper;
-    pub use mapping::{SolMappingHelper, SolSetHelper};
+    pub use mapping::{SolMappingEntries, SolMappingHelper, SolSetHelper};
     pub use structure::SolStructureHelper;
 }
 
@@ -35,38 +48,63 @@ pub trait SolStorageValue<T: SolStorageType>: Sized {
         Reader: StorageReader;
 }
 
-#[derive(Debug, Clone, thiserror::Error)]
-pub enum SolLayoutError {
-    /// Triggered when a storage entry has non-zero bytes on an unused part.
-    ///
-    /// For example, an address uses only 20 bytes in storage. So in the following contract
-    ///
-    /// ```solidity
-    /// contract Contract {
-    ///     address addr;
-    ///     uint256 value;
-    /// }
-    /// ```
-    ///
-    /// The 12 fist bytes of the first slot must remain zero.
-    #[error("non zero bytes remaining on unused part of a word: {remaining}")]
-    RemainingBytes { remaining: Bytes },
-
-    #[error("invalid mapping key, expected {sol_type} got {raw}")]
-    InvalidMappingKey { sol_type: &'static str, raw: Bytes },
-
-    /// When decoding a mapping or a dynamic array, the slot of the variable must be empty.
-    #[error("expected empty slot, got {value}")]
-    NonEmptySlot { sol_type: &'static str, value: B256 },
-
-    #[error("todo error")]
-    Err,
+/// Decodes a value whose Solidity type may only be known at runtime.
+///
+/// Containers (arrays, mappings) are decoded through this trait so that the compile time and the
+/// runtime decoders share the same layout rules.
+pub(crate) trait SolStorageSeed {
+    type Value;
+
+    fn decode_storage_seed<Reader>(
+        &self,
+        storage_reader: &mut Reader,
+    ) -> Result<Self::Value, SolLayoutError>
+    where
+        Reader: StorageReader;
+}
+
+/// [`SolStorageSeed`] of a type known at compile time.
+pub(crate) struct SolStorageTypedSeed<T, SolT>(PhantomData<(T, SolT)>);
+
+impl<T, SolT> SolStorageTypedSeed<T, SolT> {
+    pub(crate) const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, SolT> SolStorageSeed for SolStorageTypedSeed<T, SolT>
+where
+    T: SolStorageValue<SolT>,
+    SolT: SolStorageType,
+{
+    type Value = T;
+
+    fn decode_storage_seed<Reader>(&self, storage_reader: &mut Reader) -> Result<T, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        T::decode_storage(storage_reader)
+    }
 }
 
-impl SolLayoutError {
-    pub fn remaining_bytes(remaining: impl Into<Bytes>) -> Self {
-        Self::RemainingBytes {
-            remaining: remaining.into(),
-        }
+/// Inverse of [`SolStorageValue`].
+pub trait SolStorageEncode<T: SolStorageType> {
+    /// Fails if the value does not fit the type, e.g. a `Vec` encoded as a fixed-size array of
+    /// another length.
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter;
+}
+
+impl<T, V> SolStorageEncode<T> for &V
+where
+    T: SolStorageType,
+    V: SolStorageEncode<T> + ?Sized,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        (**self).encode_storage(storage_writer)
     }
 }
@@ -1,9 +1,9 @@
-use super::{SolLayoutError, SolStorageValue};
-use crate::SolStorageType;
+use super::{SolLayoutError, SolStorageEncode, SolStorageSeed, SolStorageValue};
+use crate::{SolStorageDynField, SolStorageDynType, SolStorageType};
 use paste::paste;
 use quick_impl::quick_impl;
-use sdecode_core::StorageReader;
-use std::{hash::Hash, marker::PhantomData};
+use sdecode_core::{LayoutPathSegment, StorageReader, StorageWriter};
+use std::{any::TypeId, cell::RefCell, hash::Hash, marker::PhantomData};
 
 /// Solidity doesn't have tuples, so it wouldn't be correct to implement `SolStorageTypeValue` on
 /// tuples. Instead, it is implemented on a wrapper.
@@ -20,6 +20,82 @@ impl<SolStructure, Tuple, SolTuple> SolStructureHelper<SolStructure, Tuple, SolT
     }
 }
 
+thread_local! {
+    /// Structures whose dynamic type is being built on this thread.
+    static DYN_STRUCTURES: RefCell<Vec<TypeId>> = const { RefCell::new(Vec::new()) };
+}
+
+impl<SolStructure, Tuple, SolTuple> SolStructureHelper<SolStructure, Tuple, SolTuple>
+where
+    SolStructure: SolStorageType + 'static,
+{
+    /// Dynamic type of the structure named `name`, with the given fields. Within its own fields,
+    /// the structure is a [`SolStorageDynType::StructRef`].
+    pub fn sol_storage_dyn_type(
+        name: &str,
+        fields: impl FnOnce() -> Vec<SolStorageDynField>,
+    ) -> SolStorageDynType {
+        struct Building;
+
+        impl Drop for Building {
+            fn drop(&mut self) {
+                DYN_STRUCTURES.with_borrow_mut(Vec::pop);
+            }
+        }
+
+        let id = TypeId::of::<SolStructure>();
+        if DYN_STRUCTURES.with_borrow(|structures| structures.contains(&id)) {
+            return SolStorageDynType::StructRef {
+                name: name.to_string(),
+                slots: SolStructure::sol_storage_footprint().slots(),
+            };
+        }
+
+        DYN_STRUCTURES.with_borrow_mut(|structures| structures.push(id));
+        let _building = Building;
+        SolStorageDynType::Struct {
+            name: name.to_string(),
+            fields: fields(),
+        }
+    }
+}
+
+/// Decodes the members of a struct whose fields are only known at runtime, given with their names.
+pub(crate) fn decode_structure<'a, A, S, Reader>(
+    storage_reader: &mut Reader,
+    field_seeds: impl IntoIterator<Item = (&'a str, &'a S)>,
+) -> Result<A, SolLayoutError>
+where
+    A: FromIterator<S::Value>,
+    S: SolStorageSeed + 'a,
+    Reader: StorageReader,
+{
+    // Structs always start and end on a new slot.
+    let remaining = storage_reader.consume_remaining();
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    let res = field_seeds
+        .into_iter()
+        .map(|(name, seed)| {
+            storage_reader.enter(|| LayoutPathSegment::Field(name.to_string().into()));
+            let field = seed
+                .decode_storage_seed(storage_reader)
+                .map_err(|err| err.at_field(name.to_string()));
+            storage_reader.leave();
+            field
+        })
+        .collect::<Result<A, _>>()?;
+
+    let remaining = storage_reader.consume_remaining();
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    Ok(res)
+}
+
 macro_rules! impl_sol_storage_type_structure {
     ($( $ty: ident),*) => {
         paste! {
@@ -35,9 +111,46 @@ macro_rules! impl_sol_storage_type_structure {
                 where
                     Reader: StorageReader
                 {
-                    Ok(Self::new(($(
+                    // Structs always start and end on a new slot.
+                    let remaining = storage_reader.consume_remaining();
+                    if remaining.is_not_zero() {
+                        return Err(SolLayoutError::remaining_bytes(remaining));
+                    }
+
+                    let res = Self::new(($(
                         $ty::decode_storage(storage_reader)?,
-                    )*)))
+                    )*));
+
+                    let remaining = storage_reader.consume_remaining();
+                    if remaining.is_not_zero() {
+                        return Err(SolLayoutError::remaining_bytes(remaining));
+                    }
+
+                    Ok(res)
+                }
+            }
+
+            impl<SolStructure, $($ty, [<Sol $ty>],)*> SolStorageEncode<SolStructure> for SolStructureHelper<SolStructure, ($($ty,)*), ($([<Sol $ty>],)*)>
+            where
+                SolStructure: SolStorageType,
+                $(
+                    $ty: SolStorageEncode<[<Sol $ty>]>,
+                    [<Sol $ty>]: SolStorageType,
+                )*
+            {
+                #[allow(non_snake_case)]
+                fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+                where
+                    Writer: StorageWriter
+                {
+                    let ($($ty,)*) = &self.0;
+
+                    storage_writer.skip_remaining();
+                    $(
+                        $ty.encode_storage(storage_writer)?;
+                    )*
+                    storage_writer.skip_remaining();
+                    Ok(())
                 }
             }
         }
@@ -59,6 +172,21 @@ where
     }
 }
 
+impl<SolStructure, A, SolA> SolStorageEncode<SolStructure>
+    for SolStructureHelper<SolStructure, A, SolA>
+where
+    SolStructure: SolStorageType,
+    A: SolStorageEncode<SolA>,
+    SolA: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        self.0.encode_storage(storage_writer)
+    }
+}
+
 impl_sol_storage_type_structure!(A);
 impl_sol_storage_type_structure!(A, B);
 impl_sol_storage_type_structure!(A, B, C);
@@ -0,0 +1,491 @@
+use alloy_primitives::{
+    B256, Bytes, U256,
+    bytes::{self, BufMut, BytesMut},
+    keccak256,
+};
+use sdecode_core::{LayoutPathSegment, StorageReader, StorageReaderNext, StorageWriter};
+
+use crate::{SolStorageFootprint, SolStorageType, data_types, utils::b256_to_u256};
+
+use super::{
+    SolLayoutError, SolLayoutErrorKind, SolMappingKeyValue, SolStorageEncode, SolStorageSeed,
+    SolStorageTypedSeed, SolStorageValue,
+};
+
+/// Reads the length of a Vyper `DynArray`, `Bytes` or `String`, stored in its first slot.
+fn decode_vyper_length<Reader>(
+    storage_reader: &mut Reader,
+    max_len: usize,
+) -> Result<usize, SolLayoutError>
+where
+    Reader: StorageReader,
+{
+    let remaining = storage_reader.consume_remaining();
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+
+    let StorageReaderNext {
+        word,
+        children,
+        remaining,
+    } = storage_reader.next_or_default::<B256>();
+
+    if remaining.is_not_zero() {
+        return Err(SolLayoutError::remaining_bytes(remaining));
+    }
+    storage_reader.reject_children(children)?;
+
+    let length = b256_to_u256(word);
+    match usize::try_from(length) {
+        Ok(len) if len <= max_len => Ok(len),
+        _ => Err(SolLayoutErrorKind::LengthOverflow { length }.into()),
+    }
+}
+
+/// Decodes a Vyper `DynArray` of at most `max_len` elements: the first slot holds the length and
+/// the elements follow in place, each starting on a new slot.
+///
+/// The slots of the unused capacity are skipped, as they may hold the elements of a previous,
+/// longer array.
+pub(crate) fn decode_vyper_dynamic_array<A, S, Reader>(
+    storage_reader: &mut Reader,
+    max_len: usize,
+    element_footprint: SolStorageFootprint,
+    element_seed: &S,
+) -> Result<A, SolLayoutError>
+where
+    A: FromIterator<S::Value>,
+    S: SolStorageSeed,
+    Reader: StorageReader,
+{
+    let len = decode_vyper_length(storage_reader, max_len)?;
+
+    let elements = (0..len as u64)
+        .map(|index| {
+            storage_reader.enter(|| LayoutPathSegment::Index(index));
+            let element = element_seed
+                .decode_storage_seed(storage_reader)
+                .and_then(|element| {
+                    let remaining = storage_reader.consume_remaining();
+                    if remaining.is_not_zero() {
+                        return Err(SolLayoutError::remaining_bytes(remaining));
+                    }
+                    Ok(element)
+                })
+                .map_err(|err| err.at_index(index));
+            storage_reader.leave();
+            element
+        })
+        .collect::<Result<A, _>>()?;
+
+    let element_slots = element_footprint.slots().saturating_to::<usize>();
+    storage_reader.skip_slots((max_len - len).saturating_mul(element_slots));
+
+    Ok(elements)
+}
+
+/// Decodes a Vyper `Bytes` or `String` of at most `max_len` bytes: the first slot holds the length
+/// and the data follows in place, left aligned.
+///
+/// Vyper copies whole words to storage, so the bytes after the end of the data are not checked.
+pub(crate) fn decode_vyper_bytes<Reader>(
+    storage_reader: &mut Reader,
+    max_len: usize,
+) -> Result<bytes::Bytes, SolLayoutError>
+where
+    Reader: StorageReader,
+{
+    let len = decode_vyper_length(storage_reader, max_len)?;
+
+    let mut buf = BytesMut::with_capacity(len);
+    for start in (0..len).step_by(32) {
+        let StorageReaderNext {
+            word: chunk,
+            children,
+            ..
+        } = storage_reader.next_or_default::<B256>();
+
+        storage_reader.reject_children(children)?;
+        buf.put_slice(&chunk[..(len - start).min(32)]);
+    }
+
+    storage_reader.skip_slots(max_len.div_ceil(32) - len.div_ceil(32));
+
+    Ok(buf.freeze())
+}
+
+impl<const N: usize, T, SolT> SolStorageValue<data_types::VyDynArray<SolT, N>> for Vec<T>
+where
+    T: SolStorageValue<SolT>,
+    SolT: SolStorageType,
+{
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        decode_vyper_dynamic_array(
+            storage_reader,
+            N,
+            SolT::sol_storage_footprint(),
+            &SolStorageTypedSeed::<T, SolT>::new(),
+        )
+    }
+}
+
+impl<const N: usize> SolStorageValue<data_types::VyBytes<N>> for bytes::Bytes {
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        decode_vyper_bytes(storage_reader, N)
+    }
+}
+
+impl<const N: usize> SolStorageValue<data_types::VyBytes<N>> for Bytes {
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        decode_vyper_bytes(storage_reader, N).map(Into::into)
+    }
+}
+
+impl<const N: usize> SolStorageValue<data_types::VyBytes<N>> for Vec<u8> {
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        decode_vyper_bytes(storage_reader, N).map(Into::into)
+    }
+}
+
+impl<const N: usize> SolStorageValue<data_types::VyString<N>> for String {
+    fn decode_storage<Reader>(storage_reader: &mut Reader) -> Result<Self, SolLayoutError>
+    where
+        Reader: StorageReader,
+    {
+        let b = decode_vyper_bytes(storage_reader, N)?;
+        Ok(Self::from_utf8_lossy(&b).into_owned())
+    }
+}
+
+/// Encodes a Vyper `DynArray` following [`decode_vyper_dynamic_array`], leaving the unused
+/// capacity empty. Fails if there are more than `max_len` elements.
+fn encode_vyper_dynamic_array<T, SolT, Writer>(
+    elements: &[T],
+    max_len: usize,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    T: SolStorageEncode<SolT>,
+    SolT: SolStorageType,
+    Writer: StorageWriter,
+{
+    check_vyper_length(elements.len(), max_len)?;
+
+    storage_writer.skip_remaining();
+    storage_writer.write(B256::from(U256::from(elements.len())), Default::default());
+
+    for (index, element) in elements.iter().enumerate() {
+        element
+            .encode_storage(storage_writer)
+            .map_err(|err| err.at_index(index as u64))?;
+        storage_writer.skip_remaining();
+    }
+
+    let element_slots = SolT::sol_storage_footprint()
+        .slots()
+        .saturating_to::<usize>();
+    storage_writer.skip_slots((max_len - elements.len()).saturating_mul(element_slots));
+    Ok(())
+}
+
+/// Encodes a Vyper `Bytes` or `String` following [`decode_vyper_bytes`], leaving the unused
+/// capacity empty. Fails if the data is longer than `max_len` bytes.
+fn encode_vyper_bytes<Writer>(
+    data: &[u8],
+    max_len: usize,
+    storage_writer: &mut Writer,
+) -> Result<(), SolLayoutError>
+where
+    Writer: StorageWriter,
+{
+    check_vyper_length(data.len(), max_len)?;
+
+    storage_writer.skip_remaining();
+    storage_writer.write(B256::from(U256::from(data.len())), Default::default());
+
+    for chunk in data.chunks(32) {
+        storage_writer.write(B256::right_padding_from(chunk), Default::default());
+    }
+
+    storage_writer.skip_slots(max_len.div_ceil(32) - data.len().div_ceil(32));
+    Ok(())
+}
+
+fn check_vyper_length(len: usize, max_len: usize) -> Result<(), SolLayoutError> {
+    if len > max_len {
+        return Err(SolLayoutErrorKind::LengthOverflow {
+            length: U256::from(len),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+impl<const N: usize, T, SolT> SolStorageEncode<data_types::VyDynArray<SolT, N>> for Vec<T>
+where
+    T: SolStorageEncode<SolT>,
+    SolT: SolStorageType,
+{
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_vyper_dynamic_array::<T, SolT, _>(self, N, storage_writer)
+    }
+}
+
+impl<const N: usize> SolStorageEncode<data_types::VyBytes<N>> for bytes::Bytes {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_vyper_bytes(self, N, storage_writer)
+    }
+}
+
+impl<const N: usize> SolStorageEncode<data_types::VyBytes<N>> for Bytes {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_vyper_bytes(self, N, storage_writer)
+    }
+}
+
+impl<const N: usize> SolStorageEncode<data_types::VyBytes<N>> for Vec<u8> {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_vyper_bytes(self, N, storage_writer)
+    }
+}
+
+impl<const N: usize> SolStorageEncode<data_types::VyString<N>> for String {
+    fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+    where
+        Writer: StorageWriter,
+    {
+        encode_vyper_bytes(self.as_bytes(), N, storage_writer)
+    }
+}
+
+/// Key of a Vyper `HashMap` whose `Bytes` or `String` keys are hashed into the raw keys of the
+/// entries.
+///
+/// The original value is only known when the preimage of its hash was found while decoding.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum VyHashedKey<T> {
+    Known(T),
+    Hashed(B256),
+}
+
+impl<T> VyHashedKey<T> {
+    /// The original value, if known.
+    pub fn known(&self) -> Option<&T> {
+        match self {
+            Self::Known(value) => Some(value),
+            Self::Hashed(_) => None,
+        }
+    }
+}
+
+/// Hash of a raw key, which Vyper keys are.
+fn hashed_key(key: Bytes) -> Result<B256, Bytes> {
+    if key.len() != 32 {
+        return Err(key);
+    }
+    Ok(B256::from_slice(&key))
+}
+
+impl SolMappingKeyValue<data_types::VyHashedBytes> for VyHashedKey<Bytes> {
+    fn into_sol_mapping_key(self) -> Bytes {
+        match self {
+            Self::Known(bytes) => keccak256(bytes).into(),
+            Self::Hashed(hash) => hash.into(),
+        }
+    }
+
+    fn try_from_sol_mapping_key(key: Bytes) -> Result<Self, Bytes> {
+        hashed_key(key).map(Self::Hashed)
+    }
+
+    fn decode_sol_mapping_key<Reader>(key: Bytes, storage_reader: &Reader) -> Result<Self, Bytes>
+    where
+        Reader: StorageReader,
+    {
+        match storage_reader.key_preimage(&key) {
+            Some(bytes) => Ok(Self::Known(bytes)),
+            None => Self::try_from_sol_mapping_key(key),
+        }
+    }
+}
+
+impl SolMappingKeyValue<data_types::VyHashedString> for VyHashedKey<String> {
+    fn into_sol_mapping_key(self) -> Bytes {
+        match self {
+            Self::Known(string) => keccak256(string).into(),
+            Self::Hashed(hash) => hash.into(),
+        }
+    }
+
+    fn try_from_sol_mapping_key(key: Bytes) -> Result<Self, Bytes> {
+        hashed_key(key).map(Self::Hashed)
+    }
+
+    fn decode_sol_mapping_key<Reader>(key: Bytes, storage_reader: &Reader) -> Result<Self, Bytes>
+    where
+        Reader: StorageReader,
+    {
+        match storage_reader.key_preimage(&key) {
+            Some(bytes) => Ok(Self::Known(String::from_utf8_lossy(&bytes).into_owned())),
+            None => Self::try_from_sol_mapping_key(key),
+        }
+    }
+}
+
+/// Fails when the original value is unknown, since a hash cannot be reversed.
+impl SolMappingKeyValue<data_types::VyHashedBytes> for Bytes {
+    fn into_sol_mapping_key(self) -> Bytes {
+        keccak256(self).into()
+    }
+
+    fn try_from_sol_mapping_key(key: Bytes) -> Result<Self, Bytes> {
+        Err(key)
+    }
+
+    fn decode_sol_mapping_key<Reader>(key: Bytes, storage_reader: &Reader) -> Result<Self, Bytes>
+    where
+        Reader: StorageReader,
+    {
+        storage_reader.key_preimage(&key).ok_or(key)
+    }
+}
+
+/// Fails when the original value is unknown, since a hash cannot be reversed.
+impl SolMappingKeyValue<data_types::VyHashedString> for String {
+    fn into_sol_mapping_key(self) -> Bytes {
+        keccak256(self).into()
+    }
+
+    fn try_from_sol_mapping_key(key: Bytes) -> Result<Self, Bytes> {
+        Err(key)
+    }
+
+    fn decode_sol_mapping_key<Reader>(key: Bytes, storage_reader: &Reader) -> Result<Self, Bytes>
+    where
+        Reader: StorageReader,
+    {
+        match storage_reader.key_preimage(&key) {
+            Some(bytes) => Ok(Self::from_utf8_lossy(&bytes).into_owned()),
+            None => Err(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::b256;
+    use sdecode_core::{IntoStorageReader, StorageNode, StorageStructure, StorageStructureWriter};
+
+    use super::*;
+
+    #[test]
+    fn test_vyper_dynamic_array() {
+        let coins = vec![1u64, 2];
+
+        let mut writer = StorageStructureWriter::new();
+        <Vec<u64> as SolStorageEncode<data_types::VyDynArray<data_types::Uint<64>, 4>>>::encode_storage(
+            &coins,
+            &mut writer,
+        )
+        .unwrap();
+        <u8 as SolStorageEncode<data_types::Uint<8>>>::encode_storage(&7, &mut writer).unwrap();
+        let structure = writer.into_structure();
+        assert_eq!(structure.0.len(), 6);
+
+        // Stale elements of the unused capacity are ignored.
+        let mut structure = structure;
+        structure.0[3] = StorageNode::word(B256::with_last_byte(3));
+
+        let mut reader = structure.into_storage_reader();
+        let decoded = <Vec<u64> as SolStorageValue<
+            data_types::VyDynArray<data_types::Uint<64>, 4>,
+        >>::decode_storage(&mut reader)
+        .unwrap();
+        assert_eq!(decoded, coins);
+        assert_eq!(
+            <u8 as SolStorageValue<data_types::Uint<8>>>::decode_storage(&mut reader).unwrap(),
+            7
+        );
+    }
+
+    #[test]
+    fn test_vyper_string() {
+        let name = "Curve.fi Factory Crypto Pool: tricrypto".to_string();
+
+        let mut writer = StorageStructureWriter::new();
+        <String as SolStorageEncode<data_types::VyString<64>>>::encode_storage(&name, &mut writer)
+            .unwrap();
+        let structure = writer.into_structure();
+        assert_eq!(
+            structure,
+            StorageStructure(vec![
+                StorageNode::word(B256::with_last_byte(39)),
+                StorageNode::word(b256!(
+                    "0x43757276652e666920466163746f72792043727970746f20506f6f6c3a207472"
+                )),
+                StorageNode::word(b256!(
+                    "0x6963727970746f00000000000000000000000000000000000000000000000000"
+                )),
+            ])
+        );
+
+        let decoded = <String as SolStorageValue<data_types::VyString<64>>>::decode_storage(
+            &mut structure.into_storage_reader(),
+        )
+        .unwrap();
+        assert_eq!(decoded, name);
+    }
+
+    #[test]
+    fn test_vyper_length_overflow() {
+        let structure = StorageStructure(vec![StorageNode::word(B256::with_last_byte(5))]);
+        let err = <Vec<u64> as SolStorageValue<
+            data_types::VyDynArray<data_types::Uint<64>, 4>,
+        >>::decode_storage(&mut structure.into_storage_reader())
+        .unwrap_err();
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::LengthOverflow {
+                length: U256::from(5)
+            }
+        );
+
+        let mut writer = StorageStructureWriter::new();
+        let err = <String as SolStorageEncode<data_types::VyString<4>>>::encode_storage(
+            &"tricrypto".to_string(),
+            &mut writer,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.kind,
+            SolLayoutErrorKind::LengthOverflow {
+                length: U256::from(9)
+            }
+        );
+    }
+}
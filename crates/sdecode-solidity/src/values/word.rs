@@ -1,10 +1,10 @@
-use ::alloy_primitives::{Address, FixedBytes, Function, aliases::*, keccak256};
+use ::alloy_primitives::{Address, Bytes, FixedBytes, Function, aliases::*, keccak256};
 use alloy_sol_types::SolValue;
-use sdecode_core::{StorageReader, SubB256};
+use sdecode_core::{StorageReader, StorageWriter, SubB256};
 
-use crate::{data_types, utils::b256_to_u256};
+use crate::{SolStorageType, data_types, utils::b256_to_u256};
 
-use super::{SolLayoutError, SolStorageValue};
+use super::{SolLayoutError, SolLayoutErrorKind, SolStorageEncode, SolStorageValue};
 
 /// A Solidity type that fits in a 32-bytes EVM word.
 pub trait SolWordType: Sized {
@@ -288,11 +288,26 @@ macro_rules! impl_sol_storage_type_value_for_word {
                         return Err(SolLayoutError::remaining_bytes(next.remaining))
                     }
 
-                    if next.children.is_empty() {
-                        SolWordType::try_from_packed_word(next.word).ok_or(SolLayoutError::Err)
-                    } else {
-                        Err(SolLayoutError::Err)
-                    }
+                    storage_reader.reject_children(next.children)?;
+
+                    let word = next.word;
+                    SolWordType::try_from_packed_word(word).ok_or_else(|| {
+                        SolLayoutErrorKind::InvalidWord {
+                            sol_type: <$sol_t as SolStorageType>::SOL_STORAGE_NAME,
+                            word: Bytes::copy_from_slice(SubB256::as_slice(&word)),
+                        }
+                        .into()
+                    })
+                }
+            }
+
+            impl SolStorageEncode<$sol_t> for $t {
+                fn encode_storage<Writer>(&self, storage_writer: &mut Writer) -> Result<(), SolLayoutError>
+                where
+                    Writer: StorageWriter,
+                {
+                    storage_writer.write(SolWordType::into_packed_word(*self), Default::default());
+                    Ok(())
                 }
             }
         )*
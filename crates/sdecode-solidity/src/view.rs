@@ -0,0 +1,459 @@
+use alloy_primitives::{B256, U256, keccak256};
+use sdecode_core::{IntoStorageReader, SlotBackend, StorageError, StorageNode, StorageStructure};
+
+use crate::{
+    SlotAllocator, SolLayoutError, SolLayoutErrorKind, SolStorageFootprint, SolStoragePath,
+    SolStorageType, SolStorageValue, utils::b256_to_u256,
+};
+
+impl<SolT> SolStoragePath<SolT>
+where
+    SolT: SolStorageType,
+{
+    /// Reads and decodes the value located at this path, only reading from `backend` the slots it
+    /// occupies. Mappings nested in the value are decoded empty.
+    ///
+    /// Lengths read from storage are checked before reading the data they cover: at most `max_len`
+    /// for Vyper values, and 65536 slots of data otherwise. Longer values are reported as
+    /// [`LengthOverflow`](SolLayoutErrorKind::LengthOverflow).
+    pub fn load<T, B>(&self, backend: &mut B) -> Result<T, StorageError<B::Error, SolLayoutError>>
+    where
+        T: SolStorageValue<SolT>,
+        B: SlotBackend,
+    {
+        let mut structure = SolT::sol_storage_load(backend, self.slot())?;
+
+        // The decoders expect the value to start at the beginning of the word.
+        if let (SolStorageFootprint::Packed(_), Some(node)) =
+            (SolT::sol_storage_footprint(), structure.0.first_mut())
+        {
+            let word = b256_to_u256(node.value()) >> (self.offset() * 8);
+            node.value = Some(word.into());
+        }
+
+        T::decode_storage(&mut structure.into_storage_reader()).map_err(StorageError::Layout)
+    }
+}
+
+/// Slots read from a backend, or the reason why they could not be.
+pub(crate) type SolLoadResult<E> = Result<StorageStructure, StorageError<E, SolLayoutError>>;
+
+/// Most slots read for the data of a dynamic array, `bytes` or `string` whose length comes from
+/// storage, so that a corrupted length cannot drive unbounded reads.
+const MAX_LOADED_SLOTS: usize = 1 << 16;
+
+/// Number of slots covered by a footprint.
+fn footprint_slots(footprint: SolStorageFootprint) -> usize {
+    match footprint {
+        SolStorageFootprint::Packed(_) => 1,
+        SolStorageFootprint::Slots(slots) => slots.saturating_to(),
+    }
+}
+
+/// Number of slots covered by `len` consecutive elements of type `T`.
+fn elements_slots<T>(len: usize) -> Option<usize>
+where
+    T: SolStorageType,
+{
+    match T::sol_storage_footprint() {
+        SolStorageFootprint::Packed(size) => Some(len.div_ceil(32 / size)),
+        SolStorageFootprint::Slots(slots) => usize::try_from(slots).ok()?.checked_mul(len),
+    }
+}
+
+/// Length read from storage, whose data covers `data_slots(len)` slots, at most `max_slots`.
+fn checked_len(
+    length: U256,
+    max_slots: usize,
+    data_slots: impl FnOnce(usize) -> Option<usize>,
+) -> Result<usize, SolLayoutError> {
+    usize::try_from(length)
+        .ok()
+        .filter(|len| data_slots(*len).is_some_and(|slots| slots <= max_slots))
+        .ok_or_else(|| SolLayoutErrorKind::LengthOverflow { length }.into())
+}
+
+fn length_overflow<E>(len: usize) -> StorageError<E, SolLayoutError> {
+    StorageError::Layout(
+        SolLayoutErrorKind::LengthOverflow {
+            length: U256::from(len),
+        }
+        .into(),
+    )
+}
+
+fn slot_add(slot: B256, delta: usize) -> B256 {
+    b256_to_u256(slot).wrapping_add(U256::from(delta)).into()
+}
+
+fn sload<B>(backend: &mut B, slot: B256) -> Result<B256, StorageError<B::Error, SolLayoutError>>
+where
+    B: SlotBackend,
+{
+    backend.sload(slot).map_err(StorageError::Provider)
+}
+
+/// Reads `len` consecutive words, starting at `slot`.
+pub(crate) fn load_words<B>(backend: &mut B, slot: B256, len: usize) -> SolLoadResult<B::Error>
+where
+    B: SlotBackend,
+{
+    (0..len)
+        .map(|i| sload(backend, slot_add(slot, i)).map(StorageNode::word))
+        .collect::<Result<Vec<_>, _>>()
+        .map(StorageStructure)
+}
+
+/// Reads a value whose footprint covers whole words without any child, such as a value type.
+pub(crate) fn load_footprint<B>(
+    backend: &mut B,
+    slot: B256,
+    footprint: SolStorageFootprint,
+) -> SolLoadResult<B::Error>
+where
+    B: SlotBackend,
+{
+    load_words(backend, slot, footprint_slots(footprint))
+}
+
+/// Reads `len` consecutive array elements of type `T`, starting at `slot`.
+pub(crate) fn load_elements<T, B>(
+    backend: &mut B,
+    slot: B256,
+    len: usize,
+) -> SolLoadResult<B::Error>
+where
+    T: SolStorageType,
+    B: SlotBackend,
+{
+    let Some(data_slots) = elements_slots::<T>(len) else {
+        return Err(length_overflow(len));
+    };
+
+    match T::sol_storage_footprint() {
+        SolStorageFootprint::Packed(_) => load_words(backend, slot, data_slots),
+        SolStorageFootprint::Slots(_) => load_spaced_elements::<T, _>(backend, slot, len),
+    }
+}
+
+/// Reads a dynamic array, whose elements are stored at `keccak256(slot)`.
+pub(crate) fn load_dynamic_array<T, B>(backend: &mut B, slot: B256) -> SolLoadResult<B::Error>
+where
+    T: SolStorageType,
+    B: SlotBackend,
+{
+    let word = sload(backend, slot)?;
+    let len = checked_len(b256_to_u256(word), MAX_LOADED_SLOTS, elements_slots::<T>)
+        .map_err(StorageError::Layout)?;
+
+    let mut node = StorageNode::word(word);
+    let data = load_elements::<T, _>(backend, keccak256(slot), len)?;
+    node.children.insert(Default::default(), data);
+
+    Ok(StorageStructure::single_node(node))
+}
+
+/// Reads `bytes` or `string`, stored at `keccak256(slot)` when longer than 31 bytes.
+pub(crate) fn load_bytes<B>(backend: &mut B, slot: B256) -> SolLoadResult<B::Error>
+where
+    B: SlotBackend,
+{
+    let word = sload(backend, slot)?;
+    let mut node = StorageNode::word(word);
+
+    let is_long = word[31] % 2 == 1;
+    if is_long {
+        let len = checked_len(b256_to_u256(word) >> 1, MAX_LOADED_SLOTS, |len| {
+            Some(len.div_ceil(32))
+        })
+        .map_err(StorageError::Layout)?;
+        let data = load_words(backend, keccak256(slot), len.div_ceil(32))?;
+        node.children.insert(Default::default(), data);
+    }
+
+    Ok(StorageStructure::single_node(node))
+}
+
+/// Reads a Vyper `DynArray` of at most `max_len` elements, which follow the length in place. The
+/// unused capacity is not read.
+pub(crate) fn load_vyper_dynamic_array<T, B>(
+    backend: &mut B,
+    slot: B256,
+    max_len: usize,
+) -> SolLoadResult<B::Error>
+where
+    T: SolStorageType,
+    B: SlotBackend,
+{
+    let word = sload(backend, slot)?;
+    let len = checked_len(b256_to_u256(word), max_len, Some).map_err(StorageError::Layout)?;
+
+    let mut nodes = vec![StorageNode::word(word)];
+    let elements = load_spaced_elements::<T, _>(backend, slot_add(slot, 1), len)?;
+    nodes.extend(elements.0);
+
+    Ok(StorageStructure(nodes))
+}
+
+/// Reads `len` consecutive elements, each starting on a new slot.
+fn load_spaced_elements<T, B>(backend: &mut B, slot: B256, len: usize) -> SolLoadResult<B::Error>
+where
+    T: SolStorageType,
+    B: SlotBackend,
+{
+    let slots = footprint_slots(T::sol_storage_footprint());
+    if slots.checked_mul(len).is_none() {
+        return Err(length_overflow(len));
+    }
+
+    let mut nodes = Vec::new();
+    for i in 0..len {
+        let element = T::sol_storage_load(backend, slot_add(slot, i * slots))?;
+        // Vyper arrays and bytes only read the slots they use.
+        nodes.resize(i * slots, StorageNode::empty());
+        nodes.extend(element.0);
+    }
+    Ok(StorageStructure(nodes))
+}
+
+/// Reads a Vyper `Bytes` or `String` of at most `max_len` bytes, whose data follows the length in
+/// place.
+pub(crate) fn load_vyper_bytes<B>(
+    backend: &mut B,
+    slot: B256,
+    max_len: usize,
+) -> SolLoadResult<B::Error>
+where
+    B: SlotBackend,
+{
+    let word = sload(backend, slot)?;
+    let len = checked_len(b256_to_u256(word), max_len, Some).map_err(StorageError::Layout)?;
+
+    let mut nodes = vec![StorageNode::word(word)];
+    let data = load_words(backend, slot_add(slot, 1), len.div_ceil(32))?;
+    nodes.extend(data.0);
+
+    Ok(StorageStructure(nodes))
+}
+
+/// Reads the fields of a structure one after the other, following the packing rules. Used by the
+/// code generated by `sol_storage!`.
+#[doc(hidden)]
+pub struct SolStructureLoader<'a, B> {
+    backend: &'a mut B,
+    slot: B256,
+    allocator: SlotAllocator,
+    nodes: Vec<Option<StorageNode>>,
+}
+
+impl<'a, B> SolStructureLoader<'a, B>
+where
+    B: SlotBackend,
+{
+    pub fn new(backend: &'a mut B, slot: B256) -> Self {
+        Self {
+            backend,
+            slot,
+            allocator: SlotAllocator::default(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Reads the next field, of type `T`. A slot shared by packed fields is only read once.
+    pub fn field<T>(&mut self) -> Result<(), StorageError<B::Error, SolLayoutError>>
+    where
+        T: SolStorageType,
+    {
+        let (field_slot, _) = self.allocator.allocate(T::sol_storage_footprint());
+        let field_slot = field_slot.saturating_to::<usize>();
+
+        if field_slot < self.nodes.len() {
+            // Only packed fields can share a slot with a previous field.
+            return Ok(());
+        }
+
+        let structure = T::sol_storage_load(self.backend, slot_add(self.slot, field_slot))?;
+        self.nodes.resize(field_slot, None);
+        self.nodes.extend(structure.0.into_iter().map(Some));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> StorageStructure {
+        self.allocator.align();
+        self.nodes
+            .resize(self.allocator.slot().saturating_to::<usize>(), None);
+        self.nodes
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, convert::Infallible};
+
+    use alloy_primitives::{Address, address};
+    use sdecode_core::{StorageEncode, StorageEntries};
+
+    use super::*;
+    use crate::SolStorageRoot;
+
+    use self::bank::Account;
+
+    crate::sol_storage! {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        contract Bank {
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            struct Account {
+                uint128 balance;
+                uint64 nonce;
+                string name;
+                mapping(uint256 => uint256) notes;
+            }
+
+            address owner;
+            uint8 decimals;
+            mapping(address => uint256) balanceOf;
+            mapping(address => mapping(address => uint256)) allowance;
+            mapping(address => Account) accounts;
+            uint32[] history;
+        }
+    }
+
+    crate::sol_storage! {
+        contract Queues {
+            #[sdecode(type = std::collections::BTreeMap<
+                alloy_primitives::Address,
+                crate::helpers::SolDynamicArrayHelper<std::collections::VecDeque<u32>, u32>,
+            >)]
+            mapping(address => uint32[]) queues;
+        }
+    }
+
+    /// Counts the slots read.
+    struct CountingBackend {
+        entries: StorageEntries,
+        reads: usize,
+    }
+
+    impl SlotBackend for CountingBackend {
+        type Error = Infallible;
+
+        fn sload(&mut self, slot: B256) -> Result<B256, Self::Error> {
+            self.reads += 1;
+            self.entries.sload(slot)
+        }
+    }
+
+    #[test]
+    fn test_view() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let bob = address!("0x0000000000000000000000000000000000000b0b");
+
+        let account = Account {
+            balance: 1000,
+            nonce: 7,
+            name: "a name long enough to be stored out of its slot".to_string(),
+            notes: BTreeMap::new(),
+        };
+
+        let bank = BankStorage {
+            owner: Address::repeat_byte(0x42),
+            decimals: 18,
+            balanceOf: BTreeMap::from([(alice, U256::from(5)), (bob, U256::from(6))]),
+            allowance: BTreeMap::from([(alice, BTreeMap::from([(bob, U256::from(100))]))]),
+            accounts: BTreeMap::from([(bob, account.clone())]),
+            history: vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+        };
+
+        let (entries, _) = bank.sencode().unwrap();
+        let mut backend = CountingBackend { entries, reads: 0 };
+
+        assert_eq!(BankView::owner(&mut backend).unwrap(), bank.owner);
+        assert_eq!(BankView::decimals(&mut backend).unwrap(), 18);
+        assert_eq!(backend.reads, 2);
+
+        assert_eq!(
+            BankView::balance_of(&mut backend, alice).unwrap(),
+            U256::from(5)
+        );
+        assert_eq!(
+            BankView::balance_of(&mut backend, Address::ZERO).unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            BankView::allowance(&mut backend, alice, bob).unwrap(),
+            U256::from(100)
+        );
+        assert_eq!(backend.reads, 5);
+
+        // Two slots for the packed fields and `name`, plus two for the data of `name`.
+        assert_eq!(BankView::accounts(&mut backend, bob).unwrap(), account);
+        assert_eq!(backend.reads, 9);
+
+        // The length, then two slots of elements.
+        assert_eq!(BankView::history(&mut backend).unwrap(), bank.history);
+        assert_eq!(backend.reads, 12);
+
+        let paths = BankStorage::storage_paths();
+        let history = paths.history.index(8).load::<u32, _>(&mut backend);
+        assert_eq!(history.unwrap(), 9);
+        assert_eq!(
+            paths
+                .accounts
+                .key(bob)
+                .unwrap()
+                .fields()
+                .nonce
+                .load::<u64, _>(&mut backend)
+                .unwrap(),
+            7
+        );
+    }
+
+    #[test]
+    fn test_view_length_overflow() {
+        let bank = BankStorage {
+            owner: Address::ZERO,
+            decimals: 0,
+            balanceOf: BTreeMap::new(),
+            allowance: BTreeMap::new(),
+            accounts: BTreeMap::new(),
+            history: vec![1],
+        };
+        let (mut entries, _) = bank.sencode().unwrap();
+        let history = BankStorage::storage_paths().history.slot();
+
+        for length in [U256::from(1_u64 << 40), U256::MAX] {
+            entries.insert(history, B256::from(length));
+            let mut backend = CountingBackend {
+                entries: entries.clone(),
+                reads: 0,
+            };
+
+            let err = BankView::history(&mut backend).unwrap_err();
+            assert_eq!(
+                err.into_layout().unwrap().kind,
+                SolLayoutErrorKind::LengthOverflow { length }
+            );
+            assert_eq!(backend.reads, 1);
+        }
+    }
+
+    #[test]
+    fn test_view_type_override() {
+        let alice = address!("0x00000000000000000000000000000000000a11ce");
+        let queue = std::collections::VecDeque::from([1, 2]);
+
+        let queues = QueuesStorage {
+            queues: BTreeMap::from([(
+                alice,
+                crate::helpers::SolDynamicArrayHelper::new(queue.clone()),
+            )]),
+        };
+        let (mut entries, _) = queues.sencode().unwrap();
+
+        assert_eq!(QueuesView::queues(&mut entries, alice).unwrap().0, queue);
+    }
+}
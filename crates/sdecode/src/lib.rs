@@ -5,6 +5,6 @@ pub use sdecode_core as core;
 pub use sdecode_preimages as preimages;
 pub use sdecode_solidity as solidity;
 
-pub use core::{StorageDecode, StorageEntries, StorageError};
+pub use core::{SlotBackend, StorageDecode, StorageEncode, StorageEntries, StorageError};
 
 pub use preimages::{Image, Preimage, PreimageEntry, PreimagesProvider, PreimagesProviderMut};